@@ -0,0 +1,120 @@
+//! Compares the binary `Input` encoding with the JSON one it replaced, on its own and
+//! through the store behind `SecureMemoryProvider::set`/`get`. The provider only writes
+//! the binary encoding, so both are stored the way it does: encoded, then sealed with
+//! `SecureKeyValueStore::set`, and opened and decoded again on `get`. Throughput is
+//! against the bytes the inputs hold, the same for both encodings. Run with
+//! `cargo bench -p mirage`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use std::sync::Arc;
+
+use mirage::actors::encryption::decryptor::Decryptor;
+use mirage::actors::encryption::encryptor::Encryptor;
+use mirage::actors::encryption::suite::CipherSuite;
+use mirage::actors::memory::generic::secure_key_value_store::SecureKeyValueStore;
+use mirage::actors::memory::Input;
+use mirage::utils::key_generator::generate_key;
+
+/// A 32-byte key, a small record and a larger blob.
+fn payloads() -> Vec<(&'static str, Vec<Input>)> {
+    vec![
+        ("key", vec![Input::Buffer(vec![0xa5; 32])]),
+        (
+            "record",
+            vec![
+                Input::Buffer(vec![0x5a; 16]),
+                Input::Bit(1),
+                Input::Buffer(vec![0x3c; 64]),
+            ],
+        ),
+        ("blob", vec![Input::Buffer(vec![0xff; 4096])]),
+    ]
+}
+
+/// Encodes inputs the way they are handed to the store.
+type Encode = fn(&[Input]) -> Vec<u8>;
+
+/// Bytes held by `inputs`, independent of how they are encoded.
+fn input_size(inputs: &[Input]) -> u64 {
+    inputs
+        .iter()
+        .map(|input| match input {
+            Input::Buffer(data) => data.len() as u64,
+            Input::Bit(_) => 1,
+        })
+        .sum()
+}
+
+fn encoding(c: &mut Criterion) {
+    let mut group = c.benchmark_group("input_encoding");
+    for (name, inputs) in payloads() {
+        let binary = Input::serialize(&inputs).unwrap();
+        let json = serde_json::to_vec(&inputs).unwrap();
+        group.throughput(Throughput::Bytes(input_size(&inputs)));
+        group.bench_with_input(
+            BenchmarkId::new("binary_encode", name),
+            &inputs,
+            |b, inputs| b.iter(|| Input::serialize(black_box(inputs)).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("binary_decode", name),
+            &binary,
+            |b, data| b.iter(|| Input::deserialize(black_box(data)).unwrap()),
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("json_encode", name),
+            &inputs,
+            |b, inputs| b.iter(|| serde_json::to_vec(black_box(inputs)).unwrap()),
+        );
+        group.bench_with_input(BenchmarkId::new("json_decode", name), &json, |b, data| {
+            b.iter(|| Input::deserialize(black_box(data)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn store(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let _guard = runtime.enter();
+    let runtime_handle = Arc::new(runtime.handle().clone());
+    let suite = CipherSuite::Aes256Gcm;
+    let key = generate_key(suite);
+    let store = SecureKeyValueStore::new(
+        Arc::new(Encryptor::with_suite(
+            suite,
+            key.clone(),
+            runtime_handle.clone(),
+        )),
+        Arc::new(Decryptor::with_suite(suite, key, runtime_handle)),
+    );
+    let mut store = store.lock().unwrap();
+
+    let mut group = c.benchmark_group("secure_memory_provider");
+    for (name, inputs) in payloads() {
+        group.throughput(Throughput::Bytes(input_size(&inputs)));
+        let formats: [(&str, Encode); 2] = [
+            ("binary", |inputs| Input::serialize(inputs).unwrap()),
+            ("json", |inputs| serde_json::to_vec(inputs).unwrap()),
+        ];
+        for (format, encode) in formats {
+            let id = format!("{}/{}", format, name);
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}_set", format), name),
+                &inputs,
+                |b, inputs| b.iter(|| store.set(id.clone(), encode(black_box(inputs))).unwrap()),
+            );
+            group.bench_function(BenchmarkId::new(format!("{}_get", format), name), |b| {
+                b.iter(|| {
+                    let plaintext = store.get(black_box(&id)).unwrap().unwrap();
+                    Input::deserialize(&plaintext).unwrap()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encoding, store);
+criterion_main!(benches);
@@ -0,0 +1,45 @@
+use crate::actors::encryption::AesError;
+
+#[derive(Debug)]
+pub enum AgeError {
+    /// Not an age file, or a header that violates the format.
+    MalformedHeader(String),
+    UnsupportedVersion(String),
+    /// A stanza of a known type whose arguments or body are invalid.
+    InvalidStanza(String),
+    WorkFactorTooHigh(u8),
+    HeaderMacMismatch,
+    NoMatchingIdentity,
+    /// The payload failed authentication or is truncated or malformed.
+    PayloadFailure(String),
+    InvalidRecipient(String),
+    InvalidIdentity(String),
+}
+
+impl std::fmt::Display for AgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AgeError::MalformedHeader(reason) => write!(f, "malformed age header: {}", reason),
+            AgeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported age version '{}'", version)
+            }
+            AgeError::InvalidStanza(reason) => write!(f, "invalid age stanza: {}", reason),
+            AgeError::WorkFactorTooHigh(log_n) => {
+                write!(f, "scrypt work factor {} exceeds the limit", log_n)
+            }
+            AgeError::HeaderMacMismatch => write!(f, "age header MAC does not match"),
+            AgeError::NoMatchingIdentity => {
+                write!(f, "no identity matches any of the recipients")
+            }
+            AgeError::PayloadFailure(reason) => write!(f, "age payload error: {}", reason),
+            AgeError::InvalidRecipient(reason) => write!(f, "invalid age recipient: {}", reason),
+            AgeError::InvalidIdentity(reason) => write!(f, "invalid age identity: {}", reason),
+        }
+    }
+}
+
+impl From<AgeError> for AesError {
+    fn from(err: AgeError) -> Self {
+        AesError::AgeError(err)
+    }
+}
@@ -0,0 +1,190 @@
+//! Textual age header: version line, recipient stanzas and the header MAC line.
+
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+use super::error::AgeError;
+use super::MAC_LENGTH;
+use crate::actors::encryption::AesError;
+
+pub const INTRO: &str = "age-encryption.org/v1";
+const VERSION_PREFIX: &str = "age-encryption.org/";
+const STANZA_PREFIX: &str = "-> ";
+const MAC_PREFIX: &str = "---";
+
+/// Width of a full base64 body line; a shorter line ends the stanza.
+const COLUMNS: usize = 64;
+/// Headers larger than this are rejected instead of being buffered.
+const MAX_HEADER_LENGTH: u64 = 1024 * 1024;
+
+/// Canonical, unpadded standard base64 as required by the age format.
+pub fn encode_base64(data: &[u8]) -> String {
+    STANDARD_NO_PAD.encode(data)
+}
+
+/// Rejects padding and non-zero trailing bits, so every value has exactly one encoding.
+pub fn decode_base64(text: &str) -> Result<Vec<u8>, AgeError> {
+    STANDARD_NO_PAD
+        .decode(text)
+        .map_err(|e| AgeError::MalformedHeader(format!("invalid base64: {}", e)))
+}
+
+/// One recipient stanza: `-> tag args...` followed by a wrapped file key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stanza {
+    pub tag: String,
+    pub args: Vec<String>,
+    pub body: Vec<u8>,
+}
+
+impl Stanza {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(STANZA_PREFIX.as_bytes());
+        out.extend_from_slice(self.tag.as_bytes());
+        for arg in &self.args {
+            out.push(b' ');
+            out.extend_from_slice(arg.as_bytes());
+        }
+        out.push(b'\n');
+
+        // The body always ends with a short line, which is empty for a multiple of 48 bytes.
+        let body = encode_base64(&self.body);
+        let mut lines = body.as_bytes().chunks(COLUMNS);
+        loop {
+            let line = lines.next().unwrap_or_default();
+            out.extend_from_slice(line);
+            out.push(b'\n');
+            if line.len() < COLUMNS {
+                break;
+            }
+        }
+    }
+}
+
+/// A parsed header. `mac_input` holds the bytes covered by the header MAC.
+pub struct Header {
+    pub stanzas: Vec<Stanza>,
+    pub mac: [u8; MAC_LENGTH],
+    pub mac_input: Vec<u8>,
+}
+
+/// Encodes the header up to and including `---`, which is the input of the header MAC.
+pub fn encode_mac_input(stanzas: &[Stanza]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(INTRO.as_bytes());
+    out.push(b'\n');
+    for stanza in stanzas {
+        stanza.write(&mut out);
+    }
+    out.extend_from_slice(MAC_PREFIX.as_bytes());
+    out
+}
+
+/// Line reader that enforces LF line endings and the header size limit.
+struct Lines<'a, R> {
+    reader: &'a mut R,
+    consumed: u64,
+    raw: Vec<u8>,
+}
+
+impl<'a, R: AsyncBufRead + Unpin> Lines<'a, R> {
+    async fn next(&mut self) -> Result<String, AesError> {
+        let mut line = Vec::new();
+        let remaining = MAX_HEADER_LENGTH - self.consumed;
+        (&mut *self.reader)
+            .take(remaining)
+            .read_until(b'\n', &mut line)
+            .await?;
+        self.consumed += line.len() as u64;
+
+        if line.pop() != Some(b'\n') {
+            return Err(AgeError::MalformedHeader("header is truncated".to_string()).into());
+        }
+        self.raw.extend_from_slice(&line);
+        self.raw.push(b'\n');
+        String::from_utf8(line)
+            .map_err(|_| AgeError::MalformedHeader("header is not ASCII".to_string()).into())
+    }
+}
+
+impl Header {
+    /// Reads the header up to and including the MAC line, leaving `reader` at the
+    /// payload nonce.
+    pub async fn read<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Self, AesError> {
+        let mut lines = Lines {
+            reader,
+            consumed: 0,
+            raw: Vec::new(),
+        };
+
+        let intro = lines.next().await?;
+        if intro != INTRO {
+            return Err(match intro.strip_prefix(VERSION_PREFIX) {
+                Some(version) => AgeError::UnsupportedVersion(version.to_string()),
+                None => AgeError::MalformedHeader("not an age file".to_string()),
+            }
+            .into());
+        }
+
+        let mut stanzas = Vec::new();
+        let mut line = lines.next().await?;
+        while let Some(arguments) = line.strip_prefix(STANZA_PREFIX) {
+            let mut args = arguments.split(' ').map(str::to_string);
+            let tag = args.next().unwrap_or_default();
+            let args: Vec<String> = args.collect();
+            if std::iter::once(&tag)
+                .chain(&args)
+                .any(|arg| !is_valid_argument(arg))
+            {
+                return Err(
+                    AgeError::MalformedHeader("invalid stanza argument".to_string()).into(),
+                );
+            }
+
+            let mut body = String::new();
+            loop {
+                let body_line = lines.next().await?;
+                if body_line.len() > COLUMNS {
+                    return Err(
+                        AgeError::MalformedHeader("stanza line is too long".to_string()).into(),
+                    );
+                }
+                body.push_str(&body_line);
+                if body_line.len() < COLUMNS {
+                    break;
+                }
+            }
+
+            stanzas.push(Stanza {
+                tag,
+                args,
+                body: decode_base64(&body)?,
+            });
+            line = lines.next().await?;
+        }
+
+        let encoded_mac = line
+            .strip_prefix(MAC_PREFIX)
+            .and_then(|rest| rest.strip_prefix(' '))
+            .ok_or_else(|| AgeError::MalformedHeader("missing header MAC".to_string()))?;
+        let mac = decode_base64(encoded_mac)?.try_into().map_err(|_| {
+            AgeError::MalformedHeader("header MAC has the wrong length".to_string())
+        })?;
+
+        // The MAC covers everything up to and including `---`, without the space.
+        let mut mac_input = lines.raw;
+        mac_input.truncate(mac_input.len() - line.len() - 1 + MAC_PREFIX.len());
+
+        Ok(Header {
+            stanzas,
+            mac,
+            mac_input,
+        })
+    }
+}
+
+/// Stanza arguments are non-empty runs of visible ASCII characters.
+fn is_valid_argument(arg: &str) -> bool {
+    !arg.is_empty() && arg.bytes().all(|byte| (33..=126).contains(&byte))
+}
@@ -0,0 +1,228 @@
+//! The age v1 file format (<https://age-encryption.org/v1>), so files are interchangeable
+//! with the `age` and `rage` command line tools.
+//!
+//! A random 16-byte file key is wrapped once per recipient in a textual header stanza
+//! and authenticated by an HMAC over the header. The payload key is derived from the
+//! file key and a random nonce with HKDF-SHA256 and seals the payload with
+//! ChaCha20-Poly1305 STREAM (see `stream`). X25519 and scrypt recipients are supported;
+//! unknown stanzas are ignored when decrypting. ASCII armor is not supported.
+
+use std::path::Path;
+
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha256;
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+use self::error::AgeError;
+use self::format::{Header, Stanza};
+use self::scrypt::{ScryptIdentity, ScryptRecipient};
+use self::x25519::{X25519Identity, X25519Recipient};
+
+use super::{AesError, TAG_LENGTH};
+use crate::utils::file_system::AtomicFile;
+
+pub mod error;
+pub mod format;
+pub mod scrypt;
+pub mod stream;
+pub mod x25519;
+
+pub const FILE_KEY_LENGTH: usize = 16;
+pub const WRAPPED_FILE_KEY_LENGTH: usize = FILE_KEY_LENGTH + TAG_LENGTH;
+pub const MAC_LENGTH: usize = 32;
+const PAYLOAD_NONCE_LENGTH: usize = 16;
+
+const HEADER_INFO: &[u8] = b"header";
+const PAYLOAD_INFO: &[u8] = b"payload";
+
+pub type FileKey = [u8; FILE_KEY_LENGTH];
+
+/// Someone a file is encrypted to.
+pub enum Recipient {
+    X25519(X25519Recipient),
+    /// Must be the only recipient of a file.
+    Scrypt(ScryptRecipient),
+}
+
+impl Recipient {
+    /// Parses an `age1...` recipient string.
+    pub fn parse(encoded: &str) -> Result<Self, AesError> {
+        X25519Recipient::parse(encoded).map(Recipient::X25519)
+    }
+
+    fn wrap(&self, file_key: &FileKey) -> Result<Stanza, AesError> {
+        match self {
+            Recipient::X25519(recipient) => recipient.wrap(file_key),
+            Recipient::Scrypt(recipient) => recipient.wrap(file_key),
+        }
+    }
+}
+
+/// Something that can unwrap a file key from a header stanza.
+pub enum Identity {
+    X25519(X25519Identity),
+    Scrypt(ScryptIdentity),
+}
+
+impl Identity {
+    /// Parses an `AGE-SECRET-KEY-1...` identity string.
+    pub fn parse(encoded: &str) -> Result<Self, AesError> {
+        X25519Identity::parse(encoded).map(Identity::X25519)
+    }
+
+    fn unwrap(&self, stanza: &Stanza) -> Result<Option<FileKey>, AesError> {
+        match self {
+            Identity::X25519(identity) => identity.unwrap(stanza),
+            Identity::Scrypt(identity) => identity.unwrap(stanza),
+        }
+    }
+}
+
+/// HKDF-SHA256 with a 32-byte output, as used throughout the format.
+pub(crate) fn hkdf(salt: &[u8], ikm: &[u8], info: &[u8]) -> [u8; 32] {
+    let mut okm = [0u8; 32];
+    hkdf::Hkdf::<Sha256>::new(Some(salt), ikm)
+        .expand(info, &mut okm)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    okm
+}
+
+fn header_mac(file_key: &FileKey, mac_input: &[u8]) -> Hmac<Sha256> {
+    let mac_key = hkdf(&[], file_key, HEADER_INFO);
+    let mut mac = Hmac::<Sha256>::new_from_slice(&mac_key).expect("HMAC accepts any key length");
+    mac.update(mac_input);
+    mac
+}
+
+/// Encrypts everything `reader` yields to `recipients` as an age file written to `writer`.
+pub async fn encrypt_stream<R, W>(
+    recipients: &[Recipient],
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), AesError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    if recipients.is_empty() {
+        return Err(AgeError::InvalidRecipient("no recipients given".to_string()).into());
+    }
+    let has_scrypt = recipients
+        .iter()
+        .any(|recipient| matches!(recipient, Recipient::Scrypt(_)));
+    if has_scrypt && recipients.len() > 1 {
+        return Err(AgeError::InvalidRecipient(
+            "a passphrase must be the only recipient".to_string(),
+        )
+        .into());
+    }
+
+    let mut file_key = [0u8; FILE_KEY_LENGTH];
+    OsRng.fill_bytes(&mut file_key);
+    let stanzas = recipients
+        .iter()
+        .map(|recipient| recipient.wrap(&file_key))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut header = format::encode_mac_input(&stanzas);
+    let mac = header_mac(&file_key, &header).finalize().into_bytes();
+    header.push(b' ');
+    header.extend_from_slice(format::encode_base64(&mac).as_bytes());
+    header.push(b'\n');
+    writer.write_all(&header).await?;
+
+    let mut nonce = [0u8; PAYLOAD_NONCE_LENGTH];
+    OsRng.fill_bytes(&mut nonce);
+    writer.write_all(&nonce).await?;
+
+    let payload_key = hkdf(&nonce, &file_key, PAYLOAD_INFO);
+    stream::seal(&payload_key, reader, writer).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Decrypts an age file read from `reader` with the first of `identities` that matches.
+///
+/// Payload chunks are written as soon as they authenticate. When this returns an error,
+/// `writer` may hold a prefix of the plaintext that must be discarded.
+pub async fn decrypt_stream<R, W>(
+    identities: &[Identity],
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), AesError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut reader = BufReader::new(reader);
+    let header = Header::read(&mut reader).await?;
+
+    let has_scrypt = header
+        .stanzas
+        .iter()
+        .any(|stanza| stanza.tag == scrypt::STANZA_TAG);
+    if has_scrypt && header.stanzas.len() > 1 {
+        return Err(AgeError::InvalidStanza("scrypt stanza must be alone".to_string()).into());
+    }
+
+    let mut file_key = None;
+    'search: for identity in identities {
+        for stanza in &header.stanzas {
+            if let Some(key) = identity.unwrap(stanza)? {
+                file_key = Some(key);
+                break 'search;
+            }
+        }
+    }
+    let file_key = file_key.ok_or(AgeError::NoMatchingIdentity)?;
+
+    header_mac(&file_key, &header.mac_input)
+        .verify_slice(&header.mac)
+        .map_err(|_| AgeError::HeaderMacMismatch)?;
+
+    let mut nonce = [0u8; PAYLOAD_NONCE_LENGTH];
+    reader
+        .read_exact(&mut nonce)
+        .await
+        .map_err(|_| AgeError::MalformedHeader("missing payload nonce".to_string()))?;
+
+    let payload_key = hkdf(&nonce, &file_key, PAYLOAD_INFO);
+    stream::open(&payload_key, &mut reader, writer).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Encrypts `source` to `recipients` into `destination`, which may equal `source`. The
+/// destination is replaced atomically, keeping the source's permissions.
+pub async fn encrypt_file(
+    recipients: &[Recipient],
+    source: &Path,
+    destination: &Path,
+) -> Result<(), AesError> {
+    let mut reader = File::open(source).await?;
+    let metadata = reader.metadata().await?;
+
+    let mut output = AtomicFile::create(destination).await?;
+    encrypt_stream(recipients, &mut reader, output.file()).await?;
+    output.commit(Some(&metadata)).await?;
+    Ok(())
+}
+
+/// Decrypts the age file `source` into `destination`, which may equal `source`. The
+/// destination is only replaced once the whole payload authenticated.
+pub async fn decrypt_file(
+    identities: &[Identity],
+    source: &Path,
+    destination: &Path,
+) -> Result<(), AesError> {
+    let mut reader = File::open(source).await?;
+    let metadata = reader.metadata().await?;
+
+    let mut output = AtomicFile::create(destination).await?;
+    decrypt_stream(identities, &mut reader, output.file()).await?;
+    output.commit(Some(&metadata)).await?;
+    Ok(())
+}
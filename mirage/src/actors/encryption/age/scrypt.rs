@@ -0,0 +1,134 @@
+//! Passphrase recipients. An scrypt stanza must be the only stanza in a header.
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+use super::error::AgeError;
+use super::format::{decode_base64, encode_base64, Stanza};
+use super::{FileKey, FILE_KEY_LENGTH, WRAPPED_FILE_KEY_LENGTH};
+use crate::actors::encryption::kdf::{self, error::KdfError, KdfAlgorithm, KdfCost};
+use crate::actors::encryption::AesError;
+
+pub const STANZA_TAG: &str = "scrypt";
+const SALT_LABEL: &[u8] = b"age-encryption.org/v1/scrypt";
+const SALT_LENGTH: usize = 16;
+const R: u32 = 8;
+const P: u32 = 1;
+
+/// Highest work factor a `ScryptIdentity` accepts unless configured otherwise.
+pub const DEFAULT_MAX_WORK_FACTOR: u8 = 22;
+
+pub struct ScryptRecipient {
+    passphrase: Vec<u8>,
+    work_factor: u8,
+}
+
+impl ScryptRecipient {
+    /// Encrypts to `passphrase` with scrypt cost `2^work_factor`.
+    pub fn new(passphrase: &[u8], work_factor: u8) -> Self {
+        ScryptRecipient {
+            passphrase: passphrase.to_vec(),
+            work_factor,
+        }
+    }
+
+    /// Encrypts to `passphrase` with the work factor calibrated like `KdfCost::from_config`.
+    pub fn calibrated(passphrase: &[u8]) -> Result<Self, AesError> {
+        match KdfCost::calibrate(KdfAlgorithm::Scrypt, kdf::unlock_time())? {
+            KdfCost::Scrypt { log_n, .. } => Ok(Self::new(passphrase, log_n)),
+            KdfCost::Argon2id { .. } => unreachable!("scrypt calibration returns scrypt costs"),
+        }
+    }
+
+    pub fn wrap(&self, file_key: &FileKey) -> Result<Stanza, AesError> {
+        let mut salt = [0u8; SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+
+        let wrap_key = wrap_key(&self.passphrase, &salt, self.work_factor)?;
+        let body = ChaCha20Poly1305::new(&wrap_key.into())
+            .encrypt(&Nonce::default(), file_key.as_slice())
+            .map_err(AesError::AesGcmError)?;
+
+        Ok(Stanza {
+            tag: STANZA_TAG.to_string(),
+            args: vec![encode_base64(&salt), self.work_factor.to_string()],
+            body,
+        })
+    }
+}
+
+pub struct ScryptIdentity {
+    passphrase: Vec<u8>,
+    max_work_factor: u8,
+}
+
+impl ScryptIdentity {
+    pub fn new(passphrase: &[u8]) -> Self {
+        Self::with_max_work_factor(passphrase, DEFAULT_MAX_WORK_FACTOR)
+    }
+
+    /// Refuses stanzas that ask for more than `2^max_work_factor` scrypt iterations.
+    pub fn with_max_work_factor(passphrase: &[u8], max_work_factor: u8) -> Self {
+        ScryptIdentity {
+            passphrase: passphrase.to_vec(),
+            max_work_factor,
+        }
+    }
+
+    /// Recovers the file key from `stanza`, or `None` if the passphrase does not match.
+    pub fn unwrap(&self, stanza: &Stanza) -> Result<Option<FileKey>, AesError> {
+        if stanza.tag != STANZA_TAG {
+            return Ok(None);
+        }
+        let [salt, work_factor] = stanza.args.as_slice() else {
+            return Err(AgeError::InvalidStanza("scrypt takes two arguments".to_string()).into());
+        };
+        let salt: [u8; SALT_LENGTH] = decode_base64(salt)?
+            .try_into()
+            .map_err(|_| AgeError::InvalidStanza("scrypt salt must be 16 bytes".to_string()))?;
+        let work_factor = parse_work_factor(work_factor)?;
+        if work_factor > self.max_work_factor {
+            return Err(AgeError::WorkFactorTooHigh(work_factor).into());
+        }
+        if stanza.body.len() != WRAPPED_FILE_KEY_LENGTH {
+            return Err(AgeError::InvalidStanza(
+                "wrapped file key has the wrong length".to_string(),
+            )
+            .into());
+        }
+
+        let wrap_key = wrap_key(&self.passphrase, &salt, work_factor)?;
+        Ok(ChaCha20Poly1305::new(&wrap_key.into())
+            .decrypt(&Nonce::default(), stanza.body.as_slice())
+            .ok()
+            .map(|file_key| {
+                let mut key = [0u8; FILE_KEY_LENGTH];
+                key.copy_from_slice(&file_key);
+                key
+            }))
+    }
+}
+
+/// Work factors are plain decimal without sign or leading zeros.
+fn parse_work_factor(text: &str) -> Result<u8, AgeError> {
+    let invalid = || AgeError::InvalidStanza(format!("invalid scrypt work factor '{}'", text));
+    if text.starts_with('0') || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    text.parse::<u8>().map_err(|_| invalid())
+}
+
+fn wrap_key(passphrase: &[u8], salt: &[u8], work_factor: u8) -> Result<[u8; 32], AesError> {
+    let mut labeled_salt = Vec::with_capacity(SALT_LABEL.len() + salt.len());
+    labeled_salt.extend_from_slice(SALT_LABEL);
+    labeled_salt.extend_from_slice(salt);
+
+    let params = scrypt::Params::new(work_factor, R, P, 32)
+        .map_err(|e| KdfError::InvalidParameters(e.to_string()))?;
+    let mut key = [0u8; 32];
+    scrypt::scrypt(passphrase, &labeled_salt, &params, &mut key)
+        .map_err(|e| KdfError::DerivationFailed(e.to_string()))?;
+    Ok(key)
+}
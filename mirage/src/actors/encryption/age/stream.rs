@@ -0,0 +1,83 @@
+//! The age payload: ChaCha20-Poly1305 STREAM over 64 KiB chunks.
+//!
+//! Unlike `encryption::stream`, each chunk nonce is an 11-byte big-endian counter followed
+//! by the last-chunk flag and no associated data is authenticated. The final chunk may be
+//! full, but it may only be empty when the whole payload is empty.
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use super::error::AgeError;
+use crate::actors::encryption::stream::ChunkReader;
+use crate::actors::encryption::{AesError, TAG_LENGTH};
+use crate::utils::secret::SecretBytes;
+
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+fn chunk_nonce(index: u64, is_last: bool) -> Nonce {
+    let mut nonce = Nonce::default();
+    nonce[3..11].copy_from_slice(&index.to_be_bytes());
+    nonce[11] = u8::from(is_last);
+    nonce
+}
+
+/// Encrypts everything `reader` yields into `writer` under `payload_key`.
+pub async fn seal<R, W>(
+    payload_key: &[u8; 32],
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), AesError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let cipher = ChaCha20Poly1305::new(payload_key.into());
+    let mut chunks = ChunkReader::new(reader, CHUNK_SIZE);
+
+    for index in 0u64.. {
+        let (batch, is_last) = chunks.next_batch(1).await?;
+        for chunk in &batch {
+            let sealed = cipher.encrypt(&chunk_nonce(index, is_last), chunk.as_slice())?;
+            writer.write_all(&sealed).await?;
+        }
+        if is_last {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Decrypts a payload sealed by `seal`, writing each chunk once it authenticated.
+pub async fn open<R, W>(
+    payload_key: &[u8; 32],
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), AesError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let cipher = ChaCha20Poly1305::new(payload_key.into());
+    let mut chunks = ChunkReader::new(reader, CHUNK_SIZE + TAG_LENGTH);
+
+    for index in 0u64.. {
+        let (batch, is_last) = chunks.next_batch(1).await?;
+        for chunk in &batch {
+            let plaintext = cipher
+                .decrypt(&chunk_nonce(index, is_last), chunk.as_slice())
+                .map(SecretBytes::new)
+                .map_err(|_| {
+                    AgeError::PayloadFailure(format!("chunk {} failed authentication", index))
+                })?;
+            if is_last && index > 0 && plaintext.is_empty() {
+                return Err(AgeError::PayloadFailure("final chunk is empty".to_string()).into());
+            }
+            writer.write_all(&plaintext).await?;
+        }
+        if is_last {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,146 @@
+//! X25519 recipients (`age1...`) and identities (`AGE-SECRET-KEY-1...`).
+
+use bech32::{FromBase32, ToBase32, Variant};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use rand::rngs::OsRng;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+use super::error::AgeError;
+use super::format::{decode_base64, encode_base64, Stanza};
+use super::{hkdf, FileKey, FILE_KEY_LENGTH, WRAPPED_FILE_KEY_LENGTH};
+use crate::actors::encryption::AesError;
+
+pub const STANZA_TAG: &str = "X25519";
+const RECIPIENT_HRP: &str = "age";
+const IDENTITY_HRP: &str = "age-secret-key-";
+const WRAP_INFO: &[u8] = b"age-encryption.org/v1/X25519";
+const KEY_LENGTH: usize = 32;
+
+/// Public key files are encrypted to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct X25519Recipient(PublicKey);
+
+impl X25519Recipient {
+    /// Parses a Bech32 `age1...` recipient.
+    pub fn parse(encoded: &str) -> Result<Self, AesError> {
+        let key = decode_bech32(encoded, RECIPIENT_HRP).map_err(AgeError::InvalidRecipient)?;
+        Ok(X25519Recipient(PublicKey::from(key)))
+    }
+
+    /// Seals `file_key` to this recipient under a fresh ephemeral key.
+    pub fn wrap(&self, file_key: &FileKey) -> Result<Stanza, AesError> {
+        let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+        let share = PublicKey::from(&ephemeral);
+        let shared_secret = ephemeral.diffie_hellman(&self.0);
+
+        let wrap_key = wrap_key(
+            share.as_bytes(),
+            self.0.as_bytes(),
+            shared_secret.as_bytes(),
+        );
+        let body = ChaCha20Poly1305::new(&wrap_key.into())
+            .encrypt(&Nonce::default(), file_key.as_slice())
+            .map_err(AesError::AesGcmError)?;
+
+        Ok(Stanza {
+            tag: STANZA_TAG.to_string(),
+            args: vec![encode_base64(share.as_bytes())],
+            body,
+        })
+    }
+}
+
+impl std::fmt::Display for X25519Recipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let encoded = bech32::encode(
+            RECIPIENT_HRP,
+            self.0.as_bytes().to_base32(),
+            Variant::Bech32,
+        )
+        .map_err(|_| std::fmt::Error)?;
+        write!(f, "{}", encoded)
+    }
+}
+
+/// Secret key that opens files encrypted to its `X25519Recipient`.
+#[derive(Clone)]
+pub struct X25519Identity(StaticSecret);
+
+impl X25519Identity {
+    pub fn generate() -> Self {
+        X25519Identity(StaticSecret::random_from_rng(OsRng))
+    }
+
+    /// Parses a Bech32 `AGE-SECRET-KEY-1...` identity.
+    pub fn parse(encoded: &str) -> Result<Self, AesError> {
+        let key = decode_bech32(encoded, IDENTITY_HRP).map_err(AgeError::InvalidIdentity)?;
+        Ok(X25519Identity(StaticSecret::from(key)))
+    }
+
+    /// The upper-case Bech32 encoding used by `age-keygen`.
+    pub fn encode(&self) -> String {
+        bech32::encode(IDENTITY_HRP, self.0.as_bytes().to_base32(), Variant::Bech32)
+            .expect("identity HRP is valid")
+            .to_uppercase()
+    }
+
+    pub fn to_recipient(&self) -> X25519Recipient {
+        X25519Recipient(PublicKey::from(&self.0))
+    }
+
+    /// Recovers the file key from `stanza`, or `None` if it is addressed to someone else.
+    pub fn unwrap(&self, stanza: &Stanza) -> Result<Option<FileKey>, AesError> {
+        if stanza.tag != STANZA_TAG {
+            return Ok(None);
+        }
+        let [share] = stanza.args.as_slice() else {
+            return Err(AgeError::InvalidStanza("X25519 takes one argument".to_string()).into());
+        };
+        let share: [u8; KEY_LENGTH] = decode_base64(share)?
+            .try_into()
+            .map_err(|_| AgeError::InvalidStanza("X25519 share must be 32 bytes".to_string()))?;
+        if stanza.body.len() != WRAPPED_FILE_KEY_LENGTH {
+            return Err(AgeError::InvalidStanza(
+                "wrapped file key has the wrong length".to_string(),
+            )
+            .into());
+        }
+
+        let shared_secret = self.0.diffie_hellman(&PublicKey::from(share));
+        if !shared_secret.was_contributory() {
+            return Err(
+                AgeError::InvalidStanza("X25519 share is a low-order point".to_string()).into(),
+            );
+        }
+
+        let recipient = PublicKey::from(&self.0);
+        let wrap_key = wrap_key(&share, recipient.as_bytes(), shared_secret.as_bytes());
+        Ok(ChaCha20Poly1305::new(&wrap_key.into())
+            .decrypt(&Nonce::default(), stanza.body.as_slice())
+            .ok()
+            .map(|file_key| {
+                let mut key = [0u8; FILE_KEY_LENGTH];
+                key.copy_from_slice(&file_key);
+                key
+            }))
+    }
+}
+
+fn wrap_key(share: &[u8], recipient: &[u8], shared_secret: &[u8]) -> [u8; 32] {
+    let mut salt = Vec::with_capacity(2 * KEY_LENGTH);
+    salt.extend_from_slice(share);
+    salt.extend_from_slice(recipient);
+    hkdf(&salt, shared_secret, WRAP_INFO)
+}
+
+fn decode_bech32(encoded: &str, expected_hrp: &str) -> Result<[u8; KEY_LENGTH], String> {
+    let (hrp, data, variant) = bech32::decode(encoded).map_err(|e| e.to_string())?;
+    if hrp != expected_hrp || variant != Variant::Bech32 {
+        return Err(format!("expected a Bech32 '{}' string", expected_hrp));
+    }
+    Vec::<u8>::from_base32(&data)
+        .map_err(|e| e.to_string())?
+        .try_into()
+        .map_err(|_| "key must be 32 bytes".to_string())
+}
@@ -1,149 +1,406 @@
-use aes::cipher::{generic_array::GenericArray, typenum::U12};
-use aes_gcm::{aead::Aead, Aes128Gcm, Aes256Gcm, KeyInit, Nonce};
-use log::{debug, error};
-use rayon::{iter::ParallelIterator, slice::ParallelSlice};
-use std::path::PathBuf;
+use log::debug;
+use std::ops::DerefMut;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::fs::File;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 
 use crate::{
     actors::Actor,
     tools::config::{Config, EncryptionLevel},
 };
 
-use super::{AesError, CryptoBase, Input, KEY_LENGTH};
+use super::envelope::{error::EnvelopeError, EnvelopeHeader};
+use super::kdf::{error::KdfError, Passphrase};
+use super::keyring::Keyring;
+use super::recipients::{error::RecipientError, RecipientKey};
+use crate::actors::signing::error::SignatureError;
+use crate::actors::signing::{TrailingSignature, VerifyingKey};
+use crate::utils::file_system::AtomicFile;
+use crate::utils::secret::{SecretBytes, SecretKey};
+use crate::utils::secure_region::{Backend, SecureRegion};
+
+use super::stream::{ChunkReader, Stream};
+use super::{suite::CipherSuite, AesError, CryptoBase, Input, Job, Output, TAG_LENGTH};
+
+/// Where a decryptor gets the key for an envelope.
+#[derive(Clone)]
+enum KeySource {
+    /// The key given at construction.
+    Key,
+    /// Derived from a passphrase and the header's key derivation block.
+    Passphrase(Arc<Passphrase>),
+    /// Unwrapped with an RSA private key from the header's recipients block.
+    Recipient(Arc<RecipientKey>),
+    /// Looked up in a keyring by the header's key id.
+    Keyring(Keyring),
+}
 
 #[derive(Clone)]
 pub struct Decryptor {
     base: CryptoBase,
+    source: KeySource,
+    /// Keys whose signatures are accepted; when non-empty, envelopes must be signed.
+    verifiers: Arc<Vec<VerifyingKey>>,
 }
 
 impl Actor<Input> for Decryptor {
+    type Output = Option<Job>;
+
     fn is_alive(&self) -> bool {
-        self.base.is_alive
+        self.base.is_alive()
     }
 
-    fn run(&self, input: Option<Input>) {
-        let runtime_handle = Arc::clone(&self.base.runtime_handle);
-
-        if let Some(input_type) = input {
-            match input_type {
-                Input::File(path) => {
-                    let decryptor_clone = self.clone();
-                    runtime_handle.spawn(async move {
-                        if let Err(e) = decryptor_clone.process_file(path).await {
-                            error!("Error processing file: {:?}", e);
-                        }
-                    });
-                }
-                Input::Buffer(mut data) => {
-                    let decryptor_clone = self.clone();
-                    runtime_handle.spawn(async move {
-                        if let Err(e) = decryptor_clone.process_buffer_or_bit(&mut data).await {
-                            error!("Error processing buffer: {:?}", e);
-                        }
-                    });
-                }
-                Input::Bit(bit) => {
-                    let mut data = vec![bit];
-                    let decryptor_clone = self.clone();
-                    runtime_handle.spawn(async move {
-                        if let Err(e) = decryptor_clone.process_buffer_or_bit(&mut data).await {
-                            error!("Error processing bit: {:?}", e);
-                        }
-                    });
-                }
-            }
-        }
+    /// Spawns a job for `input` and returns its handle, or `None` without input.
+    fn run(&self, input: Option<Input>) -> Option<Job> {
+        input.map(|input_type| self.dispatch(input_type, Vec::new()))
     }
 }
 
 impl Decryptor {
     pub fn new(
         level: Option<EncryptionLevel>,
-        key: Vec<u8>,
-        nonce: Vec<u8>,
+        key: impl Into<SecretKey>,
+        runtime_handle: Arc<tokio::runtime::Handle>,
+    ) -> Self {
+        let suite = level.map_or_else(CipherSuite::from_config, CipherSuite::from);
+        Self::with_suite(suite, key, runtime_handle)
+    }
+
+    /// Creates a decryptor for an explicit `CipherSuite`.
+    ///
+    /// The suite only describes the key; ciphertexts are always opened with the suite
+    /// recorded in them.
+    pub fn with_suite(
+        suite: CipherSuite,
+        key: impl Into<SecretKey>,
         runtime_handle: Arc<tokio::runtime::Handle>,
     ) -> Self {
-        let level = level.unwrap_or_else(|| Config::get_parameters().encryption_level);
         let has_parallel_processing = Config::get_features().parallel_processing;
 
         Decryptor {
-            base: CryptoBase {
-                is_alive: true,
-                level,
-                key: Arc::new(key),
-                nonce: Arc::new(nonce),
-                has_parallel_processing,
-                runtime_handle,
-            },
+            base: CryptoBase::new(suite, key.into(), has_parallel_processing, runtime_handle),
+            source: KeySource::Key,
+            verifiers: Arc::new(Vec::new()),
         }
     }
 
-    async fn process_file(&self, path: PathBuf) -> Result<(), AesError> {
-        let mut file = File::open(&path).await.map_err(AesError::IoError)?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)
-            .await
-            .map_err(AesError::IoError)?;
+    /// Creates a decryptor for envelopes sealed by `Encryptor::with_passphrase`. The key
+    /// is derived from the salt and cost recorded in each envelope.
+    pub fn with_passphrase(passphrase: &[u8], runtime_handle: Arc<tokio::runtime::Handle>) -> Self {
+        let mut decryptor = Self::with_suite(
+            CipherSuite::from_config(),
+            SecretKey::default(),
+            runtime_handle,
+        );
+        decryptor.source = KeySource::Passphrase(Arc::new(Passphrase::new(passphrase.to_vec())));
+        decryptor
+    }
 
-        let decrypted_data = self.decrypt(&buffer)?;
+    /// Creates a decryptor for envelopes addressed to `private_key` by
+    /// `Encryptor::for_recipients`.
+    pub fn with_recipient_key(
+        private_key: RecipientKey,
+        runtime_handle: Arc<tokio::runtime::Handle>,
+    ) -> Self {
+        let mut decryptor = Self::with_suite(
+            CipherSuite::from_config(),
+            SecretKey::default(),
+            runtime_handle,
+        );
+        decryptor.source = KeySource::Recipient(Arc::new(private_key));
+        decryptor
+    }
 
-        let mut file = File::create(&path).await.map_err(AesError::IoError)?;
-        file.write_all(&decrypted_data)
-            .await
-            .map_err(AesError::IoError)?;
+    /// Creates a decryptor that opens envelopes sealed with any key of `keyring` that was
+    /// not destroyed, picking the key by the id in each header.
+    pub fn with_keyring(keyring: Keyring, runtime_handle: Arc<tokio::runtime::Handle>) -> Self {
+        let mut decryptor = Self::with_suite(
+            CipherSuite::from_config(),
+            SecretKey::default(),
+            runtime_handle,
+        );
+        decryptor.source = KeySource::Keyring(keyring);
+        decryptor
+    }
 
-        Ok(())
+    /// Only accepts envelopes whose header names `key_id`.
+    pub fn with_key_id(mut self, key_id: Vec<u8>) -> Self {
+        self.base.key_id = Arc::new(key_id);
+        self
     }
 
-    async fn process_buffer_or_bit(&self, buffer: &mut Vec<u8>) -> Result<(), AesError> {
-        let decrypted_data = self.decrypt(buffer)?;
+    /// Accepts envelopes signed by `verifier`, which may be called for several signers.
+    /// Once a verifier is set, unsigned envelopes and envelopes signed by anyone else
+    /// are rejected. Without one, signed envelopes cannot be decrypted.
+    pub fn with_verifier(mut self, verifier: VerifyingKey) -> Self {
+        Arc::make_mut(&mut self.verifiers).push(verifier);
+        self
+    }
 
-        buffer.clear();
-        buffer.extend_from_slice(&decrypted_data);
+    /// Number of jobs spawned by `run` that are still in flight.
+    pub fn pending_jobs(&self) -> usize {
+        self.base.pending_jobs()
+    }
 
-        Ok(())
+    /// Stops accepting jobs; later `run` calls resolve to `AesError::ActorStopped`.
+    pub fn stop(&self) {
+        self.base.stop()
+    }
+
+    /// Spawns the job for `input`. `aad` is the context carried by an enclosing
+    /// `Input::WithAad`; the innermost context wins.
+    fn dispatch(&self, input: Input, aad: Vec<u8>) -> Job {
+        let decryptor_clone = self.clone();
+
+        match input {
+            Input::File(path) => self.base.spawn("file", async move {
+                decryptor_clone.process_file(path.clone(), path, &aad).await
+            }),
+            Input::FileTo(source, destination) => self.base.spawn("file", async move {
+                decryptor_clone
+                    .process_file(source, destination, &aad)
+                    .await
+            }),
+            Input::Buffer(data) => self.base.spawn("buffer", async move {
+                decryptor_clone.process_buffer_or_bit(&data, &aad).await
+            }),
+            Input::Bit(bit) => self.base.spawn("bit", async move {
+                decryptor_clone.process_buffer_or_bit(&[bit], &aad).await
+            }),
+            Input::WithAad(input, aad) => self.dispatch(*input, aad),
+        }
+    }
+
+    async fn process_file(
+        &self,
+        source: PathBuf,
+        destination: PathBuf,
+        aad: &[u8],
+    ) -> Result<Output, AesError> {
+        self.decrypt_file(&source, &destination, aad).await?;
+        Ok(Output::File(destination))
+    }
+
+    async fn process_buffer_or_bit(&self, buffer: &[u8], aad: &[u8]) -> Result<Output, AesError> {
+        self.decrypt_with_aad(buffer, aad).map(Output::Buffer)
     }
 
     pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, AesError> {
-        match &self.base.level {
-            EncryptionLevel::Level1 => self.decrypt_aes_gcm::<Aes128Gcm>(data),
-            EncryptionLevel::Level2 => self.decrypt_aes_gcm::<Aes256Gcm>(data),
+        self.decrypt_with_aad(data, &[])
+    }
+
+    /// Decrypts `data` sealed by `Encryptor::encrypt_with_aad` with the same `aad`.
+    pub fn decrypt_with_aad(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>, AesError> {
+        let (mut plaintext, message_length) =
+            self.open_envelope(data, aad, |length| Ok(SecretBytes::new(vec![0; length])))?;
+        // The signature left in the spare capacity is public.
+        plaintext.truncate(message_length);
+        // Moved out rather than copied, so the caller holds the only copy.
+        Ok(std::mem::take(&mut *plaintext))
+    }
+
+    /// Like `decrypt_with_aad`, but opens the plaintext in place inside a `SecureRegion`
+    /// from `backend`, so it never passes through ordinary heap memory. A signed
+    /// envelope is opened into a scratch region and its message copied into a region
+    /// of its own, since the signature trails it.
+    pub fn decrypt_to_region(
+        &self,
+        data: &[u8],
+        aad: &[u8],
+        backend: Backend,
+    ) -> Result<SecureRegion, AesError> {
+        let (plaintext, message_length) = self.open_envelope(data, aad, |length| {
+            Ok(SecureRegion::new_in(length, backend)?)
+        })?;
+        if message_length == plaintext.len() {
+            return Ok(plaintext);
         }
+        Ok(SecureRegion::from_slice_in(
+            &plaintext[..message_length],
+            backend,
+        )?)
     }
 
-    fn decrypt_aes_gcm<C>(&self, data: &[u8]) -> Result<Vec<u8>, AesError>
+    /// Opens the envelope `data` in place into a buffer from `allocate`, which gets the
+    /// length of the plaintext, and checks its signature. Returns the buffer and the
+    /// length of the message in front of the signature.
+    fn open_envelope<B>(
+        &self,
+        data: &[u8],
+        aad: &[u8],
+        allocate: impl FnOnce(usize) -> Result<B, AesError>,
+    ) -> Result<(B, usize), AesError>
     where
-        C: Aead + KeyInit + Send + Sync + 'static,
+        B: DerefMut,
+        B::Target: AsRef<[u8]> + AsMut<[u8]>,
     {
-        if self.base.nonce.len() != KEY_LENGTH {
-            return Err(AesError::InvalidKeyLength);
+        let (header, header_length) = EnvelopeHeader::parse(data)?;
+        let key = self.key_for(&header)?;
+
+        let (header_bytes, body) = data.split_at(header_length);
+        let signature = self.signature_check(&header, header_bytes)?;
+        let associated_data = EnvelopeHeader::associated_data(header_bytes, aad);
+        let stream = Stream::new(
+            &header.nonce_prefix,
+            &associated_data,
+            header.chunk_size as usize,
+        );
+        let mut plaintext = allocate(stream.plaintext_length(body)?)?;
+        header.suite.open_into(
+            &key,
+            &stream,
+            body,
+            (*plaintext).as_mut(),
+            self.base.has_parallel_processing,
+        )?;
+
+        let plaintext_bytes = (*plaintext).as_ref();
+        let message_length = match signature {
+            Some((verifier, trailer)) => {
+                let (digest, message_length) = trailer.split(plaintext_bytes)?;
+                verifier.verify_digest(digest, &plaintext_bytes[message_length..])?;
+                message_length
+            }
+            None => plaintext_bytes.len(),
+        };
+        Ok((plaintext, message_length))
+    }
+
+    /// Decrypts `source` into `destination`, which may equal `source`. `destination` is
+    /// only replaced once every chunk authenticated, so a tampered or truncated file never
+    /// overwrites anything.
+    pub async fn decrypt_file(
+        &self,
+        source: &Path,
+        destination: &Path,
+        aad: &[u8],
+    ) -> Result<(), AesError> {
+        let mut reader = File::open(source).await?;
+        let metadata = reader.metadata().await?;
+
+        let mut output = AtomicFile::create(destination).await?;
+        self.decrypt_stream(&mut reader, output.file(), aad).await?;
+        output.commit(Some(&metadata)).await?;
+        Ok(())
+    }
+
+    /// Decrypts an envelope read from `reader` into `writer` one chunk batch at a time.
+    ///
+    /// Chunks are written as soon as they authenticate. When this returns an error,
+    /// `writer` may hold a prefix of the plaintext that must be discarded.
+    pub async fn decrypt_stream<R, W>(
+        &self,
+        reader: &mut R,
+        writer: &mut W,
+        aad: &[u8],
+    ) -> Result<(), AesError>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let (header, header_bytes) = EnvelopeHeader::read_from(reader).await?;
+        let key = self.key_for(&header)?;
+        let mut signature = self.signature_check(&header, &header_bytes)?;
+
+        let chunk_size = header.chunk_size as usize;
+        let associated_data = EnvelopeHeader::associated_data(&header_bytes, aad);
+        let stream = Stream::new(&header.nonce_prefix, &associated_data, chunk_size);
+        let batch_size = self.base.batch_size();
+        let mut chunks = ChunkReader::new(reader, chunk_size + TAG_LENGTH);
+        let mut index = 0u32;
+
+        loop {
+            let (batch, is_final) = chunks.next_batch(batch_size).await?;
+            let opened = header.suite.open_batch(
+                &key,
+                &stream,
+                index,
+                &batch,
+                is_final,
+                self.base.has_parallel_processing,
+            )?;
+            for chunk in &opened {
+                match &mut signature {
+                    Some((_, trailer)) => writer.write_all(&trailer.push(chunk)).await?,
+                    None => writer.write_all(chunk).await?,
+                }
+            }
+            if is_final {
+                break;
+            }
+            index = index
+                .checked_add(batch.len() as u32)
+                .ok_or(AesError::StreamTooLong)?;
+        }
+
+        if let Some((verifier, trailer)) = signature {
+            let (digest, signature) = trailer.finish()?;
+            verifier.verify_digest(digest, &signature)?;
         }
+        writer.flush().await?;
+        Ok(())
+    }
+
+    /// The verifier for `header`'s signer, with a trailer primed with the header bytes,
+    /// or `None` for an unsigned envelope this decryptor accepts.
+    fn signature_check(
+        &self,
+        header: &EnvelopeHeader,
+        header_bytes: &[u8],
+    ) -> Result<Option<(&VerifyingKey, TrailingSignature)>, AesError> {
+        let Some(signer) = &header.signer else {
+            if self.verifiers.is_empty() {
+                return Ok(None);
+            }
+            return Err(SignatureError::Unsigned.into());
+        };
 
-        let cipher = Arc::new(C::new_from_slice(&self.base.key).map_err(AesError::from)?);
+        let verifier = self
+            .verifiers
+            .iter()
+            .find(|verifier| {
+                verifier.algorithm() == signer.algorithm
+                    && verifier.fingerprint() == &signer.fingerprint
+            })
+            .ok_or(SignatureError::UnknownSigner)?;
+        let mut digest = signer.algorithm.new_digest();
+        digest.update(header_bytes);
+        Ok(Some((
+            verifier,
+            TrailingSignature::new(digest, signer.signature_length as usize),
+        )))
+    }
+
+    /// Key that opens the envelope described by `header`. Rejects envelopes for another
+    /// key id or a key of the wrong length.
+    fn key_for(&self, header: &EnvelopeHeader) -> Result<Arc<SecretKey>, AesError> {
+        if !self.base.key_id.is_empty() && header.key_id != *self.base.key_id {
+            return Err(EnvelopeError::KeyIdMismatch.into());
+        }
 
-        let nonce_generic = GenericArray::<u8, U12>::from_slice(&self.base.nonce);
-        let nonce = Nonce::from_slice(nonce_generic);
+        let key = match &self.source {
+            KeySource::Key => Arc::clone(&self.base.key),
+            KeySource::Passphrase(passphrase) => {
+                let params = header.kdf.as_ref().ok_or(KdfError::MissingParameters)?;
+                passphrase.key(params, header.suite.key_length())?
+            }
+            KeySource::Recipient(private_key) => {
+                if header.recipients.is_empty() {
+                    return Err(RecipientError::NotARecipient.into());
+                }
+                Arc::new(private_key.unwrap(&header.recipients)?)
+            }
+            KeySource::Keyring(keyring) => keyring.decryption_key(&header.key_id, header.suite)?,
+        };
+        CryptoBase::check_key_length(&key, header.suite)?;
 
         if cfg!(feature = "development") {
             debug!(
-                "[Decryptor] Decrypting data with key: {:?} and nonce: {:?}",
-                self.base.key, self.base.nonce
+                "[Decryptor] Decrypting data with {} key: {:?}",
+                header.suite, key
             );
         }
-
-        if self.base.has_parallel_processing {
-            data.par_chunks(1024)
-                .map_init(
-                    || cipher.clone(),
-                    |cipher, chunk| cipher.decrypt(nonce, chunk).map_err(AesError::from),
-                )
-                .collect::<Result<Vec<_>, _>>()
-                .map(|chunks| chunks.concat())
-        } else {
-            cipher.decrypt(nonce, data).map_err(AesError::from)
-        }
+        Ok(key)
     }
 }
@@ -1,149 +1,360 @@
-use aes::cipher::{generic_array::GenericArray, typenum::U12};
-use aes_gcm::{aead::Aead, Aes128Gcm, Aes256Gcm, KeyInit, Nonce};
-use log::{debug, error};
-use rayon::{iter::ParallelIterator, slice::ParallelSlice};
-use std::path::PathBuf;
+use log::debug;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::fs::File;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 
 use crate::{
     actors::Actor,
     tools::config::{Config, EncryptionLevel},
 };
 
-use super::{AesError, CryptoBase, Input, KEY_LENGTH};
+use crate::actors::signing::{SignedReader, SigningKey};
+use crate::utils::file_system::AtomicFile;
+use crate::utils::key_generator::{generate_key, generate_nonce_prefix};
+use crate::utils::secret::{SecretBytes, SecretKey};
+
+use super::envelope::EnvelopeHeader;
+use super::kdf::{KdfCost, KdfParams};
+use super::keyring::Keyring;
+use super::recipients::{self, Recipient, WrappedKey};
+use super::stream::{ChunkReader, Stream, DEFAULT_CHUNK_SIZE};
+use super::{suite::CipherSuite, AesError, CryptoBase, Input, Job, Output};
 
 #[derive(Clone)]
 pub struct Encryptor {
     base: CryptoBase,
+    /// Set when the key was derived from a passphrase; recorded in every header.
+    kdf: Option<Arc<KdfParams>>,
+    /// Set when the key is a data key wrapped for RSA recipients; recorded in every header.
+    recipients: Option<Arc<Vec<WrappedKey>>>,
+    /// Set when the plaintext of every envelope is signed before it is sealed.
+    signer: Option<Arc<SigningKey>>,
 }
 
 impl Actor<Input> for Encryptor {
+    type Output = Option<Job>;
+
     fn is_alive(&self) -> bool {
-        self.base.is_alive
-    }
-
-    fn run(&self, input: Option<Input>) {
-        let runtime_handle = Arc::clone(&self.base.runtime_handle);
-
-        if let Some(input_type) = input {
-            match input_type {
-                Input::File(path) => {
-                    let encryptor_clone = self.clone();
-                    runtime_handle.spawn(async move {
-                        if let Err(e) = encryptor_clone.process_file(path).await {
-                            error!("Error processing file: {:?}", e);
-                        }
-                    });
-                }
-                Input::Buffer(mut data) => {
-                    let encryptor_clone = self.clone();
-                    runtime_handle.spawn(async move {
-                        if let Err(e) = encryptor_clone.process_buffer_or_bit(&mut data).await {
-                            error!("Error processing buffer: {:?}", e);
-                        }
-                    });
-                }
-                Input::Bit(bit) => {
-                    let mut data = vec![bit];
-                    let encryptor_clone = self.clone();
-                    runtime_handle.spawn(async move {
-                        if let Err(e) = encryptor_clone.process_buffer_or_bit(&mut data).await {
-                            error!("Error processing bit: {:?}", e);
-                        }
-                    });
-                }
-            }
-        }
+        self.base.is_alive()
+    }
+
+    /// Spawns a job for `input` and returns its handle, or `None` without input.
+    fn run(&self, input: Option<Input>) -> Option<Job> {
+        input.map(|input_type| self.dispatch(input_type, Vec::new()))
     }
 }
 
 impl Encryptor {
     pub fn new(
         level: Option<EncryptionLevel>,
-        key: Vec<u8>,
-        nonce: Vec<u8>,
+        key: impl Into<SecretKey>,
+        runtime_handle: Arc<tokio::runtime::Handle>,
+    ) -> Self {
+        let suite = level.map_or_else(CipherSuite::from_config, CipherSuite::from);
+        Self::with_suite(suite, key, runtime_handle)
+    }
+
+    /// Creates an encryptor for an explicit `CipherSuite` instead of an `EncryptionLevel`.
+    pub fn with_suite(
+        suite: CipherSuite,
+        key: impl Into<SecretKey>,
         runtime_handle: Arc<tokio::runtime::Handle>,
     ) -> Self {
-        let level = level.unwrap_or_else(|| Config::get_parameters().encryption_level);
         let has_parallel_processing = Config::get_features().parallel_processing;
 
         Encryptor {
-            base: CryptoBase {
-                is_alive: true,
-                level,
-                key: Arc::new(key),
-                nonce: Arc::new(nonce),
-                has_parallel_processing,
-                runtime_handle,
-            },
+            base: CryptoBase::new(suite, key.into(), has_parallel_processing, runtime_handle),
+            kdf: None,
+            recipients: None,
+            signer: None,
         }
     }
 
-    async fn process_file(&self, path: PathBuf) -> Result<(), AesError> {
-        let mut file = File::open(&path).await.map_err(AesError::IoError)?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)
-            .await
-            .map_err(AesError::IoError)?;
+    /// Creates an encryptor whose key is derived from `passphrase`, with the configured
+    /// suite and key derivation function calibrated to the configured unlock time.
+    ///
+    /// Calibration runs the key derivation function, so this blocks for a moment.
+    pub fn with_passphrase(
+        passphrase: &[u8],
+        runtime_handle: Arc<tokio::runtime::Handle>,
+    ) -> Result<Self, AesError> {
+        let params = KdfParams::new(KdfCost::from_config()?);
+        Self::with_kdf(
+            CipherSuite::from_config(),
+            passphrase,
+            params,
+            runtime_handle,
+        )
+    }
 
-        let encrypted_data = self.encrypt(&buffer)?;
+    /// Creates an encryptor whose key is derived from `passphrase` with explicit
+    /// parameters. The salt and cost are stored in every envelope.
+    pub fn with_kdf(
+        suite: CipherSuite,
+        passphrase: &[u8],
+        params: KdfParams,
+        runtime_handle: Arc<tokio::runtime::Handle>,
+    ) -> Result<Self, AesError> {
+        let key = params.derive(passphrase, suite.key_length())?;
+        let mut encryptor = Self::with_suite(suite, key, runtime_handle);
+        encryptor.kdf = Some(Arc::new(params));
+        Ok(encryptor)
+    }
 
-        let mut file = File::create(&path).await.map_err(AesError::IoError)?;
-        file.write_all(&encrypted_data)
-            .await
-            .map_err(AesError::IoError)?;
+    /// Creates an encryptor that seals under a fresh random data key wrapped with
+    /// RSA-OAEP for each of `recipients`. Any of them can decrypt with
+    /// `Decryptor::with_recipient_key`; nobody else needs the data key.
+    pub fn for_recipients(
+        suite: CipherSuite,
+        recipients: &[Recipient],
+        runtime_handle: Arc<tokio::runtime::Handle>,
+    ) -> Result<Self, AesError> {
+        let data_key = generate_key(suite);
+        let wrapped = recipients::wrap_for_all(recipients, &data_key)?;
+        let mut encryptor = Self::with_suite(suite, data_key, runtime_handle);
+        encryptor.recipients = Some(Arc::new(wrapped));
+        Ok(encryptor)
+    }
 
-        Ok(())
+    /// Creates an encryptor that seals with the active version of the keyring key
+    /// `name` and records its id in every envelope, see `Decryptor::with_keyring`.
+    pub fn from_keyring(
+        keyring: &Keyring,
+        name: &str,
+        runtime_handle: Arc<tokio::runtime::Handle>,
+    ) -> Result<Self, AesError> {
+        let (metadata, key) = keyring.encryption_key(name)?;
+        Ok(Self::with_suite(metadata.suite, key, runtime_handle)
+            .with_key_id(metadata.id.into_bytes()))
+    }
+
+    /// Records `key_id` in the header of every envelope this encryptor produces.
+    pub fn with_key_id(mut self, key_id: Vec<u8>) -> Self {
+        self.base.key_id = Arc::new(key_id);
+        self
     }
 
-    async fn process_buffer_or_bit(&self, buffer: &mut Vec<u8>) -> Result<(), AesError> {
-        let encrypted_data = self.encrypt(buffer)?;
+    /// Signs the plaintext of every envelope with `signer` before sealing it
+    /// (sign-then-encrypt). The signature covers the header too, so it cannot be moved
+    /// to another envelope; `Decryptor::with_verifier` checks it.
+    pub fn with_signer(mut self, signer: SigningKey) -> Self {
+        self.signer = Some(Arc::new(signer));
+        self
+    }
 
-        buffer.clear();
-        buffer.extend_from_slice(&encrypted_data);
+    /// Number of jobs spawned by `run` that are still in flight.
+    pub fn pending_jobs(&self) -> usize {
+        self.base.pending_jobs()
+    }
 
-        Ok(())
+    /// Stops accepting jobs; later `run` calls resolve to `AesError::ActorStopped`.
+    pub fn stop(&self) {
+        self.base.stop()
+    }
+
+    /// Spawns the job for `input`. `aad` is the context carried by an enclosing
+    /// `Input::WithAad`; the innermost context wins.
+    fn dispatch(&self, input: Input, aad: Vec<u8>) -> Job {
+        let encryptor_clone = self.clone();
+
+        match input {
+            Input::File(path) => self.base.spawn("file", async move {
+                encryptor_clone.process_file(path.clone(), path, &aad).await
+            }),
+            Input::FileTo(source, destination) => self.base.spawn("file", async move {
+                encryptor_clone
+                    .process_file(source, destination, &aad)
+                    .await
+            }),
+            Input::Buffer(data) => self.base.spawn("buffer", async move {
+                encryptor_clone.process_buffer_or_bit(&data, &aad).await
+            }),
+            Input::Bit(bit) => self.base.spawn("bit", async move {
+                encryptor_clone.process_buffer_or_bit(&[bit], &aad).await
+            }),
+            Input::WithAad(input, aad) => self.dispatch(*input, aad),
+        }
+    }
+
+    async fn process_file(
+        &self,
+        source: PathBuf,
+        destination: PathBuf,
+        aad: &[u8],
+    ) -> Result<Output, AesError> {
+        self.encrypt_file(&source, &destination, aad).await?;
+        Ok(Output::File(destination))
+    }
+
+    async fn process_buffer_or_bit(&self, buffer: &[u8], aad: &[u8]) -> Result<Output, AesError> {
+        self.encrypt_with_aad(buffer, aad).map(Output::Buffer)
     }
 
     pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, AesError> {
-        match &self.base.level {
-            EncryptionLevel::Level1 => self.encrypt_aes_gcm::<Aes128Gcm>(data),
-            EncryptionLevel::Level2 => self.encrypt_aes_gcm::<Aes256Gcm>(data),
+        self.encrypt_with_aad(data, &[])
+    }
+
+    /// Encrypts `data` and binds `aad` (file path, tenant, record id, ...) into the
+    /// authentication tag. `aad` is not stored; the same bytes must be supplied to
+    /// `Decryptor::decrypt_with_aad`.
+    pub fn encrypt_with_aad(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>, AesError> {
+        let suite = self.base.suite;
+        self.base.validate_key(suite)?;
+
+        if cfg!(feature = "development") {
+            debug!(
+                "[Encryptor] Encrypting data with {} \nkey: {:?}",
+                suite, self.base.key
+            );
         }
+
+        let header = self.new_header();
+        let mut encrypted = header.encode()?;
+
+        let signed = self.sign_payload(&encrypted, data)?;
+        let data = signed.as_ref().map_or(data, |signed| signed.as_slice());
+        let associated_data = EnvelopeHeader::associated_data(&encrypted, aad);
+        let stream = Stream::new(&header.nonce_prefix, &associated_data, DEFAULT_CHUNK_SIZE);
+        let sealed = suite.seal(
+            &self.base.key,
+            &stream,
+            data,
+            self.base.has_parallel_processing,
+        )?;
+
+        encrypted.extend_from_slice(&sealed);
+        Ok(encrypted)
     }
 
-    fn encrypt_aes_gcm<C>(&self, data: &[u8]) -> Result<Vec<u8>, AesError>
+    /// Encrypts `source` into `destination` one chunk batch at a time. `destination` may
+    /// equal `source`; it is only replaced, keeping the source's permissions and
+    /// modification time, once the whole file was encrypted and synced to disk.
+    pub async fn encrypt_file(
+        &self,
+        source: &Path,
+        destination: &Path,
+        aad: &[u8],
+    ) -> Result<(), AesError> {
+        let mut reader = File::open(source).await?;
+        let metadata = reader.metadata().await?;
+
+        let mut output = AtomicFile::create(destination).await?;
+        self.encrypt_stream(&mut reader, output.file(), aad).await?;
+        output.commit(Some(&metadata)).await?;
+        Ok(())
+    }
+
+    /// Encrypts everything `reader` yields into `writer` without buffering the whole
+    /// message. The output is identical to `encrypt_with_aad` over the same bytes.
+    pub async fn encrypt_stream<R, W>(
+        &self,
+        reader: &mut R,
+        writer: &mut W,
+        aad: &[u8],
+    ) -> Result<(), AesError>
     where
-        C: Aead + KeyInit + Send + Sync + 'static,
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
     {
-        if self.base.nonce.len() != KEY_LENGTH {
-            return Err(AesError::InvalidKeyLength);
+        let suite = self.base.suite;
+        self.base.validate_key(suite)?;
+
+        let header = self.new_header();
+        let header_bytes = header.encode()?;
+        writer.write_all(&header_bytes).await?;
+
+        let associated_data = EnvelopeHeader::associated_data(&header_bytes, aad);
+        let stream = Stream::new(&header.nonce_prefix, &associated_data, DEFAULT_CHUNK_SIZE);
+        match &self.signer {
+            Some(signer) => {
+                let mut digest = signer.algorithm().new_digest();
+                digest.update(&header_bytes);
+                let mut reader = SignedReader::new(reader, signer, digest);
+                self.seal_chunks(&mut reader, writer, &stream).await?;
+            }
+            None => self.seal_chunks(reader, writer, &stream).await?,
         }
 
-        let cipher = Arc::new(C::new_from_slice(&self.base.key).map_err(AesError::from)?);
+        writer.flush().await?;
+        Ok(())
+    }
 
-        let nonce_generic = GenericArray::<u8, U12>::from_slice(&self.base.nonce);
-        let nonce = Nonce::from_slice(nonce_generic);
+    /// Seals everything `reader` yields as STREAM chunks, one batch at a time.
+    async fn seal_chunks<R, W>(
+        &self,
+        reader: &mut R,
+        writer: &mut W,
+        stream: &Stream<'_>,
+    ) -> Result<(), AesError>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let suite = self.base.suite;
+        let batch_size = self.base.batch_size();
+        let mut chunks = ChunkReader::new(reader, DEFAULT_CHUNK_SIZE);
+        let mut index = 0u32;
 
-        if cfg!(feature = "development") {
-            debug!(
-                "[Encryptor] Encrypting data with \nkey: {:?} \nnonce: {:?}",
-                self.base.key, self.base.nonce
-            );
+        loop {
+            let (batch, is_final) = chunks.next_batch(batch_size).await?;
+            let sealed = suite.seal_batch(
+                &self.base.key,
+                stream,
+                index,
+                &batch,
+                is_final,
+                self.base.has_parallel_processing,
+            )?;
+            for chunk in &sealed {
+                writer.write_all(chunk).await?;
+            }
+            if is_final {
+                break;
+            }
+            index = index
+                .checked_add(batch.len() as u32)
+                .ok_or(AesError::StreamTooLong)?;
         }
+        Ok(())
+    }
+
+    /// `data` followed by its signature over `header` and `data` when this encryptor signs.
+    fn sign_payload(&self, header: &[u8], data: &[u8]) -> Result<Option<SecretBytes>, AesError> {
+        let Some(signer) = &self.signer else {
+            return Ok(None);
+        };
+        let mut digest = signer.algorithm().new_digest();
+        digest.update(header);
+        digest.update(data);
+        let signature = signer.sign_digest(digest)?;
+
+        let mut signed = SecretBytes::new(Vec::with_capacity(data.len() + signature.len()));
+        signed.extend_from_slice(data);
+        signed.extend_from_slice(&signature);
+        Ok(Some(signed))
+    }
 
-        if self.base.has_parallel_processing {
-            data.par_chunks(1024)
-                .map_init(
-                    || cipher.clone(),
-                    |cipher, chunk| cipher.encrypt(nonce, chunk).map_err(AesError::from),
-                )
-                .collect::<Result<Vec<_>, _>>()
-                .map(|chunks| chunks.concat())
-        } else {
-            cipher.encrypt(nonce, data).map_err(AesError::from)
+    fn new_header(&self) -> EnvelopeHeader {
+        let suite = self.base.suite;
+        // Every message draws its own nonce prefix; chunk nonces are derived from it.
+        let header = EnvelopeHeader::new(
+            suite,
+            DEFAULT_CHUNK_SIZE as u32,
+            self.base.key_id.to_vec(),
+            generate_nonce_prefix(suite),
+        );
+        let header = match &self.kdf {
+            Some(kdf) => header.with_kdf(KdfParams::clone(kdf)),
+            None => header,
+        };
+        let header = match &self.recipients {
+            Some(recipients) => header.with_recipients(recipients.to_vec()),
+            None => header,
+        };
+        match &self.signer {
+            Some(signer) => header.with_signer(signer.signer_info()),
+            None => header,
         }
     }
 }
@@ -0,0 +1,38 @@
+use crate::actors::encryption::AesError;
+
+#[derive(Debug)]
+pub enum EnvelopeError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownFlags(u8),
+    Truncated,
+    InvalidChunkSize(u32),
+    KeyIdTooLong(usize),
+    KeyIdMismatch,
+}
+
+impl std::fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnvelopeError::BadMagic => write!(f, "not a Mirage envelope"),
+            EnvelopeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported envelope version {}", version)
+            }
+            EnvelopeError::UnknownFlags(flags) => {
+                write!(f, "unknown envelope flags {:#04x}", flags)
+            }
+            EnvelopeError::Truncated => write!(f, "envelope header is truncated"),
+            EnvelopeError::InvalidChunkSize(size) => write!(f, "invalid chunk size {}", size),
+            EnvelopeError::KeyIdTooLong(len) => write!(f, "key id of {} bytes is too long", len),
+            EnvelopeError::KeyIdMismatch => {
+                write!(f, "envelope was sealed under a different key id")
+            }
+        }
+    }
+}
+
+impl From<EnvelopeError> for AesError {
+    fn from(err: EnvelopeError) -> Self {
+        AesError::EnvelopeError(err)
+    }
+}
@@ -0,0 +1,397 @@
+//! Self-describing binary envelope wrapped around every ciphertext.
+//!
+//! All integers are big-endian. Version 1 header layout:
+//!
+//! | size | field                                             |
+//! |------|---------------------------------------------------|
+//! | 4    | magic `MIRG`                                      |
+//! | 1    | format version (`1`)                              |
+//! | 1    | cipher suite id, see `CipherSuite`                |
+//! | 1    | flags, unknown bits are rejected                  |
+//! | 4    | STREAM chunk size in bytes                        |
+//! | 1    | key id length `k`                                 |
+//! | k    | key id, empty when the key is anonymous           |
+//! | 1    | nonce prefix length `n`, must match the suite     |
+//! | n    | STREAM nonce prefix                               |
+//!
+//! When `FLAG_PASSPHRASE` is set, the key was derived from a passphrase and the header
+//! continues with the key derivation block:
+//!
+//! | size | field                                             |
+//! |------|---------------------------------------------------|
+//! | 1    | algorithm id, see `KdfAlgorithm`                  |
+//! | 1    | salt length `s`                                   |
+//! | s    | salt                                              |
+//! | 1    | cost length `c`                                   |
+//! | c    | cost parameters, see `KdfCost::encode`            |
+//!
+//! When `FLAG_RECIPIENTS` is set, the data key is wrapped for RSA recipients and the
+//! header continues with a one-byte recipient count, then for every recipient:
+//!
+//! | size | field                                             |
+//! |------|---------------------------------------------------|
+//! | 32   | SHA-256 fingerprint of the recipient's public key |
+//! | 2    | wrapped key length `w`                            |
+//! | w    | data key wrapped with RSA-OAEP (SHA-256)          |
+//!
+//! When `FLAG_SIGNED` is set, the plaintext is followed by the signer's signature over
+//! the encoded header and the plaintext, and the header ends with the signer block:
+//!
+//! | size | field                                             |
+//! |------|---------------------------------------------------|
+//! | 1    | signature algorithm id, see `SignatureAlgorithm`  |
+//! | 32   | SHA-256 fingerprint of the signer's public key    |
+//! | 2    | signature length                                  |
+//!
+//! The STREAM chunks follow the header directly. The encoded header, followed by any
+//! caller-supplied associated data, is bound to every chunk, so no header field can be
+//! altered without the ciphertext failing authentication. Caller associated data is
+//! never stored in the envelope.
+
+use tokio::io::{self, AsyncRead, AsyncReadExt};
+
+use self::error::EnvelopeError;
+
+use crate::actors::signing::{SignatureAlgorithm, SignerInfo};
+
+use super::kdf::{KdfAlgorithm, KdfCost, KdfParams};
+use super::recipients::{error::RecipientError, WrappedKey, FINGERPRINT_LENGTH};
+use super::stream::MAX_CHUNK_SIZE;
+use super::suite::CipherSuite;
+use super::AesError;
+
+pub mod error;
+
+pub const MAGIC: [u8; 4] = *b"MIRG";
+pub const VERSION_1: u8 = 1;
+pub const CURRENT_VERSION: u8 = VERSION_1;
+
+/// The key was derived from a passphrase; a key derivation block follows the prefix.
+pub const FLAG_PASSPHRASE: u8 = 0x01;
+
+/// The data key is wrapped for RSA recipients; a recipients block follows.
+pub const FLAG_RECIPIENTS: u8 = 0x02;
+
+/// The plaintext is signed; a signer block follows and a signature trails the plaintext.
+pub const FLAG_SIGNED: u8 = 0x04;
+
+/// Flag bits understood by this release.
+pub const KNOWN_FLAGS: u8 = FLAG_PASSPHRASE | FLAG_RECIPIENTS | FLAG_SIGNED;
+
+/// Length of the signer block.
+const SIGNER_LENGTH: usize = 1 + FINGERPRINT_LENGTH + 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnvelopeHeader {
+    pub version: u8,
+    pub suite: CipherSuite,
+    pub flags: u8,
+    pub chunk_size: u32,
+    pub key_id: Vec<u8>,
+    pub nonce_prefix: Vec<u8>,
+    /// Present exactly when `FLAG_PASSPHRASE` is set.
+    pub kdf: Option<KdfParams>,
+    /// Non-empty exactly when `FLAG_RECIPIENTS` is set.
+    pub recipients: Vec<WrappedKey>,
+    /// Present exactly when `FLAG_SIGNED` is set.
+    pub signer: Option<SignerInfo>,
+}
+
+impl EnvelopeHeader {
+    pub fn new(
+        suite: CipherSuite,
+        chunk_size: u32,
+        key_id: Vec<u8>,
+        nonce_prefix: Vec<u8>,
+    ) -> Self {
+        EnvelopeHeader {
+            version: CURRENT_VERSION,
+            suite,
+            flags: 0,
+            chunk_size,
+            key_id,
+            nonce_prefix,
+            kdf: None,
+            recipients: Vec::new(),
+            signer: None,
+        }
+    }
+
+    /// Records the parameters the key was derived with.
+    pub fn with_kdf(mut self, kdf: KdfParams) -> Self {
+        self.flags |= FLAG_PASSPHRASE;
+        self.kdf = Some(kdf);
+        self
+    }
+
+    /// Records the data key wrapped for every recipient.
+    pub fn with_recipients(mut self, recipients: Vec<WrappedKey>) -> Self {
+        self.flags |= FLAG_RECIPIENTS;
+        self.recipients = recipients;
+        self
+    }
+
+    /// Records who signs the plaintext.
+    pub fn with_signer(mut self, signer: SignerInfo) -> Self {
+        self.flags |= FLAG_SIGNED;
+        self.signer = Some(signer);
+        self
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, AesError> {
+        let key_id_length = u8::try_from(self.key_id.len())
+            .map_err(|_| EnvelopeError::KeyIdTooLong(self.key_id.len()))?;
+
+        let mut header = Vec::with_capacity(13 + self.key_id.len() + self.nonce_prefix.len());
+        header.extend_from_slice(&MAGIC);
+        header.push(self.version);
+        header.push(self.suite.id());
+        header.push(self.flags);
+        header.extend_from_slice(&self.chunk_size.to_be_bytes());
+        header.push(key_id_length);
+        header.extend_from_slice(&self.key_id);
+        header.push(self.nonce_prefix.len() as u8);
+        header.extend_from_slice(&self.nonce_prefix);
+
+        if let Some(kdf) = &self.kdf {
+            KdfParams::check_salt(&kdf.salt)?;
+            let cost = kdf.cost.encode();
+            header.push(kdf.cost.algorithm().id());
+            header.push(kdf.salt.len() as u8);
+            header.extend_from_slice(&kdf.salt);
+            header.push(cost.len() as u8);
+            header.extend_from_slice(&cost);
+        }
+
+        if self.flags & FLAG_RECIPIENTS != 0 {
+            let count = u8::try_from(self.recipients.len())
+                .map_err(|_| RecipientError::TooManyRecipients(self.recipients.len()))?;
+            header.push(count);
+            for recipient in &self.recipients {
+                let wrapped_length = u16::try_from(recipient.wrapped.len()).map_err(|_| {
+                    RecipientError::WrapFailed("wrapped key is too long".to_string())
+                })?;
+                header.extend_from_slice(&recipient.fingerprint);
+                header.extend_from_slice(&wrapped_length.to_be_bytes());
+                header.extend_from_slice(&recipient.wrapped);
+            }
+        }
+
+        if let Some(signer) = &self.signer {
+            header.push(signer.algorithm.id());
+            header.extend_from_slice(&signer.fingerprint);
+            header.extend_from_slice(&signer.signature_length.to_be_bytes());
+        }
+        Ok(header)
+    }
+
+    /// Associated data authenticated by every chunk: the encoded header followed by the
+    /// caller's `aad`. The header is self-delimiting, so the concatenation is unambiguous.
+    pub fn associated_data(header: &[u8], aad: &[u8]) -> Vec<u8> {
+        let mut associated_data = Vec::with_capacity(header.len() + aad.len());
+        associated_data.extend_from_slice(header);
+        associated_data.extend_from_slice(aad);
+        associated_data
+    }
+
+    /// Parses and validates a header, returning it with the number of bytes it occupies.
+    pub fn parse(data: &[u8]) -> Result<(Self, usize), AesError> {
+        let mut reader = Reader::new(data);
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(EnvelopeError::BadMagic.into());
+        }
+
+        let version = reader.u8()?;
+        if version != VERSION_1 {
+            return Err(EnvelopeError::UnsupportedVersion(version).into());
+        }
+
+        let suite = CipherSuite::from_id(reader.u8()?)?;
+
+        let flags = reader.u8()?;
+        if flags & !KNOWN_FLAGS != 0 {
+            return Err(EnvelopeError::UnknownFlags(flags & !KNOWN_FLAGS).into());
+        }
+
+        let chunk_size = reader.u32()?;
+        if chunk_size == 0 || chunk_size as usize > MAX_CHUNK_SIZE {
+            return Err(EnvelopeError::InvalidChunkSize(chunk_size).into());
+        }
+
+        let key_id_length = reader.u8()? as usize;
+        let key_id = reader.take(key_id_length)?.to_vec();
+
+        let nonce_prefix_length = reader.u8()? as usize;
+        if nonce_prefix_length != suite.nonce_prefix_length() {
+            return Err(AesError::InvalidNonceLength {
+                expected: suite.nonce_prefix_length(),
+                actual: nonce_prefix_length,
+            });
+        }
+        let nonce_prefix = reader.take(nonce_prefix_length)?.to_vec();
+
+        let kdf = if flags & FLAG_PASSPHRASE != 0 {
+            let algorithm = KdfAlgorithm::from_id(reader.u8()?)?;
+            let salt_length = reader.u8()? as usize;
+            let salt = reader.take(salt_length)?.to_vec();
+            KdfParams::check_salt(&salt)?;
+            let cost_length = reader.u8()? as usize;
+            let cost = KdfCost::decode(algorithm, reader.take(cost_length)?)?;
+            Some(KdfParams { cost, salt })
+        } else {
+            None
+        };
+
+        let mut recipients = Vec::new();
+        if flags & FLAG_RECIPIENTS != 0 {
+            let count = reader.u8()?;
+            if count == 0 {
+                return Err(RecipientError::NoRecipients.into());
+            }
+            for _ in 0..count {
+                let fingerprint = reader.take(FINGERPRINT_LENGTH)?.try_into().unwrap();
+                let wrapped_length = reader.u16()? as usize;
+                let wrapped = reader.take(wrapped_length)?.to_vec();
+                recipients.push(WrappedKey {
+                    fingerprint,
+                    wrapped,
+                });
+            }
+        }
+
+        let signer = if flags & FLAG_SIGNED != 0 {
+            Some(SignerInfo {
+                algorithm: SignatureAlgorithm::from_id(reader.u8()?)?,
+                fingerprint: reader.take(FINGERPRINT_LENGTH)?.try_into().unwrap(),
+                signature_length: reader.u16()?,
+            })
+        } else {
+            None
+        };
+
+        let header = EnvelopeHeader {
+            version,
+            suite,
+            flags,
+            chunk_size,
+            key_id,
+            nonce_prefix,
+            kdf,
+            recipients,
+            signer,
+        };
+        Ok((header, reader.position))
+    }
+
+    /// Reads a header from the front of `reader`, returning it with its encoded bytes.
+    /// Stops right after the header, so the STREAM chunks can be read from `reader` next.
+    pub async fn read_from<R: AsyncRead + Unpin>(
+        reader: &mut R,
+    ) -> Result<(Self, Vec<u8>), AesError> {
+        // Magic and version first, so a future format is reported as such even when the
+        // rest of its header is laid out differently.
+        let mut header = vec![0u8; MAGIC.len() + 1];
+        read_exact(reader, &mut header).await?;
+        if header[..MAGIC.len()] != MAGIC {
+            return Err(EnvelopeError::BadMagic.into());
+        }
+        let version = header[MAGIC.len()];
+        if version != VERSION_1 {
+            return Err(EnvelopeError::UnsupportedVersion(version).into());
+        }
+
+        // Suite, flags, chunk size and the key id length.
+        read_exact(reader, extend(&mut header, 7)).await?;
+
+        // The key id is followed by the nonce prefix length.
+        let variable_length = header[11] as usize + 1;
+        read_exact(reader, extend(&mut header, variable_length)).await?;
+
+        let nonce_prefix_length = header[header.len() - 1] as usize;
+        read_exact(reader, extend(&mut header, nonce_prefix_length)).await?;
+
+        if header[6] & FLAG_PASSPHRASE != 0 {
+            // Algorithm id and salt length, then the salt and the cost length, then the cost.
+            read_exact(reader, extend(&mut header, 2)).await?;
+            let salt_length = header[header.len() - 1] as usize;
+            read_exact(reader, extend(&mut header, salt_length + 1)).await?;
+            let cost_length = header[header.len() - 1] as usize;
+            read_exact(reader, extend(&mut header, cost_length)).await?;
+        }
+
+        if header[6] & FLAG_RECIPIENTS != 0 {
+            read_exact(reader, extend(&mut header, 1)).await?;
+            let count = header[header.len() - 1];
+            for _ in 0..count {
+                read_exact(reader, extend(&mut header, FINGERPRINT_LENGTH + 2)).await?;
+                let length = &header[header.len() - 2..];
+                let wrapped_length = u16::from_be_bytes([length[0], length[1]]) as usize;
+                read_exact(reader, extend(&mut header, wrapped_length)).await?;
+            }
+        }
+
+        if header[6] & FLAG_SIGNED != 0 {
+            read_exact(reader, extend(&mut header, SIGNER_LENGTH)).await?;
+        }
+
+        let (parsed, _) = Self::parse(&header)?;
+        Ok((parsed, header))
+    }
+}
+
+fn extend(buffer: &mut Vec<u8>, length: usize) -> &mut [u8] {
+    let start = buffer.len();
+    buffer.resize(start + length, 0);
+    &mut buffer[start..]
+}
+
+async fn read_exact<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buffer: &mut [u8],
+) -> Result<(), AesError> {
+    match reader.read_exact(buffer).await {
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+            Err(EnvelopeError::Truncated.into())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Bounds-checked cursor over header bytes.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, position: 0 }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], EnvelopeError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|&end| end <= self.data.len())
+            .ok_or(EnvelopeError::Truncated)?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, EnvelopeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, EnvelopeError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, EnvelopeError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
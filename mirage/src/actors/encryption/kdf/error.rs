@@ -0,0 +1,36 @@
+use crate::actors::encryption::AesError;
+
+#[derive(Debug)]
+pub enum KdfError {
+    UnsupportedAlgorithm(u8),
+    InvalidParameters(String),
+    InvalidSalt(usize),
+    MissingParameters,
+    DerivationFailed(String),
+}
+
+impl std::fmt::Display for KdfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KdfError::UnsupportedAlgorithm(id) => {
+                write!(f, "unsupported key derivation function id {}", id)
+            }
+            KdfError::InvalidParameters(reason) => {
+                write!(f, "invalid key derivation parameters: {}", reason)
+            }
+            KdfError::InvalidSalt(len) => write!(f, "invalid salt length {}", len),
+            KdfError::MissingParameters => {
+                write!(f, "envelope was not sealed with a passphrase")
+            }
+            KdfError::DerivationFailed(reason) => {
+                write!(f, "key derivation failed: {}", reason)
+            }
+        }
+    }
+}
+
+impl From<KdfError> for AesError {
+    fn from(err: KdfError) -> Self {
+        AesError::KdfError(err)
+    }
+}
@@ -0,0 +1,319 @@
+//! Passphrase-based key derivation.
+//!
+//! Keys are derived with Argon2id, or scrypt where Argon2id is not wanted. The salt and
+//! cost parameters travel in the envelope header (see `envelope`), so a passphrase is all
+//! a `Decryptor` needs. Costs are calibrated to a target unlock time on the encrypting
+//! machine but never drop below the floors defined here.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use argon2::{Algorithm, Argon2, Version};
+use serde::{Deserialize, Serialize};
+
+use crate::tools::config::Config;
+use crate::utils::key_generator::generate_salt;
+use crate::utils::secret::{SecretBytes, SecretKey};
+
+use self::error::KdfError;
+
+use super::AesError;
+
+pub mod error;
+
+/// Length of the random salt drawn for every new set of parameters.
+pub const SALT_LENGTH: usize = 16;
+pub const MIN_SALT_LENGTH: usize = 8;
+pub const MAX_SALT_LENGTH: usize = 64;
+
+/// Unlock time aimed for when `Parameters` does not set one.
+pub const DEFAULT_UNLOCK_TIME: Duration = Duration::from_secs(1);
+
+/// Argon2id memory cost, RFC 9106's second recommended option (64 MiB).
+const ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+const ARGON2_MIN_ITERATIONS: u32 = 3;
+const SCRYPT_MIN_LOG_N: u8 = 17;
+const SCRYPT_MAX_CALIBRATED_LOG_N: u8 = 22;
+const SCRYPT_R: u32 = 8;
+
+/// Upper bounds for parameters read from an envelope, so a crafted header cannot make
+/// the decryptor allocate or spin without limit.
+const MAX_MEMORY_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+const MAX_ITERATIONS: u32 = 1024;
+const MAX_PARALLELISM: u32 = 64;
+
+/// Unlock time configured in `Parameters`, `DEFAULT_UNLOCK_TIME` when unset.
+pub fn unlock_time() -> Duration {
+    Config::get_parameters()
+        .kdf_unlock_time_ms
+        .map_or(DEFAULT_UNLOCK_TIME, Duration::from_millis)
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum KdfAlgorithm {
+    Argon2id = 1,
+    /// Fallback for environments where Argon2id is not available or not approved.
+    Scrypt = 2,
+}
+
+impl KdfAlgorithm {
+    /// Algorithm selected by `Parameters`, Argon2id unless configured otherwise.
+    pub fn from_config() -> Self {
+        Config::get_parameters()
+            .key_derivation
+            .unwrap_or(KdfAlgorithm::Argon2id)
+    }
+
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_id(id: u8) -> Result<Self, KdfError> {
+        match id {
+            1 => Ok(KdfAlgorithm::Argon2id),
+            2 => Ok(KdfAlgorithm::Scrypt),
+            _ => Err(KdfError::UnsupportedAlgorithm(id)),
+        }
+    }
+}
+
+/// Cost parameters of one derivation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KdfCost {
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+    Scrypt {
+        log_n: u8,
+        r: u32,
+        p: u32,
+    },
+}
+
+impl KdfCost {
+    pub fn algorithm(&self) -> KdfAlgorithm {
+        match self {
+            KdfCost::Argon2id { .. } => KdfAlgorithm::Argon2id,
+            KdfCost::Scrypt { .. } => KdfAlgorithm::Scrypt,
+        }
+    }
+
+    /// Picks the cost for `algorithm` whose derivation takes about `target` on this
+    /// machine. Runs one derivation at the minimum cost and scales from its duration.
+    pub fn calibrate(algorithm: KdfAlgorithm, target: Duration) -> Result<Self, AesError> {
+        let floor = match algorithm {
+            KdfAlgorithm::Argon2id => KdfCost::Argon2id {
+                memory_kib: ARGON2_MEMORY_KIB,
+                iterations: ARGON2_MIN_ITERATIONS,
+                parallelism: 1,
+            },
+            KdfAlgorithm::Scrypt => KdfCost::Scrypt {
+                log_n: SCRYPT_MIN_LOG_N,
+                r: SCRYPT_R,
+                p: 1,
+            },
+        };
+
+        let probe = KdfParams {
+            cost: floor,
+            salt: vec![0; SALT_LENGTH],
+        };
+        let started = Instant::now();
+        probe.derive(b"calibration", 32)?;
+        let ratio = target.as_secs_f64() / started.elapsed().as_secs_f64().max(1e-6);
+
+        Ok(match floor {
+            // Time grows linearly with the number of passes.
+            KdfCost::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => KdfCost::Argon2id {
+                memory_kib,
+                iterations: ((iterations as f64 * ratio) as u32)
+                    .clamp(ARGON2_MIN_ITERATIONS, MAX_ITERATIONS),
+                parallelism,
+            },
+            // Time doubles with every step of `log_n`.
+            KdfCost::Scrypt { log_n, r, p } => KdfCost::Scrypt {
+                log_n: (log_n as f64 + ratio.log2().floor())
+                    .clamp(SCRYPT_MIN_LOG_N as f64, SCRYPT_MAX_CALIBRATED_LOG_N as f64)
+                    as u8,
+                r,
+                p,
+            },
+        })
+    }
+
+    /// Calibrates the configured algorithm to the configured unlock time.
+    pub fn from_config() -> Result<Self, AesError> {
+        Self::calibrate(KdfAlgorithm::from_config(), unlock_time())
+    }
+
+    /// Serialized cost as stored in the envelope, without the algorithm id.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(12);
+        match *self {
+            KdfCost::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                encoded.extend_from_slice(&memory_kib.to_be_bytes());
+                encoded.extend_from_slice(&iterations.to_be_bytes());
+                encoded.extend_from_slice(&parallelism.to_be_bytes());
+            }
+            KdfCost::Scrypt { log_n, r, p } => {
+                encoded.push(log_n);
+                encoded.extend_from_slice(&r.to_be_bytes());
+                encoded.extend_from_slice(&p.to_be_bytes());
+            }
+        }
+        encoded
+    }
+
+    /// Parses and bounds-checks a cost read from an envelope.
+    pub fn decode(algorithm: KdfAlgorithm, encoded: &[u8]) -> Result<Self, KdfError> {
+        let u32_at = |offset: usize| {
+            let bytes: [u8; 4] = encoded[offset..offset + 4].try_into().unwrap();
+            u32::from_be_bytes(bytes)
+        };
+
+        let cost = match (algorithm, encoded.len()) {
+            (KdfAlgorithm::Argon2id, 12) => KdfCost::Argon2id {
+                memory_kib: u32_at(0),
+                iterations: u32_at(4),
+                parallelism: u32_at(8),
+            },
+            (KdfAlgorithm::Scrypt, 9) => KdfCost::Scrypt {
+                log_n: encoded[0],
+                r: u32_at(1),
+                p: u32_at(5),
+            },
+            (_, length) => {
+                return Err(KdfError::InvalidParameters(format!(
+                    "{} bytes of parameters for {:?}",
+                    length, algorithm
+                )))
+            }
+        };
+        cost.check_limits()?;
+        Ok(cost)
+    }
+
+    fn check_limits(&self) -> Result<(), KdfError> {
+        let (memory, iterations, parallelism) = match *self {
+            KdfCost::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => (u64::from(memory_kib) * 1024, iterations, parallelism),
+            KdfCost::Scrypt { log_n, r, p } => {
+                let memory = 1u64
+                    .checked_shl(u32::from(log_n))
+                    .and_then(|n| n.checked_mul(128 * u64::from(r)))
+                    .unwrap_or(u64::MAX);
+                (memory, 1, p)
+            }
+        };
+
+        if memory > MAX_MEMORY_BYTES {
+            return Err(KdfError::InvalidParameters(format!(
+                "{} bytes of memory exceeds the limit",
+                memory
+            )));
+        }
+        if iterations > MAX_ITERATIONS || parallelism > MAX_PARALLELISM {
+            return Err(KdfError::InvalidParameters(
+                "iteration or parallelism cost exceeds the limit".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Everything needed to re-derive a key from its passphrase.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KdfParams {
+    pub cost: KdfCost,
+    pub salt: Vec<u8>,
+}
+
+impl KdfParams {
+    /// Pairs `cost` with a fresh random salt.
+    pub fn new(cost: KdfCost) -> Self {
+        KdfParams {
+            cost,
+            salt: generate_salt(SALT_LENGTH),
+        }
+    }
+
+    pub fn check_salt(salt: &[u8]) -> Result<(), KdfError> {
+        if !(MIN_SALT_LENGTH..=MAX_SALT_LENGTH).contains(&salt.len()) {
+            return Err(KdfError::InvalidSalt(salt.len()));
+        }
+        Ok(())
+    }
+
+    /// Derives a `key_length` byte key from `passphrase`.
+    pub fn derive(&self, passphrase: &[u8], key_length: usize) -> Result<SecretKey, AesError> {
+        Self::check_salt(&self.salt)?;
+        let mut key = SecretKey::zeroed(key_length);
+
+        match self.cost {
+            KdfCost::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params =
+                    argon2::Params::new(memory_kib, iterations, parallelism, Some(key_length))
+                        .map_err(|e| KdfError::InvalidParameters(e.to_string()))?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(passphrase, &self.salt, key.as_mut_bytes())
+                    .map_err(|e| KdfError::DerivationFailed(e.to_string()))?;
+            }
+            KdfCost::Scrypt { log_n, r, p } => {
+                let params = scrypt::Params::new(log_n, r, p, key_length)
+                    .map_err(|e| KdfError::InvalidParameters(e.to_string()))?;
+                scrypt::scrypt(passphrase, &self.salt, &params, key.as_mut_bytes())
+                    .map_err(|e| KdfError::DerivationFailed(e.to_string()))?;
+            }
+        }
+
+        Ok(key)
+    }
+}
+
+/// A passphrase held by a `Decryptor`. Remembers the last derived key, since every
+/// envelope written by one `Encryptor` carries the same salt and cost.
+pub struct Passphrase {
+    passphrase: SecretBytes,
+    last_key: Mutex<Option<(KdfParams, Arc<SecretKey>)>>,
+}
+
+impl Passphrase {
+    pub fn new(passphrase: Vec<u8>) -> Self {
+        Passphrase {
+            passphrase: SecretBytes::new(passphrase),
+            last_key: Mutex::new(None),
+        }
+    }
+
+    /// Key for `params`, derived on first use.
+    pub fn key(&self, params: &KdfParams, key_length: usize) -> Result<Arc<SecretKey>, AesError> {
+        let mut last_key = self.last_key.lock().unwrap();
+        if let Some((last_params, key)) = last_key.as_ref() {
+            if last_params == params && key.len() == key_length {
+                return Ok(Arc::clone(key));
+            }
+        }
+
+        let key = Arc::new(params.derive(&self.passphrase, key_length)?);
+        *last_key = Some((params.clone(), Arc::clone(&key)));
+        Ok(key)
+    }
+}
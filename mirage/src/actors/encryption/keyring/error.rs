@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+use crate::actors::encryption::AesError;
+
+#[derive(Debug)]
+pub enum KeyringError {
+    AlreadyExists(PathBuf),
+    /// Wrong passphrase, or the keyring file was altered.
+    UnlockFailed,
+    Corrupt(String),
+    UnknownKey(String),
+    /// The key was destroyed; whatever it encrypted can no longer be decrypted.
+    KeyDestroyed(String),
+    NoActiveKey(String),
+    /// `generate` was called for a name that already has an active key; use `rotate`.
+    NameTaken(String),
+    /// An envelope names a key of another cipher suite than the one it was sealed with.
+    SuiteMismatch(String),
+}
+
+impl std::fmt::Display for KeyringError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyringError::AlreadyExists(path) => {
+                write!(f, "keyring '{}' already exists", path.display())
+            }
+            KeyringError::UnlockFailed => {
+                write!(
+                    f,
+                    "failed to unlock the keyring, the passphrase may be wrong"
+                )
+            }
+            KeyringError::Corrupt(reason) => write!(f, "corrupt keyring: {}", reason),
+            KeyringError::UnknownKey(id) => write!(f, "no key with id '{}'", id),
+            KeyringError::KeyDestroyed(id) => write!(f, "key '{}' was destroyed", id),
+            KeyringError::NoActiveKey(name) => write!(f, "no active key named '{}'", name),
+            KeyringError::NameTaken(name) => {
+                write!(f, "an active key named '{}' already exists", name)
+            }
+            KeyringError::SuiteMismatch(id) => {
+                write!(f, "key '{}' belongs to another cipher suite", id)
+            }
+        }
+    }
+}
+
+impl From<KeyringError> for AesError {
+    fn from(err: KeyringError) -> Self {
+        AesError::KeyringError(err)
+    }
+}
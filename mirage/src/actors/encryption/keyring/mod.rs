@@ -0,0 +1,417 @@
+//! Named encryption keys with their metadata, persisted encrypted on disk.
+//!
+//! A keyring holds every version of every named key. Each version has its own id, which
+//! `Encryptor::from_keyring` records in the envelope header and `Decryptor::with_keyring`
+//! looks up again, so envelopes keep opening after their key was rotated. At most one
+//! version per name is `Active`; rotated versions become `DecryptOnly`.
+//!
+//! The keyring file is itself an envelope (see `envelope`) sealed under a key derived
+//! from the keyring passphrase. Its plaintext is JSON and never touches the disk.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use rand::{rngs::OsRng, Rng};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::tools::config::EncryptionLevel;
+use crate::utils::file_system::AtomicFile;
+use crate::utils::key_generator::{generate_key, generate_nonce_prefix};
+use crate::utils::secret::{SecretBytes, SecretKey};
+
+use self::error::KeyringError;
+
+use super::envelope::EnvelopeHeader;
+use super::kdf::{error::KdfError, KdfCost, KdfParams};
+use super::stream::{Stream, DEFAULT_CHUNK_SIZE};
+use super::suite::CipherSuite;
+use super::AesError;
+
+pub mod error;
+
+/// Suite the keyring file is sealed with.
+const KEYRING_SUITE: CipherSuite = CipherSuite::XChaCha20Poly1305;
+
+/// Associated data binding the sealed file to its purpose.
+const KEYRING_CONTEXT: &[u8] = b"mirage keyring";
+
+const KEYRING_VERSION: u8 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyStatus {
+    /// Used for new envelopes and accepted for decryption.
+    Active,
+    /// Only accepted for decryption, typically after a rotation.
+    DecryptOnly,
+    /// Key material erased; the metadata is kept so lookups fail with a clear error.
+    Destroyed,
+}
+
+/// Everything about a key except the key itself.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct KeyMetadata {
+    /// Unique per version, recorded in the header of every envelope sealed with the key.
+    pub id: String,
+    pub name: String,
+    pub suite: CipherSuite,
+    pub level: EncryptionLevel,
+    pub created_at: SystemTime,
+    pub status: KeyStatus,
+    /// Age after which the key is due for rotation; never when unset.
+    pub rotation_period: Option<Duration>,
+}
+
+impl KeyMetadata {
+    /// True when the key is active and older than its rotation period at `now`.
+    pub fn rotation_due(&self, now: SystemTime) -> bool {
+        match self.rotation_period {
+            Some(period) if self.status == KeyStatus::Active => {
+                now.duration_since(self.created_at).unwrap_or_default() >= period
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct KeyEntry {
+    #[serde(flatten)]
+    metadata: KeyMetadata,
+    /// Empty once the key was destroyed.
+    key: SecretKey,
+}
+
+impl KeyEntry {
+    fn new(
+        name: &str,
+        suite: CipherSuite,
+        level: EncryptionLevel,
+        rotation_period: Option<Duration>,
+    ) -> Self {
+        KeyEntry {
+            metadata: KeyMetadata {
+                id: format!("{:032x}", OsRng.gen::<u128>()),
+                name: name.to_string(),
+                suite,
+                level,
+                created_at: SystemTime::now(),
+                status: KeyStatus::Active,
+                rotation_period,
+            },
+            key: generate_key(suite),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyringFile {
+    version: u8,
+    keys: Vec<KeyEntry>,
+}
+
+struct Shared {
+    path: PathBuf,
+    kdf: KdfParams,
+    /// Key derived from the passphrase, kept so saving does not rerun the KDF.
+    file_key: SecretKey,
+    keys: RwLock<Vec<KeyEntry>>,
+    /// Serializes updates so the file always holds the latest state.
+    writer: tokio::sync::Mutex<()>,
+}
+
+/// Handle to an unlocked keyring. Clones share the same keys, so a `Decryptor` built
+/// from a keyring sees keys rotated or destroyed later on.
+///
+/// Every change is written to disk before the call returns; if writing fails the
+/// change is dropped.
+#[derive(Clone)]
+pub struct Keyring {
+    inner: Arc<Shared>,
+}
+
+impl Keyring {
+    /// Creates an empty keyring at `path`, with the key derivation function calibrated
+    /// to the configured unlock time. Fails if `path` exists.
+    pub async fn create(path: impl AsRef<Path>, passphrase: &[u8]) -> Result<Self, AesError> {
+        let params = KdfParams::new(KdfCost::from_config()?);
+        Self::create_with_kdf(path, passphrase, params).await
+    }
+
+    /// Creates an empty keyring at `path` whose file key is derived with `params`.
+    pub async fn create_with_kdf(
+        path: impl AsRef<Path>,
+        passphrase: &[u8],
+        params: KdfParams,
+    ) -> Result<Self, AesError> {
+        let path = path.as_ref().to_path_buf();
+        if tokio::fs::try_exists(&path).await? {
+            return Err(KeyringError::AlreadyExists(path).into());
+        }
+
+        let file_key = params.derive(passphrase, KEYRING_SUITE.key_length())?;
+        let keyring = Keyring {
+            inner: Arc::new(Shared {
+                path: path.clone(),
+                kdf: params,
+                file_key,
+                keys: RwLock::new(Vec::new()),
+                writer: tokio::sync::Mutex::new(()),
+            }),
+        };
+
+        // The check above only saves the key derivation; this one settles races.
+        let mut output = AtomicFile::create(&path).await?;
+        output.file().write_all(&keyring.seal(&[])?).await?;
+        output.commit_new().await.map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => KeyringError::AlreadyExists(path).into(),
+            _ => AesError::from(e),
+        })?;
+        Ok(keyring)
+    }
+
+    /// Unlocks the keyring at `path`.
+    pub async fn open(path: impl AsRef<Path>, passphrase: &[u8]) -> Result<Self, AesError> {
+        let path = path.as_ref().to_path_buf();
+        let data = tokio::fs::read(&path).await?;
+        let (header, header_length) = EnvelopeHeader::parse(&data)?;
+        if header.suite != KEYRING_SUITE {
+            return Err(KeyringError::Corrupt(format!("unexpected suite {}", header.suite)).into());
+        }
+        let params = header.kdf.clone().ok_or(KdfError::MissingParameters)?;
+        let file_key = params.derive(passphrase, KEYRING_SUITE.key_length())?;
+
+        let (header_bytes, body) = data.split_at(header_length);
+        let associated_data = EnvelopeHeader::associated_data(header_bytes, KEYRING_CONTEXT);
+        let stream = Stream::new(
+            &header.nonce_prefix,
+            &associated_data,
+            header.chunk_size as usize,
+        );
+        let plaintext = KEYRING_SUITE
+            .open(&file_key, &stream, body, false)
+            .map_err(|_| KeyringError::UnlockFailed)?;
+
+        let file: KeyringFile =
+            serde_json::from_slice(&plaintext).map_err(|e| KeyringError::Corrupt(e.to_string()))?;
+        if file.version != KEYRING_VERSION {
+            return Err(
+                KeyringError::Corrupt(format!("unsupported version {}", file.version)).into(),
+            );
+        }
+
+        Ok(Keyring {
+            inner: Arc::new(Shared {
+                path,
+                kdf: params,
+                file_key,
+                keys: RwLock::new(file.keys),
+                writer: tokio::sync::Mutex::new(()),
+            }),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+
+    /// Metadata of every key, in creation order.
+    pub fn keys(&self) -> Vec<KeyMetadata> {
+        let keys = self.inner.keys.read().unwrap();
+        keys.iter().map(|entry| entry.metadata.clone()).collect()
+    }
+
+    pub fn metadata(&self, id: &str) -> Option<KeyMetadata> {
+        let keys = self.inner.keys.read().unwrap();
+        keys.iter()
+            .find(|entry| entry.metadata.id == id)
+            .map(|entry| entry.metadata.clone())
+    }
+
+    /// The version of `name` new envelopes are sealed with.
+    pub fn active(&self, name: &str) -> Option<KeyMetadata> {
+        let keys = self.inner.keys.read().unwrap();
+        find_active(&keys, name).map(|entry| entry.metadata.clone())
+    }
+
+    /// Active keys whose rotation period has elapsed at `now`.
+    pub fn due_for_rotation(&self, now: SystemTime) -> Vec<KeyMetadata> {
+        let keys = self.inner.keys.read().unwrap();
+        keys.iter()
+            .filter(|entry| entry.metadata.rotation_due(now))
+            .map(|entry| entry.metadata.clone())
+            .collect()
+    }
+
+    /// Adds a new key called `name` for the suite behind `level`.
+    pub async fn generate(
+        &self,
+        name: &str,
+        level: EncryptionLevel,
+        rotation_period: Option<Duration>,
+    ) -> Result<KeyMetadata, AesError> {
+        self.generate_with_suite(name, level.into(), rotation_period)
+            .await
+    }
+
+    /// Adds a new key called `name` for an explicit `CipherSuite`.
+    pub async fn generate_with_suite(
+        &self,
+        name: &str,
+        suite: CipherSuite,
+        rotation_period: Option<Duration>,
+    ) -> Result<KeyMetadata, AesError> {
+        self.update(|keys| {
+            if find_active(keys, name).is_some() {
+                return Err(KeyringError::NameTaken(name.to_string()).into());
+            }
+            let entry = KeyEntry::new(name, suite, suite.into(), rotation_period);
+            let metadata = entry.metadata.clone();
+            keys.push(entry);
+            Ok(metadata)
+        })
+        .await
+    }
+
+    /// Replaces the active version of `name` with a fresh key of the same suite and
+    /// rotation period. The previous version stays available for decryption.
+    pub async fn rotate(&self, name: &str) -> Result<KeyMetadata, AesError> {
+        self.update(|keys| {
+            let current = keys
+                .iter_mut()
+                .find(|entry| is_active(entry, name))
+                .ok_or_else(|| KeyringError::NoActiveKey(name.to_string()))?;
+            current.metadata.status = KeyStatus::DecryptOnly;
+
+            let entry = KeyEntry::new(
+                name,
+                current.metadata.suite,
+                current.metadata.level,
+                current.metadata.rotation_period,
+            );
+            let metadata = entry.metadata.clone();
+            keys.push(entry);
+            Ok(metadata)
+        })
+        .await
+    }
+
+    /// Stops `id` from sealing new envelopes while still opening old ones.
+    pub async fn retire(&self, id: &str) -> Result<(), AesError> {
+        self.update(|keys| {
+            let entry = find_mut(keys, id)?;
+            if entry.metadata.status == KeyStatus::Destroyed {
+                return Err(KeyringError::KeyDestroyed(id.to_string()).into());
+            }
+            entry.metadata.status = KeyStatus::DecryptOnly;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Erases the key material of `id`. Envelopes sealed with it can no longer be
+    /// decrypted, so this cannot be undone.
+    pub async fn destroy(&self, id: &str) -> Result<(), AesError> {
+        self.update(|keys| {
+            let entry = find_mut(keys, id)?;
+            entry.metadata.status = KeyStatus::Destroyed;
+            entry.key = SecretKey::default();
+            Ok(())
+        })
+        .await
+    }
+
+    /// Metadata and key material of the active version of `name`.
+    pub(crate) fn encryption_key(&self, name: &str) -> Result<(KeyMetadata, SecretKey), AesError> {
+        let keys = self.inner.keys.read().unwrap();
+        let entry =
+            find_active(&keys, name).ok_or_else(|| KeyringError::NoActiveKey(name.to_string()))?;
+        Ok((entry.metadata.clone(), entry.key.clone()))
+    }
+
+    /// Key that opens envelopes sealed under `suite` with key id `id`.
+    pub(crate) fn decryption_key(
+        &self,
+        id: &[u8],
+        suite: CipherSuite,
+    ) -> Result<Arc<SecretKey>, AesError> {
+        let id = String::from_utf8_lossy(id);
+        let keys = self.inner.keys.read().unwrap();
+        let entry = keys
+            .iter()
+            .find(|entry| entry.metadata.id == id)
+            .ok_or_else(|| KeyringError::UnknownKey(id.to_string()))?;
+
+        if entry.metadata.status == KeyStatus::Destroyed {
+            return Err(KeyringError::KeyDestroyed(id.to_string()).into());
+        }
+        if entry.metadata.suite != suite {
+            return Err(KeyringError::SuiteMismatch(id.to_string()).into());
+        }
+        Ok(Arc::new(entry.key.clone()))
+    }
+
+    /// Applies `change` to a copy of the keys, writes the copy to disk and only then
+    /// makes it visible.
+    async fn update<T>(
+        &self,
+        change: impl FnOnce(&mut Vec<KeyEntry>) -> Result<T, AesError>,
+    ) -> Result<T, AesError> {
+        let _writer = self.inner.writer.lock().await;
+        let mut keys = self.inner.keys.read().unwrap().clone();
+        let result = change(&mut keys)?;
+        self.write(&keys).await?;
+        *self.inner.keys.write().unwrap() = keys;
+        Ok(result)
+    }
+
+    async fn write(&self, keys: &[KeyEntry]) -> Result<(), AesError> {
+        let sealed = self.seal(keys)?;
+        let mut output = AtomicFile::create(&self.inner.path).await?;
+        output.file().write_all(&sealed).await?;
+        output.commit(None).await?;
+        Ok(())
+    }
+
+    /// The keyring file holding `keys`, sealed under the file key.
+    fn seal(&self, keys: &[KeyEntry]) -> Result<Vec<u8>, AesError> {
+        let file = KeyringFile {
+            version: KEYRING_VERSION,
+            keys: keys.to_vec(),
+        };
+        let plaintext = serde_json::to_vec(&file)
+            .map(SecretBytes::new)
+            .map_err(|e| AesError::SerializeError(e.to_string()))?;
+
+        let header = EnvelopeHeader::new(
+            KEYRING_SUITE,
+            DEFAULT_CHUNK_SIZE as u32,
+            Vec::new(),
+            generate_nonce_prefix(KEYRING_SUITE),
+        )
+        .with_kdf(self.inner.kdf.clone());
+        let mut sealed = header.encode()?;
+        let associated_data = EnvelopeHeader::associated_data(&sealed, KEYRING_CONTEXT);
+        let stream = Stream::new(&header.nonce_prefix, &associated_data, DEFAULT_CHUNK_SIZE);
+        let body = KEYRING_SUITE.seal(&self.inner.file_key, &stream, &plaintext, false)?;
+        sealed.extend_from_slice(&body);
+        Ok(sealed)
+    }
+}
+
+fn is_active(entry: &KeyEntry, name: &str) -> bool {
+    entry.metadata.name == name && entry.metadata.status == KeyStatus::Active
+}
+
+fn find_active<'a>(keys: &'a [KeyEntry], name: &str) -> Option<&'a KeyEntry> {
+    keys.iter().find(|entry| is_active(entry, name))
+}
+
+fn find_mut<'a>(keys: &'a mut [KeyEntry], id: &str) -> Result<&'a mut KeyEntry, AesError> {
+    keys.iter_mut()
+        .find(|entry| entry.metadata.id == id)
+        .ok_or_else(|| KeyringError::UnknownKey(id.to_string()).into())
+}
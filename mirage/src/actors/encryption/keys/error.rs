@@ -0,0 +1,42 @@
+use crate::actors::encryption::AesError;
+
+#[derive(Debug)]
+pub enum KeyError {
+    InvalidPem(String),
+    UnsupportedKeyType(String),
+    InvalidKey(String),
+    /// RSA modulus in bits, below `MIN_RSA_KEY_BITS`.
+    KeyTooSmall(usize),
+    /// The key is encrypted and no passphrase was given.
+    PassphraseRequired,
+    /// Wrong passphrase or key-encryption key, or the wrapped key was altered.
+    UnwrapFailed,
+    /// A data key is wrapped under another key-encryption key.
+    KekMismatch,
+    EncodingFailed(String),
+}
+
+impl std::fmt::Display for KeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyError::InvalidPem(reason) => write!(f, "invalid PEM: {}", reason),
+            KeyError::UnsupportedKeyType(label) => write!(f, "unsupported key type '{}'", label),
+            KeyError::InvalidKey(reason) => write!(f, "invalid key: {}", reason),
+            KeyError::KeyTooSmall(bits) => write!(f, "RSA key of {} bits is too small", bits),
+            KeyError::PassphraseRequired => write!(f, "key is encrypted, a passphrase is required"),
+            KeyError::UnwrapFailed => {
+                write!(f, "failed to decrypt the key, the passphrase may be wrong")
+            }
+            KeyError::KekMismatch => {
+                write!(f, "data key is wrapped under another key-encryption key")
+            }
+            KeyError::EncodingFailed(reason) => write!(f, "failed to encode the key: {}", reason),
+        }
+    }
+}
+
+impl From<KeyError> for AesError {
+    fn from(err: KeyError) -> Self {
+        AesError::KeyError(err)
+    }
+}
@@ -0,0 +1,94 @@
+//! Key-encryption keys (KEKs) and the data keys (DEKs) wrapped under them.
+//!
+//! Data is sealed with DEKs; only the DEKs are sealed with the KEK. Replacing the KEK
+//! then means re-wrapping a handful of short keys instead of re-encrypting the data.
+
+use super::error::KeyError;
+use super::{SymmetricKey, KEK_LENGTH};
+use crate::actors::encryption::keyring::Keyring;
+use crate::actors::encryption::recipients::Fingerprint;
+use crate::actors::encryption::suite::CipherSuite;
+use crate::actors::encryption::AesError;
+use crate::utils::secret::SecretKey;
+
+/// A data key as stored next to the data it protects.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WrappedDataKey {
+    /// Fingerprint of the KEK the key is wrapped under.
+    pub kek: Fingerprint,
+    pub suite: CipherSuite,
+    /// The key wrapped with RFC 5649, see `SymmetricKey::wrap`.
+    pub wrapped: Vec<u8>,
+}
+
+/// A 32-byte key that only ever encrypts other keys.
+#[derive(Clone)]
+pub struct KeyEncryptionKey {
+    key: SymmetricKey,
+}
+
+impl KeyEncryptionKey {
+    pub fn new(key: impl Into<SecretKey>) -> Result<Self, AesError> {
+        Ok(KeyEncryptionKey {
+            key: SymmetricKey::new(CipherSuite::Aes256Gcm, key)?,
+        })
+    }
+
+    pub fn generate() -> Self {
+        KeyEncryptionKey {
+            key: SymmetricKey::generate(CipherSuite::Aes256Gcm),
+        }
+    }
+
+    /// The active version of the keyring key `name`, which must be 32 bytes long.
+    pub fn from_keyring(keyring: &Keyring, name: &str) -> Result<Self, AesError> {
+        let (_, key) = keyring.encryption_key(name)?;
+        Self::new(key)
+    }
+
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.key.fingerprint()
+    }
+
+    /// Draws a fresh data key for `suite` and wraps it.
+    pub fn generate_data_key(
+        &self,
+        suite: CipherSuite,
+    ) -> Result<(SymmetricKey, WrappedDataKey), AesError> {
+        let data_key = SymmetricKey::generate(suite);
+        let wrapped = self.wrap(&data_key)?;
+        Ok((data_key, wrapped))
+    }
+
+    pub fn wrap(&self, data_key: &SymmetricKey) -> Result<WrappedDataKey, AesError> {
+        Ok(WrappedDataKey {
+            kek: self.fingerprint(),
+            suite: data_key.suite(),
+            wrapped: data_key.wrap(self.key.as_bytes())?,
+        })
+    }
+
+    pub fn unwrap(&self, data_key: &WrappedDataKey) -> Result<SymmetricKey, AesError> {
+        if data_key.kek != self.fingerprint() {
+            return Err(KeyError::KekMismatch.into());
+        }
+        SymmetricKey::unwrap(data_key.suite, self.key.as_bytes(), &data_key.wrapped)
+    }
+
+    /// Moves `data_key` from this KEK to `kek` without exposing it to the caller.
+    pub fn rewrap(
+        &self,
+        data_key: &WrappedDataKey,
+        kek: &KeyEncryptionKey,
+    ) -> Result<WrappedDataKey, AesError> {
+        kek.wrap(&self.unwrap(data_key)?)
+    }
+}
+
+impl std::fmt::Debug for KeyEncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyEncryptionKey")
+            .field("length", &KEK_LENGTH)
+            .finish_non_exhaustive()
+    }
+}
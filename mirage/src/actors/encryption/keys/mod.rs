@@ -0,0 +1,469 @@
+//! Key import and export.
+//!
+//! Asymmetric keys use the formats OpenSSL reads and writes: private keys as PKCS#8
+//! (`PRIVATE KEY`, or `ENCRYPTED PRIVATE KEY` under PBES2 with AES-256-CBC) and public
+//! keys as SubjectPublicKeyInfo (`PUBLIC KEY`). PKCS#1 RSA keys are accepted on import.
+//!
+//! Symmetric keys are wrapped with AES key wrap with padding (RFC 5649, OpenSSL's
+//! `id-aes256-wrap-pad`), either under a key-encryption key or under one derived from a
+//! passphrase for the `MIRAGE SYMMETRIC KEY` PEM format (see `SymmetricKey::to_pem`).
+//!
+//! The fingerprint of an asymmetric key is the SHA-256 of its DER SubjectPublicKeyInfo,
+//! the same value `openssl pkey -pubin -outform DER | sha256sum` prints.
+
+use aes_kw::KekAes256;
+use hmac::{Hmac, Mac};
+use pkcs8::pkcs5::pbes2;
+use pkcs8::{
+    DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey, EncryptedPrivateKeyInfo,
+    PrivateKeyInfo,
+};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use rsa::pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey};
+use rsa::traits::PublicKeyParts;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroize;
+
+use self::error::KeyError;
+
+use super::kdf::{KdfAlgorithm, KdfCost, KdfParams};
+use super::recipients::{Fingerprint, Recipient, RecipientKey};
+use super::suite::CipherSuite;
+use super::AesError;
+use crate::actors::signing::{SigningKey, VerifyingKey};
+use crate::utils::key_generator::generate_key;
+use crate::utils::secret::{Secret, SecretBytes, SecretKey};
+
+pub mod error;
+pub mod hierarchy;
+
+/// PBKDF2-HMAC-SHA256 iterations used by `Pkcs8Kdf::default`.
+pub const PKCS8_PBKDF2_ITERATIONS: u32 = 600_000;
+/// Length of a key-encryption key for `SymmetricKey::wrap`.
+pub const KEK_LENGTH: usize = 32;
+/// Smallest RSA modulus accepted for recipients and signing keys.
+pub const MIN_RSA_KEY_BITS: usize = 2048;
+
+pub const SYMMETRIC_KEY_PEM_TAG: &str = "MIRAGE SYMMETRIC KEY";
+const SYMMETRIC_KEY_VERSION_1: u8 = 1;
+const PKCS8_SALT_LENGTH: usize = 16;
+const FINGERPRINT_LABEL: &[u8] = b"mirage symmetric key fingerprint";
+
+/// Password-based key derivation protecting an encrypted PKCS#8 export.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pkcs8Kdf {
+    /// Readable by every PKCS#8 implementation, including older OpenSSL releases.
+    Pbkdf2Sha256 {
+        iterations: u32,
+    },
+    Scrypt {
+        log_n: u8,
+        r: u32,
+        p: u32,
+    },
+}
+
+impl Default for Pkcs8Kdf {
+    fn default() -> Self {
+        Pkcs8Kdf::Pbkdf2Sha256 {
+            iterations: PKCS8_PBKDF2_ITERATIONS,
+        }
+    }
+}
+
+/// An RSA or Ed25519 private key.
+pub enum PrivateKey {
+    Rsa(RsaPrivateKey),
+    Ed25519(ed25519_dalek::SigningKey),
+}
+
+impl PrivateKey {
+    /// Loads a `PRIVATE KEY` or `RSA PRIVATE KEY` PEM, or an `ENCRYPTED PRIVATE KEY`
+    /// PEM when `passphrase` is given.
+    pub fn from_pem(pem: &[u8], passphrase: Option<&[u8]>) -> Result<Self, AesError> {
+        let pem = parse_pem(pem)?;
+        match pem.tag() {
+            "PRIVATE KEY" => Self::from_pkcs8_der(pem.contents()),
+            "ENCRYPTED PRIVATE KEY" => {
+                let passphrase = passphrase.ok_or(KeyError::PassphraseRequired)?;
+                Self::from_encrypted_pkcs8_der(pem.contents(), passphrase)
+            }
+            "RSA PRIVATE KEY" => Ok(PrivateKey::Rsa(
+                RsaPrivateKey::from_pkcs1_der(pem.contents()).map_err(invalid_key)?,
+            )),
+            tag => Err(KeyError::UnsupportedKeyType(tag.to_string()).into()),
+        }
+    }
+
+    pub fn from_pkcs8_der(der: &[u8]) -> Result<Self, AesError> {
+        let oid = PrivateKeyInfo::try_from(der)
+            .map_err(invalid_key)?
+            .algorithm
+            .oid;
+        if oid == rsa::pkcs1::ALGORITHM_OID {
+            Ok(PrivateKey::Rsa(
+                RsaPrivateKey::from_pkcs8_der(der).map_err(invalid_key)?,
+            ))
+        } else if oid == ed25519_dalek::pkcs8::ALGORITHM_OID {
+            Ok(PrivateKey::Ed25519(
+                ed25519_dalek::SigningKey::from_pkcs8_der(der).map_err(invalid_key)?,
+            ))
+        } else {
+            Err(KeyError::UnsupportedKeyType(oid.to_string()).into())
+        }
+    }
+
+    pub fn from_encrypted_pkcs8_der(der: &[u8], passphrase: &[u8]) -> Result<Self, AesError> {
+        let document = EncryptedPrivateKeyInfo::try_from(der)
+            .map_err(invalid_key)?
+            .decrypt(passphrase)
+            .map_err(|_| KeyError::UnwrapFailed)?;
+        Self::from_pkcs8_der(document.as_bytes())
+    }
+
+    /// Unencrypted PKCS#8 DER, wiped when dropped.
+    pub fn to_pkcs8_der(&self) -> Result<SecretBytes, AesError> {
+        let document = match self {
+            PrivateKey::Rsa(key) => key.to_pkcs8_der(),
+            PrivateKey::Ed25519(key) => key.to_pkcs8_der(),
+        }
+        .map_err(|e| KeyError::EncodingFailed(e.to_string()))?;
+        Ok(SecretBytes::from_slice(document.as_bytes()))
+    }
+
+    /// Unencrypted `PRIVATE KEY` PEM, wiped when dropped.
+    pub fn to_pem(&self) -> Result<Secret<String>, AesError> {
+        Ok(Secret::new(encode_pem(
+            "PRIVATE KEY",
+            &self.to_pkcs8_der()?,
+        )))
+    }
+
+    /// `ENCRYPTED PRIVATE KEY` PEM, protected by `passphrase` with PBES2, `kdf` and
+    /// AES-256-CBC. `Pkcs8Kdf::default()` suits OpenSSL and most other tooling.
+    pub fn to_encrypted_pem(
+        &self,
+        passphrase: &[u8],
+        kdf: Pkcs8Kdf,
+    ) -> Result<Secret<String>, AesError> {
+        let mut salt = [0u8; PKCS8_SALT_LENGTH];
+        let mut iv = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut iv);
+
+        let params = match kdf {
+            Pkcs8Kdf::Pbkdf2Sha256 { iterations } => {
+                pbes2::Parameters::pbkdf2_sha256_aes256cbc(iterations, &salt, &iv)
+            }
+            Pkcs8Kdf::Scrypt { log_n, r, p } => {
+                let params = scrypt::Params::new(log_n, r, p, 32)
+                    .map_err(|e| KeyError::EncodingFailed(e.to_string()))?;
+                pbes2::Parameters::scrypt_aes256cbc(params, &salt, &iv)
+            }
+        }
+        .map_err(|e| KeyError::EncodingFailed(e.to_string()))?;
+
+        let der = self.to_pkcs8_der()?;
+        let encrypted = PrivateKeyInfo::try_from(der.as_slice())
+            .map_err(invalid_key)?
+            .encrypt_with_params(params, passphrase)
+            .map_err(|e| KeyError::EncodingFailed(e.to_string()))?;
+        Ok(Secret::new(encode_pem(
+            "ENCRYPTED PRIVATE KEY",
+            encrypted.as_bytes(),
+        )))
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        match self {
+            PrivateKey::Rsa(key) => PublicKey::Rsa(key.to_public_key()),
+            PrivateKey::Ed25519(key) => PublicKey::Ed25519(key.verifying_key()),
+        }
+    }
+
+    pub fn fingerprint(&self) -> Result<Fingerprint, AesError> {
+        self.public_key().fingerprint()
+    }
+
+    pub fn into_signing_key(self) -> Result<SigningKey, AesError> {
+        match self {
+            PrivateKey::Rsa(key) => SigningKey::from_rsa(key),
+            PrivateKey::Ed25519(key) => SigningKey::from_ed25519(key),
+        }
+    }
+
+    /// RSA keys only; Ed25519 keys cannot unwrap data keys.
+    pub fn into_recipient_key(self) -> Result<RecipientKey, AesError> {
+        match self {
+            PrivateKey::Rsa(key) => RecipientKey::from_key(key),
+            PrivateKey::Ed25519(_) => {
+                Err(KeyError::UnsupportedKeyType("Ed25519 recipient".to_string()).into())
+            }
+        }
+    }
+}
+
+/// An RSA or Ed25519 public key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PublicKey {
+    Rsa(RsaPublicKey),
+    Ed25519(ed25519_dalek::VerifyingKey),
+}
+
+impl PublicKey {
+    /// Loads a `PUBLIC KEY` (SubjectPublicKeyInfo) or `RSA PUBLIC KEY` (PKCS#1) PEM.
+    pub fn from_pem(pem: &[u8]) -> Result<Self, AesError> {
+        let pem = parse_pem(pem)?;
+        match pem.tag() {
+            "PUBLIC KEY" => Self::from_der(pem.contents()),
+            "RSA PUBLIC KEY" => Ok(PublicKey::Rsa(
+                RsaPublicKey::from_pkcs1_der(pem.contents()).map_err(invalid_key)?,
+            )),
+            tag => Err(KeyError::UnsupportedKeyType(tag.to_string()).into()),
+        }
+    }
+
+    /// Loads a DER SubjectPublicKeyInfo.
+    pub fn from_der(der: &[u8]) -> Result<Self, AesError> {
+        let oid = pkcs8::spki::SubjectPublicKeyInfoRef::try_from(der)
+            .map_err(invalid_key)?
+            .algorithm
+            .oid;
+        if oid == rsa::pkcs1::ALGORITHM_OID {
+            Ok(PublicKey::Rsa(
+                RsaPublicKey::from_public_key_der(der).map_err(invalid_key)?,
+            ))
+        } else if oid == ed25519_dalek::pkcs8::ALGORITHM_OID {
+            Ok(PublicKey::Ed25519(
+                ed25519_dalek::VerifyingKey::from_public_key_der(der).map_err(invalid_key)?,
+            ))
+        } else {
+            Err(KeyError::UnsupportedKeyType(oid.to_string()).into())
+        }
+    }
+
+    /// DER SubjectPublicKeyInfo.
+    pub fn to_der(&self) -> Result<Vec<u8>, AesError> {
+        let document = match self {
+            PublicKey::Rsa(key) => key.to_public_key_der(),
+            PublicKey::Ed25519(key) => key.to_public_key_der(),
+        }
+        .map_err(|e| KeyError::EncodingFailed(e.to_string()))?;
+        Ok(document.into_vec())
+    }
+
+    /// `PUBLIC KEY` PEM.
+    pub fn to_pem(&self) -> Result<String, AesError> {
+        Ok(encode_pem("PUBLIC KEY", &self.to_der()?))
+    }
+
+    /// SHA-256 of the DER SubjectPublicKeyInfo.
+    pub fn fingerprint(&self) -> Result<Fingerprint, AesError> {
+        Ok(Sha256::digest(self.to_der()?).into())
+    }
+
+    pub fn to_verifying_key(&self) -> Result<VerifyingKey, AesError> {
+        match self {
+            PublicKey::Rsa(key) => VerifyingKey::from_rsa(key.clone()),
+            PublicKey::Ed25519(key) => VerifyingKey::from_ed25519(*key),
+        }
+    }
+
+    /// RSA keys only; data keys cannot be wrapped for Ed25519 keys.
+    pub fn to_recipient(&self) -> Result<Recipient, AesError> {
+        match self {
+            PublicKey::Rsa(key) => Recipient::from_key(key.clone()),
+            PublicKey::Ed25519(_) => {
+                Err(KeyError::UnsupportedKeyType("Ed25519 recipient".to_string()).into())
+            }
+        }
+    }
+}
+
+/// A key for one `CipherSuite`, as handed to `Encryptor` and `Decryptor`.
+///
+/// The `MIRAGE SYMMETRIC KEY` PEM format holds:
+///
+/// | size | field                                             |
+/// |------|---------------------------------------------------|
+/// | 1    | format version (`1`)                              |
+/// | 1    | cipher suite id, see `CipherSuite`                |
+/// | 1    | key derivation algorithm id, see `KdfAlgorithm`   |
+/// | 1    | salt length `s`                                   |
+/// | s    | salt                                              |
+/// | 1    | cost length `c`                                   |
+/// | c    | cost parameters, see `KdfCost::encode`            |
+/// | rest | key wrapped with RFC 5649 under the derived key   |
+#[derive(Clone, PartialEq, Eq)]
+pub struct SymmetricKey {
+    suite: CipherSuite,
+    key: SecretKey,
+}
+
+impl SymmetricKey {
+    pub fn new(suite: CipherSuite, key: impl Into<SecretKey>) -> Result<Self, AesError> {
+        let key = key.into();
+        if key.len() != suite.key_length() {
+            return Err(AesError::InvalidKeyLength {
+                expected: suite.key_length(),
+                actual: key.len(),
+            });
+        }
+        Ok(SymmetricKey { suite, key })
+    }
+
+    pub fn generate(suite: CipherSuite) -> Self {
+        SymmetricKey {
+            suite,
+            key: generate_key(suite),
+        }
+    }
+
+    pub fn suite(&self) -> CipherSuite {
+        self.suite
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.key
+    }
+
+    pub fn into_key(self) -> SecretKey {
+        self.key
+    }
+
+    /// HMAC-SHA256 of a fixed label under the key. Identifies the key without revealing
+    /// anything about it.
+    pub fn fingerprint(&self) -> Fingerprint {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(FINGERPRINT_LABEL);
+        mac.finalize().into_bytes().into()
+    }
+
+    /// Wraps the key under a 32-byte key-encryption key with RFC 5649. The result can be
+    /// unwrapped by OpenSSL's `id-aes256-wrap-pad` cipher.
+    pub fn wrap(&self, kek: &[u8]) -> Result<Vec<u8>, AesError> {
+        kek_cipher(kek)?
+            .wrap_with_padding_vec(&self.key)
+            .map_err(|e| KeyError::EncodingFailed(e.to_string()).into())
+    }
+
+    pub fn unwrap(suite: CipherSuite, kek: &[u8], wrapped: &[u8]) -> Result<Self, AesError> {
+        let key = kek_cipher(kek)?
+            .unwrap_with_padding_vec(wrapped)
+            .map(SecretKey::new)
+            .map_err(|_| KeyError::UnwrapFailed)?;
+        Self::new(suite, key)
+    }
+
+    /// `MIRAGE SYMMETRIC KEY` PEM wrapped under a key derived from `passphrase` with
+    /// `params`.
+    pub fn to_pem(&self, passphrase: &[u8], params: &KdfParams) -> Result<String, AesError> {
+        let kek = params.derive(passphrase, KEK_LENGTH)?;
+        let cost = params.cost.encode();
+
+        let mut encoded = vec![
+            SYMMETRIC_KEY_VERSION_1,
+            self.suite.id(),
+            params.cost.algorithm().id(),
+        ];
+        encoded.push(params.salt.len() as u8);
+        encoded.extend_from_slice(&params.salt);
+        encoded.push(cost.len() as u8);
+        encoded.extend_from_slice(&cost);
+        encoded.extend_from_slice(&self.wrap(&kek)?);
+        Ok(encode_pem(SYMMETRIC_KEY_PEM_TAG, &encoded))
+    }
+
+    pub fn from_pem(pem: &[u8], passphrase: &[u8]) -> Result<Self, AesError> {
+        let pem = parse_pem(pem)?;
+        if pem.tag() != SYMMETRIC_KEY_PEM_TAG {
+            return Err(KeyError::UnsupportedKeyType(pem.tag().to_string()).into());
+        }
+
+        let mut fields = Fields(pem.contents());
+        if fields.take(1)?[0] != SYMMETRIC_KEY_VERSION_1 {
+            return Err(
+                KeyError::InvalidKey("unsupported symmetric key version".to_string()).into(),
+            );
+        }
+        let suite = CipherSuite::from_id(fields.take(1)?[0])?;
+        let algorithm = KdfAlgorithm::from_id(fields.take(1)?[0])?;
+        let salt_length = fields.take(1)?[0] as usize;
+        let salt = fields.take(salt_length)?.to_vec();
+        KdfParams::check_salt(&salt)?;
+        let cost_length = fields.take(1)?[0] as usize;
+        let cost = KdfCost::decode(algorithm, fields.take(cost_length)?)?;
+
+        let kek = KdfParams { cost, salt }.derive(passphrase, KEK_LENGTH)?;
+        Self::unwrap(suite, &kek, fields.0)
+    }
+}
+
+impl std::fmt::Debug for SymmetricKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SymmetricKey")
+            .field("suite", &self.suite)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Lower-case hex, as printed by `sha256sum`.
+pub fn format_fingerprint(fingerprint: &Fingerprint) -> String {
+    fingerprint
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Unread remainder of an encoded symmetric key.
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], KeyError> {
+        if self.0.len() < length {
+            return Err(KeyError::InvalidKey(
+                "symmetric key is truncated".to_string(),
+            ));
+        }
+        let (taken, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(taken)
+    }
+}
+
+fn kek_cipher(kek: &[u8]) -> Result<KekAes256, AesError> {
+    let kek: [u8; KEK_LENGTH] = kek.try_into().map_err(|_| AesError::InvalidKeyLength {
+        expected: KEK_LENGTH,
+        actual: kek.len(),
+    })?;
+    Ok(KekAes256::new(&kek.into()))
+}
+
+/// Rejects RSA keys smaller than `MIN_RSA_KEY_BITS`.
+pub(crate) fn check_rsa_size(key: &impl PublicKeyParts) -> Result<(), KeyError> {
+    let bits = key.size() * 8;
+    if bits < MIN_RSA_KEY_BITS {
+        return Err(KeyError::KeyTooSmall(bits));
+    }
+    Ok(())
+}
+
+fn parse_pem(pem: &[u8]) -> Result<pem::Pem, KeyError> {
+    pem::parse(pem).map_err(|e| KeyError::InvalidPem(e.to_string()))
+}
+
+fn encode_pem(tag: &str, contents: &[u8]) -> String {
+    let config = pem::EncodeConfig::new().set_line_ending(pem::LineEnding::LF);
+    let pem = pem::Pem::new(tag, contents);
+    let encoded = pem::encode_config(&pem, config);
+    // Private key DER passes through here, so wipe the copy the `Pem` holds.
+    pem.into_contents().zeroize();
+    encoded
+}
+
+fn invalid_key(err: impl std::fmt::Display) -> KeyError {
+    KeyError::InvalidKey(err.to_string())
+}
@@ -1,25 +1,60 @@
 use std::error::Error;
 use std::fmt;
+use std::future::Future;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::io;
+use tokio::task::JoinHandle;
 
-use aes::cipher::InvalidLength;
 use aes_gcm::Error as AesGcmError;
+use log::error;
 
-use crate::actors::memory::secure_key_value_store::SecureStoreError;
-use crate::tools::config::EncryptionLevel; // Update path as necessary
+use crate::actors::memory::generic::secure_key_value_store::error::SecureStoreError;
+use crate::actors::memory::secure_memory_provider::error::SecureMemoryProviderError;
+use crate::actors::signing::error::SignatureError;
+use crate::utils::secret::SecretKey;
 
+use self::age::error::AgeError;
+use self::envelope::error::EnvelopeError;
+use self::kdf::error::KdfError;
+use self::keyring::error::KeyringError;
+use self::keys::error::KeyError;
+use self::recipients::error::RecipientError;
+use self::suite::CipherSuite;
+
+pub mod age;
 pub mod decryptor;
 pub mod encryptor;
+pub mod envelope;
+pub mod kdf;
+pub mod keyring;
+pub mod keys;
+pub mod recipients;
+pub mod stream;
+pub mod suite;
 
-pub const KEY_LENGTH: usize = 12;
+/// Length of the authentication tag appended by every supported AEAD.
+pub const TAG_LENGTH: usize = 16;
 
 #[derive(Debug)]
 pub enum AesError {
     AesGcmError(AesGcmError),
     SecureStoreError(SecureStoreError), // New variant for SecureStoreError
-    InvalidKeyLength,
+    SecureMemoryProviderError(SecureMemoryProviderError),
+    EnvelopeError(EnvelopeError),
+    KdfError(KdfError),
+    KeyError(KeyError),
+    KeyringError(KeyringError),
+    RecipientError(RecipientError),
+    AgeError(AgeError),
+    SignatureError(SignatureError),
+    InvalidKeyLength { expected: usize, actual: usize },
+    InvalidNonceLength { expected: usize, actual: usize },
+    UnsupportedCipherSuite(u8),
+    CiphertextTooShort(usize),
+    StreamTooLong,
+    ActorStopped,
     IoError(io::Error),
     SerializeError(String),
     DeserializeError(String),
@@ -30,11 +65,36 @@ impl Error for AesError {}
 impl fmt::Display for AesError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            AesError::AesGcmError(err) => write!(f, "AES GCM encryption/decryption error: {}", err),
+            AesError::AesGcmError(err) => write!(f, "AEAD encryption/decryption error: {}", err),
             AesError::SecureStoreError(err) => write!(f, "Secure store error: {:?}", err), // Display for SecureStoreError
-            AesError::InvalidKeyLength => {
-                write!(f, "Invalid key length, must be {} bytes", KEY_LENGTH)
+            AesError::SecureMemoryProviderError(err) => {
+                write!(f, "Secure memory provider error: {:?}", err)
             }
+            AesError::EnvelopeError(err) => write!(f, "Envelope error: {}", err),
+            AesError::KdfError(err) => write!(f, "Key derivation error: {}", err),
+            AesError::KeyError(err) => write!(f, "Key error: {}", err),
+            AesError::KeyringError(err) => write!(f, "Keyring error: {}", err),
+            AesError::RecipientError(err) => write!(f, "Recipient error: {}", err),
+            AesError::AgeError(err) => write!(f, "age error: {}", err),
+            AesError::SignatureError(err) => write!(f, "Signature error: {}", err),
+            AesError::InvalidKeyLength { expected, actual } => write!(
+                f,
+                "Invalid key length: expected {} bytes, got {}",
+                expected, actual
+            ),
+            AesError::InvalidNonceLength { expected, actual } => write!(
+                f,
+                "Invalid nonce length: expected {} bytes, got {}",
+                expected, actual
+            ),
+            AesError::UnsupportedCipherSuite(id) => write!(f, "Unsupported cipher suite id {}", id),
+            AesError::CiphertextTooShort(len) => write!(
+                f,
+                "Ciphertext too short: {} bytes cannot hold a nonce and tag",
+                len
+            ),
+            AesError::StreamTooLong => write!(f, "Message exceeds the maximum number of chunks"),
+            AesError::ActorStopped => write!(f, "Actor is no longer accepting work"),
             AesError::IoError(err) => write!(f, "IO error: {}", err),
             AesError::SerializeError(err) => write!(f, "Serialization error: {}", err),
             AesError::DeserializeError(err) => write!(f, "Deserialization error: {}", err),
@@ -48,12 +108,6 @@ impl From<AesGcmError> for AesError {
     }
 }
 
-impl From<InvalidLength> for AesError {
-    fn from(_err: InvalidLength) -> Self {
-        AesError::InvalidKeyLength
-    }
-}
-
 impl From<io::Error> for AesError {
     fn from(err: io::Error) -> Self {
         AesError::IoError(err)
@@ -61,17 +115,137 @@ impl From<io::Error> for AesError {
 }
 
 pub enum Input {
+    /// Processed in place; the file is replaced atomically once the job succeeds.
     File(PathBuf),
+    /// Reads the first path and atomically writes the result to the second one.
+    FileTo(PathBuf, PathBuf),
     Buffer(Vec<u8>),
     Bit(u8),
+    /// Wraps another input with associated data bound into its authentication tag.
+    WithAad(Box<Input>, Vec<u8>),
+}
+
+/// Result of an `Encryptor` or `Decryptor` job.
+#[derive(Debug)]
+pub enum Output {
+    /// The result was written to this path.
+    File(PathBuf),
+    /// Ciphertext or plaintext produced from an `Input::Buffer` or `Input::Bit`.
+    Buffer(Vec<u8>),
+}
+
+/// Handle returned by `Actor::run` for crypto actors; await it to get the job's result.
+pub type Job = JoinHandle<Result<Output, AesError>>;
+
+/// Liveness shared by an actor and every task it spawned.
+struct ActorState {
+    alive: AtomicBool,
+    pending: AtomicUsize,
+}
+
+/// Tracks one spawned task; marks the actor dead if the task panics.
+struct TaskGuard(Arc<ActorState>);
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.0.pending.fetch_sub(1, Ordering::SeqCst);
+        if std::thread::panicking() {
+            self.0.alive.store(false, Ordering::SeqCst);
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct CryptoBase {
-    is_alive: bool,
-    level: EncryptionLevel,
-    key: Arc<Vec<u8>>,
-    nonce: Arc<Vec<u8>>,
+    state: Arc<ActorState>,
+    suite: CipherSuite,
+    key: Arc<SecretKey>,
+    key_id: Arc<Vec<u8>>,
     has_parallel_processing: bool,
     runtime_handle: Arc<tokio::runtime::Handle>,
 }
+
+impl CryptoBase {
+    fn new(
+        suite: CipherSuite,
+        key: SecretKey,
+        has_parallel_processing: bool,
+        runtime_handle: Arc<tokio::runtime::Handle>,
+    ) -> Self {
+        CryptoBase {
+            state: Arc::new(ActorState {
+                alive: AtomicBool::new(true),
+                pending: AtomicUsize::new(0),
+            }),
+            suite,
+            key: Arc::new(key),
+            key_id: Arc::new(Vec::new()),
+            has_parallel_processing,
+            runtime_handle,
+        }
+    }
+
+    /// False once the actor was stopped or one of its tasks panicked.
+    fn is_alive(&self) -> bool {
+        self.state.alive.load(Ordering::SeqCst)
+    }
+
+    /// Number of spawned jobs that have not finished yet.
+    fn pending_jobs(&self) -> usize {
+        self.state.pending.load(Ordering::SeqCst)
+    }
+
+    /// Stops accepting new jobs. Jobs already running are not cancelled.
+    fn stop(&self) {
+        self.state.alive.store(false, Ordering::SeqCst);
+    }
+
+    /// Spawns `job` on the runtime, logging failures under `label` and keeping the
+    /// liveness state up to date.
+    fn spawn<F>(&self, label: &'static str, job: F) -> Job
+    where
+        F: Future<Output = Result<Output, AesError>> + Send + 'static,
+    {
+        if !self.is_alive() {
+            return self
+                .runtime_handle
+                .spawn(async { Err(AesError::ActorStopped) });
+        }
+
+        self.state.pending.fetch_add(1, Ordering::SeqCst);
+        let guard = TaskGuard(Arc::clone(&self.state));
+        self.runtime_handle.spawn(async move {
+            let _guard = guard;
+            let result = job.await;
+            if let Err(e) = &result {
+                error!("Error processing {}: {:?}", label, e);
+            }
+            result
+        })
+    }
+
+    /// Number of chunks sealed or opened together when streaming.
+    fn batch_size(&self) -> usize {
+        if self.has_parallel_processing {
+            rayon::current_num_threads()
+        } else {
+            1
+        }
+    }
+
+    /// Rejects keys whose length does not match `suite`.
+    fn validate_key(&self, suite: CipherSuite) -> Result<(), AesError> {
+        Self::check_key_length(&self.key, suite)
+    }
+
+    fn check_key_length(key: &[u8], suite: CipherSuite) -> Result<(), AesError> {
+        let expected = suite.key_length();
+        if key.len() != expected {
+            return Err(AesError::InvalidKeyLength {
+                expected,
+                actual: key.len(),
+            });
+        }
+        Ok(())
+    }
+}
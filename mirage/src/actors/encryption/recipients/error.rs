@@ -0,0 +1,36 @@
+use crate::actors::encryption::AesError;
+
+#[derive(Debug)]
+pub enum RecipientError {
+    InvalidKey(String),
+    NoRecipients,
+    TooManyRecipients(usize),
+    WrapFailed(String),
+    NotARecipient,
+    UnwrapFailed,
+}
+
+impl std::fmt::Display for RecipientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecipientError::InvalidKey(reason) => write!(f, "invalid RSA key: {}", reason),
+            RecipientError::NoRecipients => write!(f, "at least one recipient is required"),
+            RecipientError::TooManyRecipients(count) => {
+                write!(f, "{} recipients exceed the limit", count)
+            }
+            RecipientError::WrapFailed(reason) => {
+                write!(f, "failed to wrap the data key: {}", reason)
+            }
+            RecipientError::NotARecipient => {
+                write!(f, "envelope is not addressed to this private key")
+            }
+            RecipientError::UnwrapFailed => write!(f, "failed to unwrap the data key"),
+        }
+    }
+}
+
+impl From<RecipientError> for AesError {
+    fn from(err: RecipientError) -> Self {
+        AesError::RecipientError(err)
+    }
+}
@@ -0,0 +1,129 @@
+//! Hybrid encryption to RSA recipients.
+//!
+//! A random data key seals the payload as usual and is wrapped with RSA-OAEP (SHA-256)
+//! once per recipient. The wrapped keys travel in the envelope header next to the
+//! SHA-256 fingerprint of each recipient's public key, so any listed recipient can open
+//! the envelope with their private key alone.
+
+use rand::rngs::OsRng;
+use rsa::pkcs8::EncodePublicKey;
+use rsa::{Oaep, RsaPrivateKey, RsaPublicKey};
+use sha2::{Digest, Sha256};
+
+use self::error::RecipientError;
+
+use super::keys::{self, PrivateKey, PublicKey};
+use super::AesError;
+use crate::utils::secret::SecretKey;
+
+pub mod error;
+
+pub const FINGERPRINT_LENGTH: usize = 32;
+/// Recipients that fit the one-byte count in the envelope header.
+pub const MAX_RECIPIENTS: usize = u8::MAX as usize;
+
+pub type Fingerprint = [u8; FINGERPRINT_LENGTH];
+
+/// The data key wrapped for one recipient.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WrappedKey {
+    /// SHA-256 of the recipient's DER-encoded SubjectPublicKeyInfo.
+    pub fingerprint: Fingerprint,
+    pub wrapped: Vec<u8>,
+}
+
+/// Public key of someone an envelope is addressed to.
+#[derive(Clone, Debug)]
+pub struct Recipient {
+    key: RsaPublicKey,
+    fingerprint: Fingerprint,
+}
+
+impl Recipient {
+    /// Loads a `PUBLIC KEY` (SubjectPublicKeyInfo) or `RSA PUBLIC KEY` (PKCS#1) PEM.
+    pub fn from_pem(pem: &[u8]) -> Result<Self, AesError> {
+        PublicKey::from_pem(pem)?.to_recipient()
+    }
+
+    pub fn from_key(key: RsaPublicKey) -> Result<Self, AesError> {
+        keys::check_rsa_size(&key)?;
+        let fingerprint = fingerprint(&key)?;
+        Ok(Recipient { key, fingerprint })
+    }
+
+    pub fn fingerprint(&self) -> &Fingerprint {
+        &self.fingerprint
+    }
+
+    /// Wraps `data_key` for this recipient.
+    pub fn wrap(&self, data_key: &[u8]) -> Result<WrappedKey, AesError> {
+        let wrapped = self
+            .key
+            .encrypt(&mut OsRng, Oaep::new::<Sha256>(), data_key)
+            .map_err(|e| RecipientError::WrapFailed(e.to_string()))?;
+        Ok(WrappedKey {
+            fingerprint: self.fingerprint,
+            wrapped,
+        })
+    }
+}
+
+/// Private key that opens envelopes addressed to its public half.
+pub struct RecipientKey {
+    key: RsaPrivateKey,
+    fingerprint: Fingerprint,
+}
+
+impl RecipientKey {
+    /// Loads an unencrypted `PRIVATE KEY` (PKCS#8) or `RSA PRIVATE KEY` (PKCS#1) PEM.
+    /// Use `keys::PrivateKey` for encrypted keys.
+    pub fn from_pem(pem: &[u8]) -> Result<Self, AesError> {
+        PrivateKey::from_pem(pem, None)?.into_recipient_key()
+    }
+
+    pub fn from_key(key: RsaPrivateKey) -> Result<Self, AesError> {
+        keys::check_rsa_size(&key)?;
+        let fingerprint = fingerprint(&key.to_public_key())?;
+        Ok(RecipientKey { key, fingerprint })
+    }
+
+    pub fn fingerprint(&self) -> &Fingerprint {
+        &self.fingerprint
+    }
+
+    /// Recovers the data key from the entry addressed to this key.
+    pub fn unwrap(&self, wrapped_keys: &[WrappedKey]) -> Result<SecretKey, AesError> {
+        let entry = wrapped_keys
+            .iter()
+            .find(|entry| entry.fingerprint == self.fingerprint)
+            .ok_or(RecipientError::NotARecipient)?;
+        self.key
+            .decrypt(Oaep::new::<Sha256>(), &entry.wrapped)
+            .map(SecretKey::new)
+            .map_err(|_| RecipientError::UnwrapFailed.into())
+    }
+}
+
+/// Wraps `data_key` for every recipient.
+pub fn wrap_for_all(
+    recipients: &[Recipient],
+    data_key: &[u8],
+) -> Result<Vec<WrappedKey>, AesError> {
+    if recipients.is_empty() {
+        return Err(RecipientError::NoRecipients.into());
+    }
+    if recipients.len() > MAX_RECIPIENTS {
+        return Err(RecipientError::TooManyRecipients(recipients.len()).into());
+    }
+    recipients
+        .iter()
+        .map(|recipient| recipient.wrap(data_key))
+        .collect()
+}
+
+fn fingerprint(key: &RsaPublicKey) -> Result<Fingerprint, RecipientError> {
+    let der = key
+        .to_public_key_der()
+        .map_err(|e| RecipientError::InvalidKey(e.to_string()))?;
+    Ok(Sha256::digest(der.as_bytes()).into())
+}
@@ -0,0 +1,376 @@
+//! Chunked AEAD following the STREAM construction (Hoang, Reyhanitabar, Rogaway, Vizár).
+//!
+//! A message is split into `chunk_size` plaintext chunks that are sealed independently.
+//! Each chunk nonce is `prefix || counter || last_flag`, where `prefix` is random per
+//! message, `counter` is the 4-byte big-endian chunk index and `last_flag` is `1` only for
+//! the final chunk. The prefix fills whatever the cipher's nonce leaves over (7 bytes for
+//! 96-bit nonces, 19 bytes for XChaCha20). Each chunk also authenticates the message's
+//! associated data followed by its 8-byte big-endian index. Reordering, dropping,
+//! duplicating or truncating chunks therefore fails authentication.
+//!
+//! Wire layout: `chunk_0 || chunk_1 || ... || chunk_n`, where every sealed chunk except
+//! the last one is exactly `chunk_size + TAG_LENGTH` bytes long. The prefix and chunk
+//! size travel in the envelope header.
+
+use aes_gcm::aead::{self, Aead, AeadCore, AeadInPlace, Nonce, Payload, Tag};
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
+use tokio::io::{self, AsyncRead, AsyncReadExt};
+
+use super::{AesError, TAG_LENGTH};
+use crate::utils::secret::SecretBytes;
+
+/// Plaintext size of every chunk but the last, unless configured otherwise.
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+/// Largest chunk size accepted from an envelope header.
+pub const MAX_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Bytes of the chunk nonce taken by the counter and the last-chunk flag.
+const NONCE_SUFFIX_LENGTH: usize = 5;
+
+const LAST_CHUNK: u8 = 1;
+const INTERMEDIATE_CHUNK: u8 = 0;
+
+/// Length of the random per-message nonce prefix for cipher `C`.
+pub fn nonce_prefix_length<C: AeadCore>() -> usize {
+    Nonce::<C>::default().len() - NONCE_SUFFIX_LENGTH
+}
+
+/// Builds the nonce of chunk `index` from the message prefix.
+fn chunk_nonce<C: AeadCore>(prefix: &[u8], index: u32, is_last: bool) -> Nonce<C> {
+    let mut nonce = Nonce::<C>::default();
+    let prefix_length = nonce.len() - NONCE_SUFFIX_LENGTH;
+    nonce[..prefix_length].copy_from_slice(prefix);
+    nonce[prefix_length..prefix_length + 4].copy_from_slice(&index.to_be_bytes());
+    nonce[prefix_length + 4] = if is_last {
+        LAST_CHUNK
+    } else {
+        INTERMEDIATE_CHUNK
+    };
+    nonce
+}
+
+fn check_prefix<C: AeadCore>(prefix: &[u8]) -> Result<(), AesError> {
+    let expected = nonce_prefix_length::<C>();
+    if prefix.len() != expected {
+        return Err(AesError::InvalidNonceLength {
+            expected,
+            actual: prefix.len(),
+        });
+    }
+    Ok(())
+}
+
+fn chunk_index(index: usize) -> Result<u32, AesError> {
+    u32::try_from(index).map_err(|_| AesError::StreamTooLong)
+}
+
+/// Per-message STREAM parameters shared by every chunk.
+#[derive(Clone, Copy)]
+pub struct Stream<'a> {
+    pub prefix: &'a [u8],
+    pub associated_data: &'a [u8],
+    pub chunk_size: usize,
+}
+
+impl<'a> Stream<'a> {
+    pub fn new(prefix: &'a [u8], associated_data: &'a [u8], chunk_size: usize) -> Self {
+        Stream {
+            prefix,
+            associated_data,
+            chunk_size,
+        }
+    }
+
+    fn chunk_aad(&self, index: u32) -> Vec<u8> {
+        let mut aad = Vec::with_capacity(self.associated_data.len() + 8);
+        aad.extend_from_slice(self.associated_data);
+        aad.extend_from_slice(&u64::from(index).to_be_bytes());
+        aad
+    }
+
+    /// Seals a single chunk. Exposed so streaming callers can process chunks as they arrive.
+    pub fn seal_chunk<C>(
+        &self,
+        cipher: &C,
+        index: u32,
+        is_last: bool,
+        chunk: &[u8],
+    ) -> Result<Vec<u8>, AesError>
+    where
+        C: Aead,
+    {
+        check_prefix::<C>(self.prefix)?;
+        let nonce = chunk_nonce::<C>(self.prefix, index, is_last);
+        let aad = self.chunk_aad(index);
+        let payload = Payload {
+            msg: chunk,
+            aad: &aad,
+        };
+        Ok(cipher.encrypt(&nonce, payload)?)
+    }
+
+    /// Opens a single chunk sealed by [`Stream::seal_chunk`].
+    pub fn open_chunk<C>(
+        &self,
+        cipher: &C,
+        index: u32,
+        is_last: bool,
+        chunk: &[u8],
+    ) -> Result<SecretBytes, AesError>
+    where
+        C: Aead,
+    {
+        check_prefix::<C>(self.prefix)?;
+        let nonce = chunk_nonce::<C>(self.prefix, index, is_last);
+        let aad = self.chunk_aad(index);
+        let payload = Payload {
+            msg: chunk,
+            aad: &aad,
+        };
+        Ok(SecretBytes::new(cipher.decrypt(&nonce, payload)?))
+    }
+
+    /// Opens a single chunk into `opened`, which is exactly as long as its plaintext.
+    fn open_chunk_into<C>(
+        &self,
+        cipher: &C,
+        index: u32,
+        is_last: bool,
+        chunk: &[u8],
+        opened: &mut [u8],
+    ) -> Result<(), AesError>
+    where
+        C: AeadInPlace,
+    {
+        let (ciphertext, tag) = chunk.split_at(chunk.len() - TAG_LENGTH);
+        opened.copy_from_slice(ciphertext);
+        let nonce = chunk_nonce::<C>(self.prefix, index, is_last);
+        let aad = self.chunk_aad(index);
+        Ok(cipher.decrypt_in_place_detached(&nonce, &aad, opened, Tag::<C>::from_slice(tag))?)
+    }
+
+    /// Seals a run of consecutive chunks starting at `first_index`. The last chunk of the
+    /// batch is marked final when `ends_stream` is set.
+    pub fn seal_batch<C, T>(
+        &self,
+        cipher: &C,
+        first_index: u32,
+        chunks: &[T],
+        ends_stream: bool,
+        parallel: bool,
+    ) -> Result<Vec<Vec<u8>>, AesError>
+    where
+        C: Aead + Sync,
+        T: AsRef<[u8]> + Sync,
+    {
+        map_batch(
+            chunks,
+            first_index,
+            ends_stream,
+            parallel,
+            |index, is_last, chunk| self.seal_chunk(cipher, index, is_last, chunk),
+        )
+    }
+
+    /// Opens a run of consecutive chunks sealed by [`Stream::seal_batch`].
+    pub fn open_batch<C, T>(
+        &self,
+        cipher: &C,
+        first_index: u32,
+        chunks: &[T],
+        ends_stream: bool,
+        parallel: bool,
+    ) -> Result<Vec<SecretBytes>, AesError>
+    where
+        C: Aead + Sync,
+        T: AsRef<[u8]> + Sync,
+    {
+        map_batch(
+            chunks,
+            first_index,
+            ends_stream,
+            parallel,
+            |index, is_last, chunk| self.open_chunk(cipher, index, is_last, chunk),
+        )
+    }
+
+    /// Encrypts `data` into a sequence of sealed chunks.
+    ///
+    /// The output is identical whether `parallel` is set or not; it only decides whether
+    /// chunks are sealed on the rayon thread pool.
+    pub fn seal<C>(&self, cipher: &C, data: &[u8], parallel: bool) -> Result<Vec<u8>, AesError>
+    where
+        C: Aead + Sync,
+    {
+        check_prefix::<C>(self.prefix)?;
+        // An empty message is still sealed as one (empty) final chunk.
+        let chunks: Vec<&[u8]> = if data.is_empty() {
+            vec![data]
+        } else {
+            data.chunks(self.chunk_size).collect()
+        };
+        chunk_index(chunks.len() - 1)?;
+
+        Ok(self
+            .seal_batch(cipher, 0, &chunks, true, parallel)?
+            .concat())
+    }
+
+    /// Decrypts the output of [`Stream::seal`], see [`Stream::open_into`].
+    pub fn open<C>(&self, cipher: &C, body: &[u8], parallel: bool) -> Result<SecretBytes, AesError>
+    where
+        C: AeadInPlace + Sync,
+    {
+        let mut plaintext = SecretBytes::new(vec![0; self.plaintext_length(body)?]);
+        self.open_into(cipher, body, &mut plaintext, parallel)?;
+        Ok(plaintext)
+    }
+
+    /// Length of the plaintext sealed in `body`, if it is well-formed.
+    pub fn plaintext_length(&self, body: &[u8]) -> Result<usize, AesError> {
+        if body.len() < TAG_LENGTH {
+            return Err(AesError::CiphertextTooShort(body.len()));
+        }
+        let chunks = body.len().div_ceil(self.chunk_size + TAG_LENGTH);
+        body.len()
+            .checked_sub(chunks * TAG_LENGTH)
+            .ok_or(AesError::CiphertextTooShort(body.len()))
+    }
+
+    /// Decrypts the output of [`Stream::seal`] in place into `plaintext`, which must be
+    /// [`Stream::plaintext_length`] bytes long. No other buffer ever holds the plaintext,
+    /// so it can be opened straight into locked memory. On error `plaintext` may hold
+    /// unauthenticated bytes and must be discarded.
+    ///
+    /// Panics if `plaintext` has the wrong length.
+    pub fn open_into<C>(
+        &self,
+        cipher: &C,
+        body: &[u8],
+        plaintext: &mut [u8],
+        parallel: bool,
+    ) -> Result<(), AesError>
+    where
+        C: AeadInPlace + Sync,
+    {
+        check_prefix::<C>(self.prefix)?;
+        if body.len() < TAG_LENGTH {
+            return Err(AesError::CiphertextTooShort(body.len()));
+        }
+
+        let sealed: Vec<&[u8]> = body.chunks(self.chunk_size + TAG_LENGTH).collect();
+        let last = chunk_index(sealed.len() - 1)?;
+        let mut chunks = Vec::with_capacity(sealed.len());
+        let mut rest = plaintext;
+        for chunk in sealed {
+            // Only a truncated last chunk can be shorter than its tag.
+            let length = chunk.len().checked_sub(TAG_LENGTH).ok_or(aead::Error)?;
+            assert!(length <= rest.len(), "plaintext buffer too short");
+            let (opened, tail) = std::mem::take(&mut rest).split_at_mut(length);
+            chunks.push((chunk, opened));
+            rest = tail;
+        }
+        assert!(rest.is_empty(), "plaintext buffer too long");
+
+        let open = |(index, (chunk, opened)): (usize, (&[u8], &mut [u8]))| {
+            let index = index as u32;
+            self.open_chunk_into(cipher, index, index == last, chunk, opened)
+        };
+        if parallel {
+            chunks.into_par_iter().enumerate().try_for_each(open)
+        } else {
+            chunks.into_iter().enumerate().try_for_each(open)
+        }
+    }
+}
+
+/// Applies `process` to every chunk of a batch with its absolute index and final flag.
+fn map_batch<T, O, F>(
+    chunks: &[T],
+    first_index: u32,
+    ends_stream: bool,
+    parallel: bool,
+    process: F,
+) -> Result<Vec<O>, AesError>
+where
+    T: AsRef<[u8]> + Sync,
+    O: Send,
+    F: Fn(u32, bool, &[u8]) -> Result<O, AesError> + Sync,
+{
+    let last = chunks.len().saturating_sub(1);
+    let process_indexed = |(offset, chunk): (usize, &T)| {
+        let index = chunk_index(offset)?
+            .checked_add(first_index)
+            .ok_or(AesError::StreamTooLong)?;
+        process(index, ends_stream && offset == last, chunk.as_ref())
+    };
+
+    if parallel {
+        chunks.par_iter().enumerate().map(process_indexed).collect()
+    } else {
+        chunks.iter().enumerate().map(process_indexed).collect()
+    }
+}
+
+/// Reads fixed-size chunks from an async source, looking one chunk ahead so the final
+/// chunk is known before it is processed. Chunks may be plaintext and are wiped when
+/// dropped.
+pub struct ChunkReader<'a, R> {
+    reader: &'a mut R,
+    chunk_size: usize,
+    lookahead: Option<SecretBytes>,
+}
+
+impl<'a, R: AsyncRead + Unpin> ChunkReader<'a, R> {
+    pub fn new(reader: &'a mut R, chunk_size: usize) -> Self {
+        ChunkReader {
+            reader,
+            chunk_size,
+            lookahead: None,
+        }
+    }
+
+    /// Reads up to `chunk_size` bytes; a shorter chunk is only returned at end of input.
+    /// The buffer is allocated at its final size, so no unwiped copy is left behind by
+    /// growing it.
+    async fn read_chunk(&mut self) -> io::Result<SecretBytes> {
+        let mut chunk = SecretBytes::new(vec![0; self.chunk_size]);
+        let mut filled = 0;
+        while filled < self.chunk_size {
+            match self.reader.read(&mut chunk[filled..]).await? {
+                0 => break,
+                read => filled += read,
+            }
+        }
+        chunk.truncate(filled);
+        Ok(chunk)
+    }
+
+    /// Returns up to `max_chunks` chunks and whether they end the input. The input always
+    /// yields at least one chunk, which is empty for empty input.
+    pub async fn next_batch(&mut self, max_chunks: usize) -> io::Result<(Vec<SecretBytes>, bool)> {
+        let first = match self.lookahead.take() {
+            Some(chunk) => chunk,
+            None => self.read_chunk().await?,
+        };
+        let mut batch = vec![first];
+
+        loop {
+            if batch.last().map_or(0, |chunk| chunk.len()) < self.chunk_size {
+                return Ok((batch, true));
+            }
+
+            let next = self.read_chunk().await?;
+            if next.is_empty() {
+                return Ok((batch, true));
+            }
+            if batch.len() >= max_chunks {
+                self.lookahead = Some(next);
+                return Ok((batch, false));
+            }
+            batch.push(next);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use aes_gcm::aead::KeyInit;
+use aes_gcm::{Aes128Gcm, Aes256Gcm};
+use aes_gcm_siv::Aes256GcmSiv;
+use chacha20poly1305::{ChaCha20Poly1305, XChaCha20Poly1305};
+use serde::{Deserialize, Serialize};
+
+use crate::tools::config::{Config, EncryptionLevel};
+use crate::utils::secret::SecretBytes;
+
+use super::stream::{self, Stream};
+use super::AesError;
+
+/// Evaluates `$body` with `$cipher` bound to the suite's AEAD keyed with `$key`.
+macro_rules! with_cipher {
+    ($suite:expr, $key:expr, |$cipher:ident| $body:expr) => {
+        match $suite {
+            CipherSuite::Aes128Gcm => {
+                let $cipher = $suite.cipher::<Aes128Gcm>($key)?;
+                $body
+            }
+            CipherSuite::Aes256Gcm => {
+                let $cipher = $suite.cipher::<Aes256Gcm>($key)?;
+                $body
+            }
+            CipherSuite::ChaCha20Poly1305 => {
+                let $cipher = $suite.cipher::<ChaCha20Poly1305>($key)?;
+                $body
+            }
+            CipherSuite::XChaCha20Poly1305 => {
+                let $cipher = $suite.cipher::<XChaCha20Poly1305>($key)?;
+                $body
+            }
+            CipherSuite::Aes256GcmSiv => {
+                let $cipher = $suite.cipher::<Aes256GcmSiv>($key)?;
+                $body
+            }
+        }
+    };
+}
+
+/// AEAD algorithms available to `Encryptor` and `Decryptor`.
+///
+/// The discriminant is the suite id written in front of every ciphertext, so existing
+/// values must never be renumbered.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CipherSuite {
+    Aes128Gcm = 1,
+    Aes256Gcm = 2,
+    /// Constant-time in software, preferred on hosts without AES-NI.
+    ChaCha20Poly1305 = 3,
+    /// 192-bit nonces, large enough to be drawn at random without a birthday bound concern.
+    XChaCha20Poly1305 = 4,
+    /// Nonce-misuse resistant: a repeated nonce only reveals equality of messages.
+    Aes256GcmSiv = 5,
+}
+
+impl CipherSuite {
+    /// Suite selected by `Parameters`, falling back to the configured `EncryptionLevel`.
+    pub fn from_config() -> Self {
+        let parameters = Config::get_parameters();
+        parameters
+            .cipher_suite
+            .unwrap_or_else(|| parameters.encryption_level.into())
+    }
+
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_id(id: u8) -> Result<Self, AesError> {
+        match id {
+            1 => Ok(CipherSuite::Aes128Gcm),
+            2 => Ok(CipherSuite::Aes256Gcm),
+            3 => Ok(CipherSuite::ChaCha20Poly1305),
+            4 => Ok(CipherSuite::XChaCha20Poly1305),
+            5 => Ok(CipherSuite::Aes256GcmSiv),
+            _ => Err(AesError::UnsupportedCipherSuite(id)),
+        }
+    }
+
+    pub fn key_length(&self) -> usize {
+        match self {
+            CipherSuite::Aes128Gcm => 16,
+            _ => 32,
+        }
+    }
+
+    pub fn nonce_prefix_length(&self) -> usize {
+        match self {
+            CipherSuite::Aes128Gcm => stream::nonce_prefix_length::<Aes128Gcm>(),
+            CipherSuite::Aes256Gcm => stream::nonce_prefix_length::<Aes256Gcm>(),
+            CipherSuite::ChaCha20Poly1305 => stream::nonce_prefix_length::<ChaCha20Poly1305>(),
+            CipherSuite::XChaCha20Poly1305 => stream::nonce_prefix_length::<XChaCha20Poly1305>(),
+            CipherSuite::Aes256GcmSiv => stream::nonce_prefix_length::<Aes256GcmSiv>(),
+        }
+    }
+
+    /// Seals `data` with the STREAM construction under this suite.
+    pub fn seal(
+        &self,
+        key: &[u8],
+        stream: &Stream,
+        data: &[u8],
+        parallel: bool,
+    ) -> Result<Vec<u8>, AesError> {
+        with_cipher!(self, key, |cipher| stream.seal(&cipher, data, parallel))
+    }
+
+    /// Opens STREAM chunks sealed under this suite.
+    pub fn open(
+        &self,
+        key: &[u8],
+        stream: &Stream,
+        body: &[u8],
+        parallel: bool,
+    ) -> Result<SecretBytes, AesError> {
+        with_cipher!(self, key, |cipher| stream.open(&cipher, body, parallel))
+    }
+
+    /// Opens STREAM chunks sealed under this suite in place, see `Stream::open_into`.
+    pub fn open_into(
+        &self,
+        key: &[u8],
+        stream: &Stream,
+        body: &[u8],
+        plaintext: &mut [u8],
+        parallel: bool,
+    ) -> Result<(), AesError> {
+        with_cipher!(self, key, |cipher| stream
+            .open_into(&cipher, body, plaintext, parallel))
+    }
+
+    /// Seals consecutive STREAM chunks under this suite, see `Stream::seal_batch`.
+    pub fn seal_batch<T: AsRef<[u8]> + Sync>(
+        &self,
+        key: &[u8],
+        stream: &Stream,
+        first_index: u32,
+        chunks: &[T],
+        ends_stream: bool,
+        parallel: bool,
+    ) -> Result<Vec<Vec<u8>>, AesError> {
+        with_cipher!(self, key, |cipher| stream.seal_batch(
+            &cipher,
+            first_index,
+            chunks,
+            ends_stream,
+            parallel
+        ))
+    }
+
+    /// Opens consecutive STREAM chunks sealed under this suite, see `Stream::open_batch`.
+    pub fn open_batch<T: AsRef<[u8]> + Sync>(
+        &self,
+        key: &[u8],
+        stream: &Stream,
+        first_index: u32,
+        chunks: &[T],
+        ends_stream: bool,
+        parallel: bool,
+    ) -> Result<Vec<SecretBytes>, AesError> {
+        with_cipher!(self, key, |cipher| stream.open_batch(
+            &cipher,
+            first_index,
+            chunks,
+            ends_stream,
+            parallel
+        ))
+    }
+
+    fn cipher<C: KeyInit>(&self, key: &[u8]) -> Result<C, AesError> {
+        C::new_from_slice(key).map_err(|_| AesError::InvalidKeyLength {
+            expected: self.key_length(),
+            actual: key.len(),
+        })
+    }
+}
+
+impl From<EncryptionLevel> for CipherSuite {
+    fn from(level: EncryptionLevel) -> Self {
+        match level {
+            EncryptionLevel::Level1 => CipherSuite::Aes128Gcm,
+            EncryptionLevel::Level2 => CipherSuite::Aes256Gcm,
+        }
+    }
+}
+
+impl From<CipherSuite> for EncryptionLevel {
+    /// Level whose key length matches the suite's.
+    fn from(suite: CipherSuite) -> Self {
+        match suite.key_length() {
+            16 => EncryptionLevel::Level1,
+            _ => EncryptionLevel::Level2,
+        }
+    }
+}
+
+impl std::fmt::Display for CipherSuite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CipherSuite::Aes128Gcm => write!(f, "AES-128-GCM"),
+            CipherSuite::Aes256Gcm => write!(f, "AES-256-GCM"),
+            CipherSuite::ChaCha20Poly1305 => write!(f, "ChaCha20-Poly1305"),
+            CipherSuite::XChaCha20Poly1305 => write!(f, "XChaCha20-Poly1305"),
+            CipherSuite::Aes256GcmSiv => write!(f, "AES-256-GCM-SIV"),
+        }
+    }
+}
@@ -1 +1,2 @@
+pub mod secure_hasher;
 pub mod secure_key_value_store;
@@ -1 +1,62 @@
+//! Keyed hashing for table indexes.
+//!
+//! `SecureHasher` builds SipHash-2-4 hashers under a secret 128-bit key. Without the key,
+//! the slot a name hashes to says nothing about the name, and nobody can pick names that
+//! collide to degrade a table into a linear scan (hash flooding).
 
+use std::fmt;
+use std::hash::BuildHasher;
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+use siphasher::sip::SipHasher24;
+use zeroize::Zeroize;
+
+/// Length of a `SecureHasher` key.
+pub const HASH_KEY_LENGTH: usize = 16;
+
+/// `BuildHasher` for keyed SipHash-2-4. Hashes only agree between clones and
+/// builders made from the same key.
+#[derive(Clone)]
+pub struct SecureHasher {
+    key: [u8; HASH_KEY_LENGTH],
+}
+
+impl SecureHasher {
+    /// A builder under a fresh random key.
+    pub fn new() -> Self {
+        let mut key = [0u8; HASH_KEY_LENGTH];
+        OsRng.fill_bytes(&mut key);
+        SecureHasher { key }
+    }
+
+    pub fn with_key(key: [u8; HASH_KEY_LENGTH]) -> Self {
+        SecureHasher { key }
+    }
+}
+
+impl Default for SecureHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildHasher for SecureHasher {
+    type Hasher = SipHasher24;
+
+    fn build_hasher(&self) -> SipHasher24 {
+        SipHasher24::new_with_key(&self.key)
+    }
+}
+
+impl Drop for SecureHasher {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl fmt::Debug for SecureHasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecureHasher").finish_non_exhaustive()
+    }
+}
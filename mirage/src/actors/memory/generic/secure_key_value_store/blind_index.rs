@@ -0,0 +1,143 @@
+//! Entry names kept as blind indexes.
+//!
+//! The store never keeps a name in the clear. Entries are filed under the HMAC-SHA256 of
+//! their name, so exact-match lookups work while the table itself reveals nothing about
+//! the names. Optionally the name is also kept encrypted with the entry, which is what
+//! makes listing possible; without it, names can only be checked, not recovered.
+//!
+//! Both the HMAC key and the name encryption key are derived from one `NameKey`. A store
+//! whose `NameKey` is persisted, e.g. in a keyring, computes the same indexes again.
+
+use std::fmt;
+use std::sync::Arc;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::actors::encryption::decryptor::Decryptor;
+use crate::actors::encryption::encryptor::Encryptor;
+use crate::actors::encryption::keyring::Keyring;
+use crate::actors::encryption::suite::CipherSuite;
+use crate::actors::encryption::AesError;
+use crate::utils::key_generator::generate_key;
+use crate::utils::secret::{Secret, SecretKey};
+
+use super::error::SecureStoreError;
+
+/// HMAC-SHA256 of an entry name under the index key.
+pub type BlindIndex = [u8; 32];
+
+/// Length of a `NameKey`.
+pub const NAME_KEY_LENGTH: usize = 32;
+
+const INDEX_KEY_LABEL: &[u8] = b"mirage blind index";
+const NAMESPACE_INDEX_KEY_LABEL: &[u8] = b"mirage namespace blind index";
+const NAME_ENCRYPTION_LABEL: &[u8] = b"mirage name encryption";
+/// Suite names are encrypted with; its keys match the derived key length.
+const NAME_SUITE: CipherSuite = CipherSuite::Aes256Gcm;
+
+/// Secret the index key and the name encryption key are derived from.
+#[derive(Clone)]
+pub struct NameKey(SecretKey);
+
+impl NameKey {
+    pub fn new(key: impl Into<SecretKey>) -> Result<Self, AesError> {
+        let key = key.into();
+        if key.len() != NAME_KEY_LENGTH {
+            return Err(AesError::InvalidKeyLength {
+                expected: NAME_KEY_LENGTH,
+                actual: key.len(),
+            });
+        }
+        Ok(NameKey(key))
+    }
+
+    pub fn generate() -> Self {
+        NameKey(generate_key(NAME_SUITE))
+    }
+
+    /// The active version of the keyring key `name`, which must be 32 bytes long.
+    pub fn from_keyring(keyring: &Keyring, name: &str) -> Result<Self, AesError> {
+        let (_, key) = keyring.encryption_key(name)?;
+        Self::new(key)
+    }
+
+    fn derive(&self, label: &[u8]) -> SecretKey {
+        SecretKey::new(hmac(&self.0, label).to_vec())
+    }
+}
+
+impl fmt::Debug for NameKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NameKey").finish_non_exhaustive()
+    }
+}
+
+/// Computes blind indexes and, when names are kept, encrypts and decrypts them.
+pub struct NameIndex {
+    index_key: SecretKey,
+    /// Separate from `index_key`, so a namespace and an entry of the same name get
+    /// unrelated indexes.
+    namespace_key: SecretKey,
+    names: Option<(Encryptor, Decryptor)>,
+}
+
+impl NameIndex {
+    /// Indexes names under `name_key` without keeping them, so the store cannot be listed.
+    pub fn new(name_key: &NameKey) -> Self {
+        NameIndex {
+            index_key: name_key.derive(INDEX_KEY_LABEL),
+            namespace_key: name_key.derive(NAMESPACE_INDEX_KEY_LABEL),
+            names: None,
+        }
+    }
+
+    /// Indexes names under `name_key` and keeps every name encrypted with its entry.
+    pub fn keeping_names(name_key: &NameKey, runtime_handle: Arc<tokio::runtime::Handle>) -> Self {
+        let key = name_key.derive(NAME_ENCRYPTION_LABEL);
+        NameIndex {
+            index_key: name_key.derive(INDEX_KEY_LABEL),
+            namespace_key: name_key.derive(NAMESPACE_INDEX_KEY_LABEL),
+            names: Some((
+                Encryptor::with_suite(NAME_SUITE, key.clone(), Arc::clone(&runtime_handle)),
+                Decryptor::with_suite(NAME_SUITE, key, runtime_handle),
+            )),
+        }
+    }
+
+    pub fn keeps_names(&self) -> bool {
+        self.names.is_some()
+    }
+
+    pub fn index(&self, name: &str) -> BlindIndex {
+        hmac(&self.index_key, name.as_bytes())
+    }
+
+    /// Blind index of a namespace, see `data_keys`.
+    pub fn namespace_index(&self, namespace: &str) -> BlindIndex {
+        hmac(&self.namespace_key, namespace.as_bytes())
+    }
+
+    /// `name` encrypted and bound to `index`, when names are kept.
+    pub fn seal_name(&self, index: &BlindIndex, name: &str) -> Result<Option<Vec<u8>>, AesError> {
+        let Some((encryptor, _)) = &self.names else {
+            return Ok(None);
+        };
+        encryptor.encrypt_with_aad(name.as_bytes(), index).map(Some)
+    }
+
+    /// Recovers a name sealed by `seal_name` for `index`.
+    pub fn open_name(&self, index: &BlindIndex, sealed: &[u8]) -> Result<Secret<String>, AesError> {
+        let (_, decryptor) = self.names.as_ref().ok_or(SecureStoreError::NamesNotKept)?;
+        let name = decryptor.decrypt_with_aad(sealed, index)?;
+        String::from_utf8(name)
+            .map(Secret::new)
+            .map_err(|e| AesError::DeserializeError(e.to_string()))
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
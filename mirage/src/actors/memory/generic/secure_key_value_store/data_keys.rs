@@ -0,0 +1,233 @@
+//! Two-level key hierarchy for `SecureKeyValueStore`.
+//!
+//! Values are sealed with data keys, one per namespace or one per entry, and the data
+//! keys are only kept wrapped under a key-encryption key. A data key is unwrapped for
+//! the duration of a single `seal` or `open`. Rotating the key-encryption key re-wraps
+//! the data keys and leaves every sealed value untouched.
+//!
+//! Namespace keys are filed under the blind index of the namespace, computed by the
+//! store's `NameIndex` under a key of its own, so namespaces are never kept in the clear
+//! either and cannot be linked to entries of the same name.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::actors::encryption::decryptor::Decryptor;
+use crate::actors::encryption::encryptor::Encryptor;
+use crate::actors::encryption::keys::hierarchy::{KeyEncryptionKey, WrappedDataKey};
+use crate::actors::encryption::keys::SymmetricKey;
+use crate::actors::encryption::suite::CipherSuite;
+use crate::actors::encryption::AesError;
+use crate::utils::secure_region::{Backend, Protection, SecureRegion};
+
+use super::blind_index::{BlindIndex, NameIndex};
+use super::error::SecureStoreError;
+
+/// Separates the namespace from the rest of an entry name.
+pub const NAMESPACE_SEPARATOR: char = '/';
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataKeyScope {
+    /// One data key per namespace, the part of the entry name before the first
+    /// `NAMESPACE_SEPARATOR`. Names without one share the default namespace.
+    Namespace,
+    /// One data key per entry, stored with the entry.
+    Entry,
+}
+
+/// A value as kept in the store.
+#[derive(Clone, Debug)]
+pub struct SealedValue {
+    /// The entry's own data key, under `DataKeyScope::Entry`.
+    pub data_key: Option<WrappedDataKey>,
+    /// Version of the store key the value is sealed under, see `rotation`.
+    pub key_version: u32,
+    /// Kept in a `SecureRegion`, so it is never swapped out or dumped, and not accessible
+    /// outside of `SecureRegion::read`.
+    pub envelope: SecureRegion,
+    /// The entry name, encrypted, when the store keeps names, see `blind_index`.
+    pub encrypted_name: Option<Vec<u8>>,
+    /// The plaintext is itself an envelope under the same store key, see
+    /// `StoreKeys::seal_nested`.
+    pub nested: bool,
+}
+
+/// Seals and opens store values with wrapped data keys.
+pub struct DataKeys {
+    kek: KeyEncryptionKey,
+    suite: CipherSuite,
+    scope: DataKeyScope,
+    /// Wrapped data key of every namespace by its blind index, under
+    /// `DataKeyScope::Namespace`.
+    namespaces: HashMap<BlindIndex, WrappedDataKey>,
+    runtime_handle: Arc<tokio::runtime::Handle>,
+    /// Where envelopes are kept.
+    backend: Backend,
+}
+
+impl DataKeys {
+    pub fn new(
+        kek: KeyEncryptionKey,
+        suite: CipherSuite,
+        scope: DataKeyScope,
+        runtime_handle: Arc<tokio::runtime::Handle>,
+    ) -> Self {
+        DataKeys {
+            kek,
+            suite,
+            scope,
+            namespaces: HashMap::new(),
+            runtime_handle,
+            backend: Backend::default(),
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Keeps envelopes sealed from now on in `backend`.
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    pub fn scope(&self) -> DataKeyScope {
+        self.scope
+    }
+
+    pub fn kek(&self) -> &KeyEncryptionKey {
+        &self.kek
+    }
+
+    /// Encrypts `value` for the entry `name`, looking its namespace key up under the
+    /// blind index `names` computes. The name is bound into the envelope, so a sealed
+    /// value cannot be moved to another entry.
+    pub fn seal(
+        &mut self,
+        names: &NameIndex,
+        name: &str,
+        value: &[u8],
+    ) -> Result<SealedValue, AesError> {
+        let (data_key, stored_key) = match self.scope {
+            DataKeyScope::Entry => {
+                let (data_key, wrapped) = self.kek.generate_data_key(self.suite)?;
+                (data_key, Some(wrapped))
+            }
+            DataKeyScope::Namespace => {
+                let namespace = names.namespace_index(namespace(name));
+                let data_key = match self.namespaces.get(&namespace) {
+                    Some(wrapped) => self.kek.unwrap(wrapped)?,
+                    None => {
+                        let (data_key, wrapped) = self.kek.generate_data_key(self.suite)?;
+                        self.namespaces.insert(namespace, wrapped);
+                        data_key
+                    }
+                };
+                (data_key, None)
+            }
+        };
+
+        let encryptor = Encryptor::with_suite(
+            data_key.suite(),
+            data_key.into_key(),
+            Arc::clone(&self.runtime_handle),
+        );
+        Ok(SealedValue {
+            data_key: stored_key,
+            key_version: 0,
+            envelope: hide_envelope(
+                &encryptor.encrypt_with_aad(value, name.as_bytes())?,
+                self.backend,
+            )?,
+            encrypted_name: None,
+            nested: false,
+        })
+    }
+
+    /// Decrypts the value stored for the entry `name`, with the same `names` it was
+    /// sealed with, in place inside a region from `backend`.
+    pub fn open(
+        &self,
+        names: &NameIndex,
+        name: &str,
+        value: &SealedValue,
+    ) -> Result<SecureRegion, AesError> {
+        let data_key = self.data_key_for(names, name, value)?;
+        let decryptor = Decryptor::with_suite(
+            data_key.suite(),
+            data_key.into_key(),
+            Arc::clone(&self.runtime_handle),
+        );
+        value
+            .envelope
+            .read(|envelope| decryptor.decrypt_to_region(envelope, name.as_bytes(), self.backend))?
+    }
+
+    /// Re-wraps every data key, the namespace keys and those of `values`, under `kek`,
+    /// which replaces the current key-encryption key. Nothing changes unless every data
+    /// key could be re-wrapped. Returns the number of data keys re-wrapped.
+    pub fn rotate_kek<'a>(
+        &mut self,
+        kek: KeyEncryptionKey,
+        values: impl IntoIterator<Item = &'a mut SealedValue>,
+    ) -> Result<usize, AesError> {
+        let mut values: Vec<&mut SealedValue> = values
+            .into_iter()
+            .filter(|value| value.data_key.is_some())
+            .collect();
+
+        let namespaces = self
+            .namespaces
+            .iter()
+            .map(|(namespace, wrapped)| Ok((*namespace, self.kek.rewrap(wrapped, &kek)?)))
+            .collect::<Result<HashMap<_, _>, AesError>>()?;
+        let entries = values
+            .iter()
+            .filter_map(|value| value.data_key.as_ref())
+            .map(|wrapped| self.kek.rewrap(wrapped, &kek))
+            .collect::<Result<Vec<_>, AesError>>()?;
+
+        let count = namespaces.len() + entries.len();
+        for (value, wrapped) in values.iter_mut().zip(entries) {
+            value.data_key = Some(wrapped);
+        }
+        self.namespaces = namespaces;
+        self.kek = kek;
+        Ok(count)
+    }
+
+    /// Drops every namespace key, e.g. when the store switches to another `NameIndex`
+    /// and their blind indexes no longer match.
+    pub(super) fn clear_namespaces(&mut self) {
+        self.namespaces.clear();
+    }
+
+    fn data_key_for(
+        &self,
+        names: &NameIndex,
+        name: &str,
+        value: &SealedValue,
+    ) -> Result<SymmetricKey, AesError> {
+        let wrapped = match &value.data_key {
+            Some(wrapped) => wrapped,
+            None => self
+                .namespaces
+                .get(&names.namespace_index(namespace(name)))
+                .ok_or(SecureStoreError::MissingDataKey)?,
+        };
+        self.kek.unwrap(wrapped)
+    }
+}
+
+/// Copies `envelope` into a `SecureRegion` from `backend`, no longer accessible until it
+/// is read.
+pub(super) fn hide_envelope(envelope: &[u8], backend: Backend) -> Result<SecureRegion, AesError> {
+    let region = SecureRegion::from_slice_in(envelope, backend)?;
+    region.set_protection(Protection::NoAccess)?;
+    Ok(region)
+}
+
+fn namespace(name: &str) -> &str {
+    name.split_once(NAMESPACE_SEPARATOR)
+        .map_or("", |(namespace, _)| namespace)
+}
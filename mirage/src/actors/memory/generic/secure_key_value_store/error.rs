@@ -4,6 +4,26 @@ use crate::actors::encryption::AesError;
 pub enum SecureStoreError {
     EncryptionError,
     DecryptionError,
+    /// No secure memory could be mapped, see `SecureRegion`.
+    AllocationError,
+    /// Re-wrapping the data keys under a new key-encryption key failed.
+    KeyWrapError,
+    /// The store seals every value with one key and has no key-encryption key.
+    NoKeyHierarchy,
+    /// A value's namespace has no data key.
+    MissingDataKey,
+    /// The store seals values with data keys; rotate those with `rotate_kek`.
+    NoStoreKey,
+    /// A value is sealed under a key the store no longer holds.
+    MissingKey,
+    RotationInProgress,
+    NoRotation,
+    /// The background re-encryption task panicked or was cancelled.
+    RotationAborted,
+    /// The name index does not keep names, so entries cannot be listed.
+    NamesNotKept,
+    /// The name index can only be replaced while the store is empty.
+    StoreNotEmpty,
 }
 
 impl From<SecureStoreError> for AesError {
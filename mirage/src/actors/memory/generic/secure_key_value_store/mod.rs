@@ -1,77 +1,471 @@
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use std::hash::BuildHasher;
 use std::sync::{Arc, Mutex};
 use std::vec::Vec;
 
 use crate::actors::encryption::decryptor::Decryptor;
 use crate::actors::encryption::encryptor::Encryptor;
+use crate::actors::encryption::keys::hierarchy::KeyEncryptionKey;
+use crate::utils::secret::{Secret, SecretBytes};
+use crate::utils::secure_region::{self, Backend, Protection, ProtectionLevel, SecureRegion};
 
+use super::secure_hasher::SecureHasher;
+
+use tokio::sync::watch;
+use tokio::task::AbortHandle;
+
+use self::blind_index::{BlindIndex, NameIndex, NameKey};
+use self::data_keys::{DataKeys, SealedValue};
 use self::error::SecureStoreError;
+use self::rotation::{Rotation, RotationProgress, StoreKeys};
 
+pub mod blind_index;
+pub mod data_keys;
 pub mod error;
+pub mod rotation;
+
+/// How values are encrypted.
+enum Sealing {
+    /// Every value directly under the store key, which `rotate_key` replaces.
+    Direct(StoreKeys),
+    /// Under data keys wrapped by a key-encryption key.
+    DataKeys(DataKeys),
+}
+
+/// Number of slots allocated by the first insert.
+const INITIAL_CAPACITY: usize = 8;
+/// The table grows or is rebuilt once more than `MAX_LOAD_NUMERATOR /
+/// MAX_LOAD_DENOMINATOR` of its slots are occupied or tombstones.
+const MAX_LOAD_NUMERATOR: usize = 3;
+const MAX_LOAD_DENOMINATOR: usize = 4;
 
+#[derive(Clone)]
+enum Slot {
+    Empty,
+    /// A removed entry. Lookups probe past it and inserts reuse it.
+    Tombstone,
+    Occupied(BlindIndex, SealedValue),
+}
+
+/// Hash table of sealed values, using open addressing with linear probing. Entries are
+/// filed under the blind index of their name, see `blind_index`, which is hashed under
+/// a key drawn for each store, see `secure_hasher`.
 pub struct SecureKeyValueStore {
-    data: Vec<Option<(String, Vec<u8>)>>,
-    encryptor: Arc<Encryptor>,
-    decryptor: Arc<Decryptor>,
+    /// Always empty or a power of two long.
+    slots: Vec<Slot>,
+    /// Occupied slots.
+    len: usize,
+    /// Occupied slots and tombstones, which both lengthen probe sequences.
+    used: usize,
+    hasher: SecureHasher,
+    names: NameIndex,
+    sealing: Sealing,
+    /// Next slot `reseal_batch` looks at while a rotation runs. Slots before it hold no
+    /// value under an old key, until `resize` moves entries and resets it.
+    reseal_cursor: usize,
+    /// Background task of the last rotation started or resumed.
+    rotation_task: Option<AbortHandle>,
 }
 
 impl SecureKeyValueStore {
     pub fn new(encryptor: Arc<Encryptor>, decryptor: Arc<Decryptor>) -> Arc<Mutex<Self>> {
+        Self::with_sealing(Sealing::Direct(StoreKeys::new(encryptor, decryptor)))
+    }
+
+    /// Creates a store whose values are sealed with wrapped data keys, see `data_keys`.
+    pub fn with_data_keys(data_keys: DataKeys) -> Arc<Mutex<Self>> {
+        Self::with_sealing(Sealing::DataKeys(data_keys))
+    }
+
+    fn with_sealing(sealing: Sealing) -> Arc<Mutex<Self>> {
         Arc::new(Mutex::new(SecureKeyValueStore {
-            data: Vec::new(),
-            encryptor,
-            decryptor,
+            slots: Vec::new(),
+            len: 0,
+            used: 0,
+            hasher: SecureHasher::new(),
+            names: NameIndex::new(&NameKey::generate()),
+            sealing,
+            reseal_cursor: 0,
+            rotation_task: None,
         }))
     }
 
-    pub fn set(&mut self, key: String, value: Vec<u8>) -> Result<(), SecureStoreError> {
-        let encrypted_value = self
-            .encryptor
-            .encrypt(&value)
-            .map_err(|_| SecureStoreError::EncryptionError)?;
-
-        let index = self.calculate_unique_index(&key);
-        self.ensure_capacity(index);
-        self.data[index] = Some((key, encrypted_value));
+    /// Replaces the random name index every store starts with, e.g. with one under a
+    /// persisted `NameKey` or one that keeps names for `names`. Only possible while the
+    /// store is empty, since existing entries cannot be re-indexed.
+    pub fn set_name_index(&mut self, names: NameIndex) -> Result<(), SecureStoreError> {
+        if !self.is_empty() {
+            return Err(SecureStoreError::StoreNotEmpty);
+        }
+        if let Sealing::DataKeys(data_keys) = &mut self.sealing {
+            data_keys.clear_namespaces();
+        }
+        self.names = names;
         Ok(())
     }
 
-    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>, SecureStoreError> {
-        let index = self.calculate_unique_index(key);
-        if let Some((_, encrypted_value)) = &self.data.get(index).unwrap_or(&None) {
-            let decrypted_value = self
-                .decryptor
-                .decrypt(encrypted_value)
-                .map_err(|_| SecureStoreError::DecryptionError)?;
-            Ok(Some(decrypted_value))
+    pub fn backend(&self) -> Backend {
+        match &self.sealing {
+            Sealing::Direct(keys) => keys.backend(),
+            Sealing::DataKeys(data_keys) => data_keys.backend(),
+        }
+    }
+
+    /// Keeps values stored from now on, and the plaintext `get` returns, in `backend`,
+    /// e.g. `Backend::SecretMemory` for `memfd_secret` pages. Values already stored stay
+    /// where they are until they are set again or resealed by `rotate_key`.
+    pub fn set_backend(&mut self, backend: Backend) {
+        match &mut self.sealing {
+            Sealing::Direct(keys) => keys.set_backend(backend),
+            Sealing::DataKeys(data_keys) => data_keys.set_backend(backend),
+        }
+    }
+
+    /// Weakest protection any stored value actually got, or for an empty store the one a
+    /// new value would get. Below what `backend` asks for when its pages are unavailable,
+    /// see `secure_region`.
+    pub fn protection_level(&self) -> ProtectionLevel {
+        self.values()
+            .map(|value| value.envelope.protection_level())
+            .min()
+            .unwrap_or_else(|| secure_region::protection_level(self.backend()))
+    }
+
+    /// Seals and stores `value`, which is wiped afterwards.
+    pub fn set(
+        &mut self,
+        key: String,
+        value: impl Into<SecretBytes>,
+    ) -> Result<(), SecureStoreError> {
+        let value = value.into();
+        let sealed_value = match &mut self.sealing {
+            Sealing::Direct(keys) => keys
+                .seal(&value)
+                .map_err(|_| SecureStoreError::EncryptionError)?,
+            Sealing::DataKeys(data_keys) => data_keys
+                .seal(&self.names, &key, &value)
+                .map_err(|_| SecureStoreError::EncryptionError)?,
+        };
+        self.insert_named(key, sealed_value)
+    }
+
+    /// Stores `envelope`, made by `encrypt_value`, sealed once more under the store key.
+    /// Unlike values stored with `set`, both layers move to the new key on `rotate_key`.
+    pub fn set_encrypted(
+        &mut self,
+        key: String,
+        envelope: Vec<u8>,
+    ) -> Result<(), SecureStoreError> {
+        let envelope = SecretBytes::new(envelope);
+        let sealed_value = self
+            .store_keys()?
+            .seal_nested(&envelope)
+            .map_err(|_| SecureStoreError::EncryptionError)?;
+        self.insert_named(key, sealed_value)
+    }
+
+    /// The plaintext stored under `key`, in a read-only `SecureRegion`.
+    pub fn get(&self, key: &str) -> Result<Option<SecureRegion>, SecureStoreError> {
+        let index = self.names.index(key);
+        if let Some(Slot::Occupied(_, sealed_value)) = self.find(&index).map(|i| &self.slots[i]) {
+            let plaintext = match &self.sealing {
+                Sealing::Direct(keys) => keys.open(sealed_value),
+                Sealing::DataKeys(data_keys) => data_keys.open(&self.names, key, sealed_value),
+            }
+            .map_err(|_| SecureStoreError::DecryptionError)?;
+            Ok(Some(read_only(plaintext)?))
         } else {
             Ok(None)
         }
     }
 
-    fn is_empty(&self) -> bool {
-        self.data.is_empty()
+    /// Removes `key`, returning whether it was present.
+    pub fn remove(&mut self, key: &str) -> bool {
+        match self.find(&self.names.index(key)) {
+            Some(index) => {
+                self.slots[index] = Slot::Tombstone;
+                self.len -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.find(&self.names.index(key)).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
     }
 
-    fn ensure_capacity(&mut self, index: usize) {
-        if index >= self.data.len() {
-            self.data.resize(index + 1, None);
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Decrypted names of every entry, in no particular order. Fails with `NamesNotKept`
+    /// unless the name index keeps names.
+    pub fn names(&self) -> Result<Vec<Secret<String>>, SecureStoreError> {
+        if !self.names.keeps_names() {
+            return Err(SecureStoreError::NamesNotKept);
         }
+        self.entries()
+            .map(|(index, value)| {
+                let sealed = value
+                    .encrypted_name
+                    .as_ref()
+                    .ok_or(SecureStoreError::NamesNotKept)?;
+                self.names
+                    .open_name(index, sealed)
+                    .map_err(|_| SecureStoreError::DecryptionError)
+            })
+            .collect()
     }
 
-    fn calculate_unique_index<Q: ?Sized>(&self, key: &Q) -> usize
-    where
-        Q: Hash,
-    {
-        let mut hasher = DefaultHasher::new(); // Replace with a custom hasher for better security
-        key.hash(&mut hasher);
-        hasher.finish() as usize % self.data.capacity()
+    /// Re-wraps every data key under `kek`, which replaces the current key-encryption
+    /// key. Values are not re-encrypted. Returns the number of data keys re-wrapped.
+    pub fn rotate_kek(&mut self, kek: KeyEncryptionKey) -> Result<usize, SecureStoreError> {
+        let Sealing::DataKeys(data_keys) = &mut self.sealing else {
+            return Err(SecureStoreError::NoKeyHierarchy);
+        };
+        let values = self.slots.iter_mut().filter_map(|slot| match slot {
+            Slot::Occupied(_, value) => Some(value),
+            _ => None,
+        });
+        data_keys
+            .rotate_kek(kek, values)
+            .map_err(|_| SecureStoreError::KeyWrapError)
     }
-}
 
-// Implement custom hasher as needed
+    /// Encrypts `data` under the store key without storing it.
+    pub fn encrypt_value(&self, data: &[u8]) -> Result<Vec<u8>, SecureStoreError> {
+        self.store_keys()?
+            .encrypt(data)
+            .map_err(|_| SecureStoreError::EncryptionError)
+    }
+
+    /// Decrypts an envelope made by `encrypt_value`, also mid-rotation.
+    pub fn decrypt_value(&self, envelope: &[u8]) -> Result<SecureRegion, SecureStoreError> {
+        let plaintext = self
+            .store_keys()?
+            .decrypt(envelope)
+            .map_err(|_| SecureStoreError::DecryptionError)?;
+        read_only(plaintext)
+    }
+
+    /// Installs the key of `encryptor` and `decryptor` and re-encrypts every value
+    /// under it in the background, `batch_size` values at a time. The store stays
+    /// usable throughout: reads open values under either key and writes use the new
+    /// one. The old key is released once no value is sealed under it anymore.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn rotate_key(
+        store: &Arc<Mutex<Self>>,
+        encryptor: Arc<Encryptor>,
+        decryptor: Arc<Decryptor>,
+        batch_size: usize,
+    ) -> Result<Rotation, SecureStoreError> {
+        let mut guard = store.lock().unwrap();
+        guard.check_rotation_task()?;
+        let Sealing::Direct(keys) = &mut guard.sealing else {
+            return Err(SecureStoreError::NoStoreKey);
+        };
+        keys.begin_rotation(encryptor, decryptor)?;
+        guard.spawn_rotation(store, batch_size)
+    }
+
+    /// Restarts the re-encryption of a rotation whose background task failed. Fails
+    /// with `RotationInProgress` while that task is still running.
+    pub fn resume_rotation(
+        store: &Arc<Mutex<Self>>,
+        batch_size: usize,
+    ) -> Result<Rotation, SecureStoreError> {
+        let mut guard = store.lock().unwrap();
+        guard.check_rotation_task()?;
+        if !guard.store_keys()?.is_rotating() {
+            return Err(SecureStoreError::NoRotation);
+        }
+        guard.spawn_rotation(store, batch_size)
+    }
+
+    /// Rejects starting a second re-encryption task next to a live one.
+    fn check_rotation_task(&self) -> Result<(), SecureStoreError> {
+        match &self.rotation_task {
+            Some(task) if !task.is_finished() => Err(SecureStoreError::RotationInProgress),
+            _ => Ok(()),
+        }
+    }
+
+    /// Spawns the task re-encrypting every value still under an old key. The caller
+    /// holds the lock of `store`, which is `self`.
+    fn spawn_rotation(
+        &mut self,
+        store: &Arc<Mutex<Self>>,
+        batch_size: usize,
+    ) -> Result<Rotation, SecureStoreError> {
+        let keys = self.store_keys()?;
+        let total = self
+            .values()
+            .filter(|value| keys.needs_reseal(value))
+            .count();
+        self.reseal_cursor = 0;
+
+        let (sender, receiver) = watch::channel(RotationProgress {
+            total,
+            re_encrypted: 0,
+            finished: false,
+        });
+        let store = Arc::clone(store);
+        let batch_size = batch_size.max(1);
+        let task = tokio::spawn(async move {
+            loop {
+                let (re_encrypted, finished) = store.lock().unwrap().reseal_batch(batch_size)?;
+                sender.send_modify(|progress| {
+                    progress.re_encrypted += re_encrypted;
+                    progress.finished = finished;
+                });
+                if finished {
+                    return Ok(());
+                }
+                tokio::task::yield_now().await;
+            }
+        });
+        self.rotation_task = Some(task.abort_handle());
+        Ok(Rotation::new(receiver, task))
+    }
+
+    /// Re-encrypts up to `batch_size` values still sealed under an old key, continuing
+    /// where the previous batch stopped. Releases the old key and reports `true` once
+    /// none is left.
+    fn reseal_batch(&mut self, batch_size: usize) -> Result<(usize, bool), SecureStoreError> {
+        let Sealing::Direct(keys) = &mut self.sealing else {
+            return Err(SecureStoreError::NoStoreKey);
+        };
+
+        let mut re_encrypted = 0;
+        while let Some(slot) = self.slots.get_mut(self.reseal_cursor) {
+            if let Slot::Occupied(_, value) = slot {
+                if re_encrypted == batch_size {
+                    return Ok((re_encrypted, false));
+                }
+                if keys.needs_reseal(value) {
+                    keys.reseal(value)
+                        .map_err(|_| SecureStoreError::EncryptionError)?;
+                    re_encrypted += 1;
+                }
+            }
+            self.reseal_cursor += 1;
+        }
+
+        keys.finish_rotation();
+        Ok((re_encrypted, true))
+    }
+
+    fn store_keys(&self) -> Result<&StoreKeys, SecureStoreError> {
+        match &self.sealing {
+            Sealing::Direct(keys) => Ok(keys),
+            Sealing::DataKeys(_) => Err(SecureStoreError::NoStoreKey),
+        }
+    }
+
+    fn values(&self) -> impl Iterator<Item = &SealedValue> {
+        self.entries().map(|(_, value)| value)
+    }
 
-// Usage example
-// let secure_store = SecureKeyValueStore::new(Arc::new(Encryptor::new(...)), Arc::new(Decryptor::new(...)));
-// let result = secure_store.lock().unwrap().set("key".to_string(), vec![1, 2, 3]);
+    fn entries(&self) -> impl Iterator<Item = (&BlindIndex, &SealedValue)> {
+        self.slots.iter().filter_map(|slot| match slot {
+            Slot::Occupied(name, value) => Some((name, value)),
+            _ => None,
+        })
+    }
+
+    /// Slot holding `key`, if any.
+    fn find(&self, key: &BlindIndex) -> Option<usize> {
+        if self.slots.is_empty() {
+            return None;
+        }
+        let mask = self.slots.len() - 1;
+        let mut index = self.home_index(key);
+        // The load factor guarantees at least one empty slot, which ends every probe.
+        loop {
+            match &self.slots[index] {
+                Slot::Empty => return None,
+                Slot::Occupied(stored, _) if stored == key => return Some(index),
+                _ => index = (index + 1) & mask,
+            }
+        }
+    }
+
+    /// Files `value` under the blind index of `key`, with the name sealed when kept.
+    fn insert_named(
+        &mut self,
+        key: String,
+        mut value: SealedValue,
+    ) -> Result<(), SecureStoreError> {
+        let index = self.names.index(&key);
+        value.encrypted_name = self
+            .names
+            .seal_name(&index, &key)
+            .map_err(|_| SecureStoreError::EncryptionError)?;
+        self.insert(index, value);
+        Ok(())
+    }
+
+    /// Stores `value` under `key`, replacing an existing entry.
+    fn insert(&mut self, key: BlindIndex, value: SealedValue) {
+        if let Some(index) = self.find(&key) {
+            self.slots[index] = Slot::Occupied(key, value);
+            return;
+        }
+
+        if (self.used + 1) * MAX_LOAD_DENOMINATOR > self.slots.len() * MAX_LOAD_NUMERATOR {
+            self.resize();
+        }
+        let mask = self.slots.len() - 1;
+        let mut index = self.home_index(&key);
+        while let Slot::Occupied(..) = self.slots[index] {
+            index = (index + 1) & mask;
+        }
+        if let Slot::Empty = self.slots[index] {
+            self.used += 1;
+        }
+        self.slots[index] = Slot::Occupied(key, value);
+        self.len += 1;
+    }
+
+    /// Rehashes every entry into a table with room for one more, doubling the capacity
+    /// unless dropping the tombstones frees enough space.
+    fn resize(&mut self) {
+        let mut capacity = self.slots.len().max(INITIAL_CAPACITY);
+        while (self.len + 1) * MAX_LOAD_DENOMINATOR * 2 > capacity * MAX_LOAD_NUMERATOR {
+            capacity *= 2;
+        }
+
+        let slots = std::mem::replace(&mut self.slots, vec![Slot::Empty; capacity]);
+        let mask = capacity - 1;
+        for slot in slots {
+            if let Slot::Occupied(key, value) = slot {
+                let mut index = self.home_index(&key);
+                while let Slot::Occupied(..) = self.slots[index] {
+                    index = (index + 1) & mask;
+                }
+                self.slots[index] = Slot::Occupied(key, value);
+            }
+        }
+        self.used = self.len;
+        // Entries moved, possibly to slots a running rotation already passed.
+        self.reseal_cursor = 0;
+    }
+
+    /// First slot probed for `key`.
+    fn home_index(&self, key: &BlindIndex) -> usize {
+        self.hasher.hash_one(key) as usize & (self.slots.len() - 1)
+    }
+}
+
+/// Seals the plaintext `region`, decrypted in place, against writes.
+fn read_only(region: SecureRegion) -> Result<SecureRegion, SecureStoreError> {
+    region
+        .set_protection(Protection::ReadOnly)
+        .map_err(|_| SecureStoreError::AllocationError)?;
+    Ok(region)
+}
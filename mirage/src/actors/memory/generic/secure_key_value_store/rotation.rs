@@ -0,0 +1,227 @@
+//! Key rotation for stores that seal values directly under one key.
+//!
+//! A rotation installs the new key as the current one and keeps the old key around for
+//! reading until every value was re-encrypted, so reads never fail mid-rotation. Once
+//! the last value moved, the old key is released; `Encryptor` and `Decryptor` wipe
+//! their key when their last clone is dropped.
+
+use std::sync::Arc;
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::actors::encryption::decryptor::Decryptor;
+use crate::actors::encryption::encryptor::Encryptor;
+use crate::actors::encryption::AesError;
+use crate::utils::secret::SecretBytes;
+use crate::utils::secure_region::{Backend, SecureRegion};
+
+use super::data_keys::{hide_envelope, SealedValue};
+use super::error::SecureStoreError;
+
+struct KeySlot {
+    version: u32,
+    encryptor: Arc<Encryptor>,
+    decryptor: Arc<Decryptor>,
+}
+
+/// The key a store seals with, plus the previous one while a rotation runs.
+pub struct StoreKeys {
+    /// Oldest first; the last slot is the current key.
+    slots: Vec<KeySlot>,
+    /// Where envelopes are kept.
+    backend: Backend,
+}
+
+impl StoreKeys {
+    pub fn new(encryptor: Arc<Encryptor>, decryptor: Arc<Decryptor>) -> Self {
+        StoreKeys {
+            slots: vec![KeySlot {
+                version: 0,
+                encryptor,
+                decryptor,
+            }],
+            backend: Backend::default(),
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Keeps envelopes sealed or resealed from now on in `backend`.
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    /// Incremented by every rotation.
+    pub fn current_version(&self) -> u32 {
+        self.current().version
+    }
+
+    pub fn is_rotating(&self) -> bool {
+        self.slots.len() > 1
+    }
+
+    /// Seals `value` under the current key.
+    pub fn seal(&self, value: &[u8]) -> Result<SealedValue, AesError> {
+        let current = self.current();
+        Ok(SealedValue {
+            data_key: None,
+            key_version: current.version,
+            envelope: hide_envelope(&current.encryptor.encrypt(value)?, self.backend)?,
+            encrypted_name: None,
+            nested: false,
+        })
+    }
+
+    /// Seals `envelope`, made by `encrypt` under the current key, and marks the value as
+    /// nested so `reseal` re-encrypts both layers.
+    pub fn seal_nested(&self, envelope: &[u8]) -> Result<SealedValue, AesError> {
+        Ok(SealedValue {
+            nested: true,
+            ..self.seal(envelope)?
+        })
+    }
+
+    /// Opens `value` with the key it was sealed under, in place inside a region from
+    /// `backend`.
+    pub fn open(&self, value: &SealedValue) -> Result<SecureRegion, AesError> {
+        let slot = self
+            .slots
+            .iter()
+            .find(|slot| slot.version == value.key_version)
+            .ok_or(SecureStoreError::MissingKey)?;
+        value.envelope.read(|envelope| {
+            slot.decryptor
+                .decrypt_to_region(envelope, &[], self.backend)
+        })?
+    }
+
+    /// Encrypts a bare envelope under the current key.
+    pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, AesError> {
+        self.current().encryptor.encrypt(data)
+    }
+
+    /// Opens a bare envelope into a region from `backend`, trying the current key first.
+    pub fn decrypt(&self, envelope: &[u8]) -> Result<SecureRegion, AesError> {
+        let mut result = Err(SecureStoreError::MissingKey.into());
+        for slot in self.slots.iter().rev() {
+            result = slot
+                .decryptor
+                .decrypt_to_region(envelope, &[], self.backend);
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+
+    /// Makes the key of `encryptor` and `decryptor` the current one. The previous key
+    /// keeps opening values until `finish_rotation`.
+    pub fn begin_rotation(
+        &mut self,
+        encryptor: Arc<Encryptor>,
+        decryptor: Arc<Decryptor>,
+    ) -> Result<u32, SecureStoreError> {
+        if self.is_rotating() {
+            return Err(SecureStoreError::RotationInProgress);
+        }
+        let version = self.current_version().wrapping_add(1);
+        self.slots.push(KeySlot {
+            version,
+            encryptor,
+            decryptor,
+        });
+        Ok(version)
+    }
+
+    pub fn needs_reseal(&self, value: &SealedValue) -> bool {
+        value.key_version != self.current_version()
+    }
+
+    /// Re-encrypts `value` under the current key. The inner envelope of a value sealed by
+    /// `seal_nested`, such as a fragment sealed by `SecureMemoryProvider::encrypt_fragment`,
+    /// is re-encrypted as well.
+    pub fn reseal(&self, value: &mut SealedValue) -> Result<(), AesError> {
+        let old = self
+            .slots
+            .iter()
+            .find(|slot| slot.version == value.key_version)
+            .ok_or(SecureStoreError::MissingKey)?;
+        let envelope = value.envelope.read(|envelope| {
+            Self::reseal_envelope(old, self.current(), envelope, value.nested)
+        })??;
+        value.envelope = hide_envelope(&envelope, self.backend)?;
+        value.key_version = self.current_version();
+        Ok(())
+    }
+
+    /// Drops every key but the current one.
+    pub fn finish_rotation(&mut self) {
+        let current = self.slots.len() - 1;
+        self.slots.drain(..current);
+    }
+
+    fn current(&self) -> &KeySlot {
+        self.slots.last().expect("a store always has a key")
+    }
+
+    fn reseal_envelope(
+        old: &KeySlot,
+        new: &KeySlot,
+        envelope: &[u8],
+        nested: bool,
+    ) -> Result<Vec<u8>, AesError> {
+        let plaintext = SecretBytes::new(old.decryptor.decrypt(envelope)?);
+        if nested {
+            let inner = Self::reseal_envelope(old, new, &plaintext, false)?;
+            return new.encryptor.encrypt(&inner);
+        }
+        new.encryptor.encrypt(&plaintext)
+    }
+}
+
+/// How far a rotation got.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RotationProgress {
+    /// Values sealed under an old key when the rotation started.
+    pub total: usize,
+    pub re_encrypted: usize,
+    /// Set once every value moved and the old key was released.
+    pub finished: bool,
+}
+
+/// A rotation running in the background, see `SecureKeyValueStore::rotate_key`.
+pub struct Rotation {
+    progress: watch::Receiver<RotationProgress>,
+    task: JoinHandle<Result<(), SecureStoreError>>,
+}
+
+impl Rotation {
+    pub(super) fn new(
+        progress: watch::Receiver<RotationProgress>,
+        task: JoinHandle<Result<(), SecureStoreError>>,
+    ) -> Self {
+        Rotation { progress, task }
+    }
+
+    pub fn progress(&self) -> RotationProgress {
+        *self.progress.borrow()
+    }
+
+    /// Receiver notified after every batch.
+    pub fn subscribe(&self) -> watch::Receiver<RotationProgress> {
+        self.progress.clone()
+    }
+
+    /// Waits for the rotation to finish. On failure both keys stay installed, so every
+    /// value remains readable, and the rotation can be resumed with
+    /// `SecureKeyValueStore::resume_rotation`.
+    pub async fn wait(self) -> Result<RotationProgress, SecureStoreError> {
+        self.task
+            .await
+            .map_err(|_| SecureStoreError::RotationAborted)??;
+        Ok(*self.progress.borrow())
+    }
+}
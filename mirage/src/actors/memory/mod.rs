@@ -3,6 +3,7 @@ pub mod secure_memory_provider;
 
 use crate::actors::encryption::AesError;
 use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub enum Input {
@@ -10,12 +11,125 @@ pub enum Input {
     Bit(u8),
 }
 
+impl Zeroize for Input {
+    fn zeroize(&mut self) {
+        match self {
+            Input::Buffer(data) => data.zeroize(),
+            Input::Bit(bit) => bit.zeroize(),
+        }
+    }
+}
+
+/// Version of the binary encoding written by `Input::serialize`.
+pub const INPUT_ENCODING_VERSION: u8 = 1;
+
+const BUFFER_TAG: u8 = 0;
+const BIT_TAG: u8 = 1;
+/// First byte of the JSON arrays written before the binary encoding existed.
+const LEGACY_JSON_START: u8 = b'[';
+
 impl Input {
+    /// Encodes `inputs` as version 1:
+    ///
+    /// | Size | Field                                   |
+    /// |------|-----------------------------------------|
+    /// | 1    | version, `INPUT_ENCODING_VERSION`       |
+    ///
+    /// followed by every input in order, either
+    ///
+    /// | Size | Field                                   |
+    /// |------|-----------------------------------------|
+    /// | 1    | `0`, a buffer                           |
+    /// | 4    | length `n`, little endian               |
+    /// | n    | bytes                                   |
+    ///
+    /// or
+    ///
+    /// | Size | Field                                   |
+    /// |------|-----------------------------------------|
+    /// | 1    | `1`, a bit                              |
+    /// | 1    | value                                   |
+    ///
+    /// The output is allocated at its final size, so no partial copy of the plaintext is
+    /// left behind by a reallocation.
     pub fn serialize(inputs: &[Input]) -> Result<Vec<u8>, AesError> {
-        serde_json::to_vec(inputs).map_err(|e| AesError::SerializeError(e.to_string()))
+        let length = 1 + inputs.iter().map(Input::encoded_length).sum::<usize>();
+        let mut encoded = Vec::with_capacity(length);
+        encoded.push(INPUT_ENCODING_VERSION);
+        for input in inputs {
+            match input {
+                Input::Buffer(data) => {
+                    let data_length = u32::try_from(data.len()).map_err(|_| {
+                        AesError::SerializeError(format!("buffer of {} bytes", data.len()))
+                    })?;
+                    encoded.push(BUFFER_TAG);
+                    encoded.extend_from_slice(&data_length.to_le_bytes());
+                    encoded.extend_from_slice(data);
+                }
+                Input::Bit(bit) => encoded.extend_from_slice(&[BIT_TAG, *bit]),
+            }
+        }
+        Ok(encoded)
     }
 
+    /// Decodes the output of `serialize`, or the JSON written by earlier releases.
     pub fn deserialize(data: &[u8]) -> Result<Vec<Input>, AesError> {
-        serde_json::from_slice(data).map_err(|e| AesError::DeserializeError(e.to_string()))
+        let Some((&version, mut rest)) = data.split_first() else {
+            return Err(AesError::DeserializeError(
+                "no encoding version".to_string(),
+            ));
+        };
+        match version {
+            INPUT_ENCODING_VERSION => {}
+            LEGACY_JSON_START => {
+                return serde_json::from_slice(data)
+                    .map_err(|e| AesError::DeserializeError(e.to_string()))
+            }
+            version => {
+                return Err(AesError::DeserializeError(format!(
+                    "unsupported encoding version {}",
+                    version
+                )))
+            }
+        }
+
+        let mut inputs = Vec::new();
+        while let Some((&tag, tail)) = rest.split_first() {
+            let input = match tag {
+                BUFFER_TAG => {
+                    let length = take(tail, 4)?;
+                    let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+                    let buffer = take(&tail[4..], length)?;
+                    rest = &tail[4 + length..];
+                    Input::Buffer(buffer.to_vec())
+                }
+                BIT_TAG => {
+                    let bit = take(tail, 1)?[0];
+                    rest = &tail[1..];
+                    Input::Bit(bit)
+                }
+                tag => {
+                    return Err(AesError::DeserializeError(format!(
+                        "unknown input tag {}",
+                        tag
+                    )))
+                }
+            };
+            inputs.push(input);
+        }
+        Ok(inputs)
+    }
+
+    fn encoded_length(&self) -> usize {
+        match self {
+            Input::Buffer(data) => 1 + 4 + data.len(),
+            Input::Bit(_) => 2,
+        }
     }
 }
+
+/// First `length` bytes of `data`.
+fn take(data: &[u8], length: usize) -> Result<&[u8], AesError> {
+    data.get(..length)
+        .ok_or_else(|| AesError::DeserializeError("truncated input".to_string()))
+}
@@ -1,8 +1,11 @@
+use crate::actors::encryption::AesError;
 use crate::actors::memory::generic::secure_key_value_store::error::SecureStoreError;
 
 #[derive(Debug)]
 pub enum SecureMemoryProviderError {
     StoreError(SecureStoreError),
+    /// A `PlaintextGuard` was read after its timeout and has been wiped.
+    PlaintextExpired,
 }
 
 impl From<SecureStoreError> for SecureMemoryProviderError {
@@ -10,3 +13,9 @@ impl From<SecureStoreError> for SecureMemoryProviderError {
         SecureMemoryProviderError::StoreError(err)
     }
 }
+
+impl From<SecureMemoryProviderError> for AesError {
+    fn from(err: SecureMemoryProviderError) -> Self {
+        AesError::SecureMemoryProviderError(err)
+    }
+}
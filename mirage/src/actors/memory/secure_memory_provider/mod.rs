@@ -1,26 +1,41 @@
-use super::generic::secure_key_value_store::error::SecureStoreError;
+use self::plaintext::PlaintextGuard;
+use super::generic::secure_key_value_store::rotation::Rotation;
 use super::generic::secure_key_value_store::SecureKeyValueStore;
 use super::Input;
 use crate::actors::encryption::decryptor::Decryptor;
 use crate::actors::encryption::encryptor::Encryptor;
+use crate::actors::encryption::keyring::Keyring;
 use crate::actors::encryption::AesError;
 use crate::actors::Actor;
-use crate::utils::key_generator::{generate_aes_key, generate_nonce};
+use crate::utils::key_generator::generate_aes_key;
+use crate::utils::secret::{Secret, SecretBytes, SecretKey};
+use crate::utils::secure_region::{Backend, ProtectionLevel, SecureRegion};
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use zeroize::Zeroize;
 
 pub mod error;
+pub mod plaintext;
+
+/// Version of the encoding `set_typed` writes, its first byte.
+pub const TYPED_ENCODING_VERSION: u8 = 1;
 
 pub struct SecureMemoryProvider {
     fragments: Arc<Mutex<SecureKeyValueStore>>,
-    encryptor: Arc<Encryptor>,
-    decryptor: Arc<Decryptor>,
+    /// How long a `PlaintextGuard` stays readable, if limited.
+    plaintext_timeout: Option<Duration>,
 }
 
-trait Encryption {
+pub trait Encryption {
     fn encrypt_fragment(&self, id: String) -> Result<(), AesError>;
-    fn decrypt_fragment(&self, id: String) -> Result<Option<Vec<u8>>, AesError>;
+    fn decrypt_fragment(&self, id: String) -> Result<Option<SecureRegion>, AesError>;
 }
 
+// Not implemented yet, so kept out of the public API.
+#[allow(dead_code)]
 trait Mitigation {
     fn scramble_fragment(&mut self);
     fn tamper_adjacent_blocks(&mut self);
@@ -32,19 +47,18 @@ impl Encryption for SecureMemoryProvider {
     fn encrypt_fragment(&self, id: String) -> Result<(), AesError> {
         let mut fragments = self.fragments.lock().unwrap();
         if let Some(data) = fragments.get(&id).map_err(AesError::from)? {
-            let encrypted_data = self.encryptor.encrypt(&data).map_err(AesError::from)?;
-            fragments.set(id, encrypted_data).map_err(AesError::from)?;
+            let encrypted_data = fragments.encrypt_value(&data)?;
+            fragments
+                .set_encrypted(id, encrypted_data)
+                .map_err(AesError::from)?;
         }
         Ok(())
     }
 
-    fn decrypt_fragment(&self, id: String) -> Result<Option<Vec<u8>>, AesError> {
+    fn decrypt_fragment(&self, id: String) -> Result<Option<SecureRegion>, AesError> {
         let fragments = self.fragments.lock().unwrap();
         if let Some(encrypted_data) = fragments.get(&id).map_err(AesError::from)? {
-            let decrypted_data = self
-                .decryptor
-                .decrypt(&encrypted_data)
-                .map_err(AesError::from)?;
+            let decrypted_data = fragments.decrypt_value(&encrypted_data)?;
             Ok(Some(decrypted_data))
         } else {
             Ok(None)
@@ -71,70 +85,211 @@ impl Mitigation for SecureMemoryProvider {
 }
 
 impl Actor<Input> for SecureMemoryProvider {
+    type Output = ();
+
     fn is_alive(&self) -> bool {
         true
     }
 
-    fn run(&self, input: Option<Input>) {
+    fn run(&self, _input: Option<Input>) {
         // Implementation goes here...
     }
 }
 
+impl Default for SecureMemoryProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SecureMemoryProvider {
     pub fn new() -> Self {
         let key = generate_aes_key();
-        let nonce = generate_nonce();
         let runtime_handle = Arc::new(tokio::runtime::Handle::try_current().unwrap());
 
-        let encryptor = Arc::new(Encryptor::new(
-            None,
-            key.clone(),
-            nonce.clone(),
-            runtime_handle.clone(),
-        ));
-        let decryptor = Arc::new(Decryptor::new(
-            None,
-            key.clone(),
-            nonce.clone(),
+        let encryptor = Arc::new(Encryptor::new(None, key.clone(), runtime_handle.clone()));
+        let decryptor = Arc::new(Decryptor::new(None, key, runtime_handle));
+
+        Self::with_actors(encryptor, decryptor)
+    }
+
+    /// Creates a provider that seals fragments with the keyring key `name` instead of a
+    /// throwaway random key, so they stay readable across restarts and rotations.
+    pub fn with_keyring(keyring: &Keyring, name: &str) -> Result<Self, AesError> {
+        let runtime_handle = Arc::new(tokio::runtime::Handle::try_current().unwrap());
+
+        let encryptor = Arc::new(Encryptor::from_keyring(
+            keyring,
+            name,
             runtime_handle.clone(),
-        ));
+        )?);
+        let decryptor = Arc::new(Decryptor::with_keyring(keyring.clone(), runtime_handle));
+
+        Ok(Self::with_actors(encryptor, decryptor))
+    }
 
+    fn with_actors(encryptor: Arc<Encryptor>, decryptor: Arc<Decryptor>) -> Self {
         SecureMemoryProvider {
-            fragments: SecureKeyValueStore::new(encryptor.clone(), decryptor.clone()),
+            fragments: SecureKeyValueStore::new(encryptor, decryptor),
+            plaintext_timeout: None,
+        }
+    }
+
+    /// Replaces the key with `key` and re-encrypts every fragment under it in the
+    /// background, see `SecureKeyValueStore::rotate_key`. Fragments stay readable
+    /// throughout and the old key is wiped once none is sealed under it.
+    pub fn rotate_key(&self, key: SecretKey, batch_size: usize) -> Result<Rotation, AesError> {
+        let runtime_handle = Arc::new(tokio::runtime::Handle::try_current().unwrap());
+
+        let encryptor = Arc::new(Encryptor::new(None, key.clone(), runtime_handle.clone()));
+        let decryptor = Arc::new(Decryptor::new(None, key, runtime_handle));
+        Ok(SecureKeyValueStore::rotate_key(
+            &self.fragments,
             encryptor,
             decryptor,
-        }
+            batch_size,
+        )?)
+    }
+
+    /// Keeps fragments stored from now on in `backend`, see
+    /// `SecureKeyValueStore::set_backend`.
+    pub fn set_backend(&self, backend: Backend) {
+        self.fragments.lock().unwrap().set_backend(backend);
+    }
+
+    /// Protection the stored fragments actually got.
+    pub fn protection_level(&self) -> ProtectionLevel {
+        self.fragments.lock().unwrap().protection_level()
+    }
+
+    /// Limits how long guards returned by `plaintext` stay readable; `None`, the default,
+    /// keeps them readable until dropped.
+    pub fn set_plaintext_timeout(&mut self, timeout: Option<Duration>) {
+        self.plaintext_timeout = timeout;
+    }
+
+    /// Calls `f` with the plaintext stored under `id`, as sealed by `set` or `set_typed`,
+    /// and returns its result. The plaintext is decrypted in place into locked memory,
+    /// never passes through the ordinary heap and is wiped once `f` returns.
+    pub fn read<R>(&self, id: &str, f: impl FnOnce(&[u8]) -> R) -> Result<Option<R>, AesError> {
+        let plaintext = self.fragments.lock().unwrap().get(id)?;
+        Ok(plaintext.map(|plaintext| f(&plaintext)))
+    }
+
+    /// The plaintext stored under `id`, lent out by a guard that wipes it when dropped
+    /// or once the plaintext timeout has passed, see `set_plaintext_timeout`.
+    pub fn plaintext(&self, id: &str) -> Result<Option<PlaintextGuard<'_>>, AesError> {
+        let plaintext = self.fragments.lock().unwrap().get(id)?;
+        plaintext
+            .map(|plaintext| PlaintextGuard::new(plaintext, self.plaintext_timeout))
+            .transpose()
     }
 
-    pub fn get(&self, id: &str) -> Result<Option<Vec<Input>>, AesError> {
+    /// The inputs stored under `id`. They are wiped when the result is dropped, but
+    /// live in ordinary memory until then; `read` and `plaintext` avoid the copy.
+    pub fn get(&self, id: &str) -> Result<Option<Secret<Vec<Input>>>, AesError> {
         let fragments = self.fragments.lock().unwrap();
         if let Some(encrypted_data) = fragments.get(id)? {
             let data: Vec<Input> = Input::deserialize(&encrypted_data)?; // Ensure correct type
-            Ok(Some(data))
+            Ok(Some(Secret::new(data)))
         } else {
             Ok(None)
         }
     }
 
-    pub fn set(&mut self, id: String, data: Vec<Input>) -> Result<(), SecureStoreError> {
-        let serialized_data: Vec<u8> = Input::serialize(&data)?; // Ensure correct type
-        let mut fragments = self.fragments.lock().unwrap();
-        fragments.set(id, serialized_data)
+    /// Stores `data` under `id` and wipes it.
+    pub fn set(&mut self, id: String, data: Vec<Input>) -> Result<(), AesError> {
+        self.store(id, &Secret::new(data))
+    }
+
+    pub fn push(&mut self, id: String, data: Input) -> Result<(), AesError> {
+        let mut inputs = self.get(&id)?.unwrap_or_default();
+        inputs.push(data);
+        self.store(id, &inputs)
+    }
+
+    pub fn pop(&mut self, id: &str) -> Result<Option<Input>, AesError> {
+        let Some(mut inputs) = self.get(id)? else {
+            return Ok(None);
+        };
+        let data = inputs.pop();
+        self.store(id.to_owned(), &inputs)?;
+        Ok(data)
     }
 
-    pub fn push(&mut self, id: String, data: Input) -> Result<(), SecureStoreError> {
-        let serialized_data: Vec<u8> = Input::serialize(&[data])?; // Ensure correct type
+    /// Stores `value` under `id`, e.g. a credentials struct or a token bundle, encoded
+    /// as `TYPED_ENCODING_VERSION`:
+    ///
+    /// | Size | Field                                   |
+    /// |------|-----------------------------------------|
+    /// | 1    | version, `TYPED_ENCODING_VERSION`       |
+    /// | n    | `value` in bincode's default options    |
+    ///
+    /// The encoded form is allocated at its final size and wiped once sealed.
+    pub fn set_typed<T: Serialize + ?Sized>(
+        &mut self,
+        id: String,
+        value: &T,
+    ) -> Result<(), AesError> {
+        let serialized_data = encode_typed(value)?;
         let mut fragments = self.fragments.lock().unwrap();
-        fragments.push(id, serialized_data)
+        Ok(fragments.set(id, serialized_data)?)
     }
 
-    pub fn pop(&mut self, id: &str) -> Result<Option<Input>, SecureStoreError> {
+    /// The value stored under `id` by `set_typed`, wiped when the result is dropped.
+    /// It lives in ordinary memory until then; `get_with` avoids keeping it around.
+    pub fn get_typed<T>(&self, id: &str) -> Result<Option<Secret<T>>, AesError>
+    where
+        T: DeserializeOwned + Zeroize,
+    {
+        self.read(id, decode_typed)?.transpose()
+    }
+
+    /// Calls `f` with the value stored under `id` by `set_typed` and returns its result.
+    /// The value is decoded straight from locked memory, only lives for the duration of
+    /// the call and is wiped afterwards.
+    pub fn get_with<T, R>(&self, id: &str, f: impl FnOnce(&T) -> R) -> Result<Option<R>, AesError>
+    where
+        T: DeserializeOwned + Zeroize,
+    {
+        self.read(id, |plaintext| {
+            decode_typed::<T>(plaintext).map(|value| f(&value))
+        })?
+        .transpose()
+    }
+
+    fn store(&self, id: String, data: &[Input]) -> Result<(), AesError> {
+        let serialized_data = SecretBytes::new(Input::serialize(data)?);
         let mut fragments = self.fragments.lock().unwrap();
-        if let Some(encrypted_data) = fragments.pop(id)? {
-            let mut data: Vec<Input> = Input::deserialize(&encrypted_data)?;
-            Ok(data.pop())
-        } else {
-            Ok(None)
-        }
+        Ok(fragments.set(id, serialized_data)?)
+    }
+}
+
+fn encode_typed<T: Serialize + ?Sized>(value: &T) -> Result<SecretBytes, AesError> {
+    let options = bincode::DefaultOptions::new();
+    let length = options
+        .serialized_size(value)
+        .map_err(|e| AesError::SerializeError(e.to_string()))?;
+    let mut encoded = SecretBytes::new(Vec::with_capacity(1 + length as usize));
+    encoded.push(TYPED_ENCODING_VERSION);
+    options
+        .serialize_into(&mut *encoded, value)
+        .map_err(|e| AesError::SerializeError(e.to_string()))?;
+    Ok(encoded)
+}
+
+fn decode_typed<T: DeserializeOwned + Zeroize>(data: &[u8]) -> Result<Secret<T>, AesError> {
+    match data.split_first() {
+        Some((&TYPED_ENCODING_VERSION, body)) => bincode::DefaultOptions::new()
+            .deserialize(body)
+            .map(Secret::new)
+            .map_err(|e| AesError::DeserializeError(e.to_string())),
+        Some((version, _)) => Err(AesError::DeserializeError(format!(
+            "unsupported encoding version {}",
+            version
+        ))),
+        None => Err(AesError::DeserializeError(
+            "no encoding version".to_string(),
+        )),
     }
 }
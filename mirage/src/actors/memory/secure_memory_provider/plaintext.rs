@@ -0,0 +1,110 @@
+//! Borrowed access to decrypted fragments.
+//!
+//! A `PlaintextGuard` holds one fragment decrypted into a locked `SecureRegion` and
+//! lends it out through `read` only. Between reads the region is `Protection::NoAccess`,
+//! so a stray pointer faults instead of reading it. The plaintext is wiped when the
+//! guard is dropped or, when the provider sets a timeout, by a task on the runtime as
+//! soon as it expires, whether or not it is read again. The guard borrows the provider,
+//! so it cannot outlive it.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::debug;
+use tokio::task::JoinHandle;
+
+use crate::actors::encryption::AesError;
+use crate::utils::secure_region::{Protection, SecureRegion};
+
+use super::error::SecureMemoryProviderError;
+use super::SecureMemoryProvider;
+
+/// A decrypted fragment, readable until it is dropped or expires.
+pub struct PlaintextGuard<'a> {
+    /// `None` once expired and wiped. Shared with the task that wipes it.
+    plaintext: Arc<Mutex<Option<SecureRegion>>>,
+    expires_at: Option<Instant>,
+    /// Wipes the plaintext at `expires_at`, aborted when the guard is dropped first.
+    wipe: Option<JoinHandle<()>>,
+    _provider: PhantomData<&'a SecureMemoryProvider>,
+}
+
+impl<'a> PlaintextGuard<'a> {
+    pub(super) fn new(
+        plaintext: SecureRegion,
+        timeout: Option<Duration>,
+    ) -> Result<Self, AesError> {
+        plaintext.set_protection(Protection::NoAccess)?;
+        let plaintext = Arc::new(Mutex::new(Some(plaintext)));
+        let expires_at = timeout.map(|timeout| Instant::now() + timeout);
+        let wipe = expires_at.map(|expires_at| {
+            let plaintext = Arc::clone(&plaintext);
+            tokio::spawn(async move {
+                tokio::time::sleep_until(expires_at.into()).await;
+                wipe(&plaintext);
+            })
+        });
+        Ok(PlaintextGuard {
+            plaintext,
+            expires_at,
+            wipe,
+            _provider: PhantomData,
+        })
+    }
+
+    /// Calls `f` with the plaintext, which is only readable for the duration of the call.
+    /// Fails once the guard has expired.
+    pub fn read<R>(&self, f: impl FnOnce(&[u8]) -> R) -> Result<R, AesError> {
+        if self.is_expired() {
+            // The wipe task may not have run yet.
+            wipe(&self.plaintext);
+            return Err(SecureMemoryProviderError::PlaintextExpired.into());
+        }
+        let plaintext = self.plaintext.lock().unwrap();
+        let plaintext = plaintext
+            .as_ref()
+            .ok_or(SecureMemoryProviderError::PlaintextExpired)?;
+        Ok(plaintext.read(f)?)
+    }
+
+    pub fn expires_at(&self) -> Option<Instant> {
+        self.expires_at
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| Instant::now() >= expires_at)
+    }
+
+    /// Whether the plaintext has been wiped, which happens once the guard expires.
+    pub fn is_wiped(&self) -> bool {
+        self.plaintext.lock().unwrap().is_none()
+    }
+}
+
+impl Drop for PlaintextGuard<'_> {
+    fn drop(&mut self) {
+        // The timer task holds the region too and may never run again once aborted, so
+        // it is wiped here rather than left to whichever side lets go of it last.
+        wipe(&self.plaintext);
+        if let Some(wipe) = self.wipe.take() {
+            wipe.abort();
+        }
+    }
+}
+
+fn wipe(plaintext: &Mutex<Option<SecureRegion>>) {
+    if plaintext.lock().unwrap().take().is_some() {
+        debug!("Plaintext guard wiped");
+    }
+}
+
+impl fmt::Debug for PlaintextGuard<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PlaintextGuard")
+            .field("expires_at", &self.expires_at)
+            .finish_non_exhaustive()
+    }
+}
@@ -1,8 +1,12 @@
 pub mod encryption;
 pub mod evasion;
 pub mod memory;
+pub mod signing;
 
 pub trait Actor<T: Send + Sync>: Send {
+    /// What `run` hands back to the caller, e.g. a handle to await the result.
+    type Output;
+
     fn is_alive(&self) -> bool;
-    fn run(&self, input: Option<T>);
+    fn run(&self, input: Option<T>) -> Self::Output;
 }
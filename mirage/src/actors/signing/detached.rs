@@ -0,0 +1,139 @@
+//! Detached signatures, stored next to the signed file as `<file>.sig`.
+//!
+//! A detached signature is a PEM block labelled `MIRAGE SIGNATURE` whose contents are:
+//!
+//! | size | field                                             |
+//! |------|---------------------------------------------------|
+//! | 1    | format version (`1`)                              |
+//! | 1    | algorithm id, see `SignatureAlgorithm`            |
+//! | 32   | SHA-256 fingerprint of the signer's public key    |
+//! | rest | signature over the raw file contents              |
+//!
+//! The signature itself is a plain RSA-PSS or Ed25519ph signature over the file, so it
+//! can be checked by other tools once extracted.
+
+use std::path::{Path, PathBuf};
+
+use tokio::io::AsyncWriteExt;
+
+use super::error::SignatureError;
+use super::{SignatureAlgorithm, SigningKey, VerifyingKey};
+use crate::actors::encryption::recipients::{Fingerprint, FINGERPRINT_LENGTH};
+use crate::actors::encryption::AesError;
+use crate::utils::file_system::AtomicFile;
+
+pub const PEM_TAG: &str = "MIRAGE SIGNATURE";
+pub const VERSION_1: u8 = 1;
+pub const EXTENSION: &str = "sig";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DetachedSignature {
+    pub algorithm: SignatureAlgorithm,
+    pub fingerprint: Fingerprint,
+    pub signature: Vec<u8>,
+}
+
+impl DetachedSignature {
+    /// Signs `data` with `key`.
+    pub fn sign(key: &SigningKey, data: &[u8]) -> Result<Self, AesError> {
+        Ok(Self::from_signature(key, key.sign(data)?))
+    }
+
+    fn from_signature(key: &SigningKey, signature: Vec<u8>) -> Self {
+        DetachedSignature {
+            algorithm: key.algorithm(),
+            fingerprint: *key.fingerprint(),
+            signature,
+        }
+    }
+
+    /// Checks that `key` made this signature over `data`.
+    pub fn verify(&self, key: &VerifyingKey, data: &[u8]) -> Result<(), AesError> {
+        self.check_signer(key)?;
+        key.verify(data, &self.signature)
+    }
+
+    fn check_signer(&self, key: &VerifyingKey) -> Result<(), SignatureError> {
+        if self.algorithm != key.algorithm() || &self.fingerprint != key.fingerprint() {
+            return Err(SignatureError::KeyMismatch);
+        }
+        Ok(())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(2 + FINGERPRINT_LENGTH + self.signature.len());
+        encoded.push(VERSION_1);
+        encoded.push(self.algorithm.id());
+        encoded.extend_from_slice(&self.fingerprint);
+        encoded.extend_from_slice(&self.signature);
+        encoded
+    }
+
+    pub fn decode(encoded: &[u8]) -> Result<Self, SignatureError> {
+        if encoded.len() <= 2 + FINGERPRINT_LENGTH {
+            return Err(SignatureError::MalformedSignature(
+                "detached signature is truncated".to_string(),
+            ));
+        }
+        if encoded[0] != VERSION_1 {
+            return Err(SignatureError::MalformedSignature(format!(
+                "unsupported detached signature version {}",
+                encoded[0]
+            )));
+        }
+        let (fingerprint, signature) = encoded[2..].split_at(FINGERPRINT_LENGTH);
+        Ok(DetachedSignature {
+            algorithm: SignatureAlgorithm::from_id(encoded[1])?,
+            fingerprint: fingerprint.try_into().unwrap(),
+            signature: signature.to_vec(),
+        })
+    }
+
+    pub fn to_pem(&self) -> String {
+        let config = pem::EncodeConfig::new().set_line_ending(pem::LineEnding::LF);
+        pem::encode_config(&pem::Pem::new(PEM_TAG, self.encode()), config)
+    }
+
+    pub fn from_pem(pem: &[u8]) -> Result<Self, AesError> {
+        let pem = pem::parse(pem).map_err(|e| SignatureError::InvalidPem(e.to_string()))?;
+        if pem.tag() != PEM_TAG {
+            return Err(SignatureError::InvalidPem(format!(
+                "expected '{}', found '{}'",
+                PEM_TAG,
+                pem.tag()
+            ))
+            .into());
+        }
+        Ok(Self::decode(pem.contents())?)
+    }
+}
+
+/// Where the detached signature of `path` is stored: `path` with `.sig` appended.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut signature_path = path.as_os_str().to_owned();
+    signature_path.push(".");
+    signature_path.push(EXTENSION);
+    PathBuf::from(signature_path)
+}
+
+/// Signs the file at `path` while streaming it and writes the detached signature to
+/// `signature_path(path)`, replacing any previous one atomically.
+pub async fn sign_file(key: &SigningKey, path: &Path) -> Result<PathBuf, AesError> {
+    let signature = DetachedSignature::from_signature(key, key.sign_file(path).await?);
+
+    let destination = signature_path(path);
+    let mut output = AtomicFile::create(&destination).await?;
+    output
+        .file()
+        .write_all(signature.to_pem().as_bytes())
+        .await?;
+    output.commit(None).await?;
+    Ok(destination)
+}
+
+/// Checks the file at `path` against the detached signature next to it.
+pub async fn verify_file(key: &VerifyingKey, path: &Path) -> Result<(), AesError> {
+    let signature = DetachedSignature::from_pem(&tokio::fs::read(signature_path(path)).await?)?;
+    signature.check_signer(key)?;
+    key.verify_file(path, &signature.signature).await
+}
@@ -0,0 +1,45 @@
+use crate::actors::encryption::AesError;
+
+#[derive(Debug)]
+pub enum SignatureError {
+    InvalidPem(String),
+    InvalidKey(String),
+    UnsupportedAlgorithm(u8),
+    MalformedSignature(String),
+    SigningFailed(String),
+    /// The signature was made by another key than the one verifying it.
+    KeyMismatch,
+    VerificationFailed,
+    /// A signed envelope names a signer the decryptor does not know.
+    UnknownSigner,
+    /// The decryptor expects signed envelopes but got an unsigned one.
+    Unsigned,
+}
+
+impl std::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureError::InvalidPem(reason) => write!(f, "invalid PEM: {}", reason),
+            SignatureError::InvalidKey(reason) => write!(f, "invalid signing key: {}", reason),
+            SignatureError::UnsupportedAlgorithm(id) => {
+                write!(f, "unsupported signature algorithm id {}", id)
+            }
+            SignatureError::MalformedSignature(reason) => {
+                write!(f, "malformed signature: {}", reason)
+            }
+            SignatureError::SigningFailed(reason) => write!(f, "signing failed: {}", reason),
+            SignatureError::KeyMismatch => write!(f, "signature was made by a different key"),
+            SignatureError::VerificationFailed => write!(f, "signature verification failed"),
+            SignatureError::UnknownSigner => {
+                write!(f, "envelope is signed by an unknown key")
+            }
+            SignatureError::Unsigned => write!(f, "envelope is not signed"),
+        }
+    }
+}
+
+impl From<SignatureError> for AesError {
+    fn from(err: SignatureError) -> Self {
+        AesError::SignatureError(err)
+    }
+}
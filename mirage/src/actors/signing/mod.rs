@@ -0,0 +1,473 @@
+//! Digital signatures with RSA-PSS (SHA-256) or Ed25519ph (SHA-512).
+//!
+//! Both algorithms sign a digest of the message, so buffers, streams and files are signed
+//! incrementally and a signature over a buffer verifies the same bytes read from a file.
+//! Keys are identified by the SHA-256 fingerprint of their SubjectPublicKeyInfo, like
+//! RSA recipients. See `detached` for `.sig` files; signed envelopes are produced by
+//! `Encryptor::with_signer` and checked by `Decryptor::with_verifier`.
+
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use pkcs8::EncodePublicKey;
+use rand::rngs::OsRng;
+use rsa::signature::{DigestVerifier, Keypair, RandomizedDigestSigner, SignatureEncoding};
+use rsa::traits::PublicKeyParts;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use sha2::{Digest, Sha256, Sha512};
+use tokio::fs::File;
+use tokio::io::{self, AsyncRead, AsyncReadExt, ReadBuf};
+
+use self::error::SignatureError;
+
+use crate::actors::encryption::keys::{self, PrivateKey, PublicKey};
+use crate::actors::encryption::recipients::Fingerprint;
+use crate::actors::encryption::AesError;
+use crate::utils::secret::SecretBytes;
+
+pub mod detached;
+pub mod error;
+
+/// Modulus size of RSA keys made by `SigningKey::generate`.
+pub const RSA_KEY_BITS: usize = 3072;
+pub const ED25519_SIGNATURE_LENGTH: usize = ed25519_dalek::SIGNATURE_LENGTH;
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignatureAlgorithm {
+    /// RSASSA-PSS with SHA-256, MGF1-SHA-256 and a 32-byte salt.
+    RsaPssSha256 = 1,
+    /// Ed25519ph (RFC 8032) over SHA-512 without a context string.
+    Ed25519ph = 2,
+}
+
+impl SignatureAlgorithm {
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_id(id: u8) -> Result<Self, SignatureError> {
+        match id {
+            1 => Ok(SignatureAlgorithm::RsaPssSha256),
+            2 => Ok(SignatureAlgorithm::Ed25519ph),
+            _ => Err(SignatureError::UnsupportedAlgorithm(id)),
+        }
+    }
+
+    /// Empty digest to feed the message into before signing or verifying.
+    pub fn new_digest(&self) -> MessageDigest {
+        match self {
+            SignatureAlgorithm::RsaPssSha256 => MessageDigest::Sha256(Sha256::new()),
+            SignatureAlgorithm::Ed25519ph => MessageDigest::Sha512(Sha512::new()),
+        }
+    }
+}
+
+/// Running hash of a message being signed or verified.
+#[derive(Clone)]
+pub enum MessageDigest {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl MessageDigest {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            MessageDigest::Sha256(digest) => digest.update(data),
+            MessageDigest::Sha512(digest) => digest.update(data),
+        }
+    }
+
+    /// Hashes everything `reader` yields.
+    pub async fn update_from<R: AsyncRead + Unpin>(&mut self, reader: &mut R) -> io::Result<()> {
+        let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+        loop {
+            let read = reader.read(&mut buffer).await?;
+            if read == 0 {
+                return Ok(());
+            }
+            self.update(&buffer[..read]);
+        }
+    }
+}
+
+/// Identifies who signed an envelope; recorded in its header when `FLAG_SIGNED` is set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignerInfo {
+    pub algorithm: SignatureAlgorithm,
+    pub fingerprint: Fingerprint,
+    /// Length of the signature trailing the plaintext.
+    pub signature_length: u16,
+}
+
+enum SecretKind {
+    RsaPss(rsa::pss::BlindedSigningKey<Sha256>),
+    Ed25519(ed25519_dalek::SigningKey),
+}
+
+/// Private key that produces signatures.
+pub struct SigningKey {
+    key: SecretKind,
+    fingerprint: Fingerprint,
+}
+
+impl SigningKey {
+    /// Generates a fresh key; RSA keys have `RSA_KEY_BITS` bits.
+    pub fn generate(algorithm: SignatureAlgorithm) -> Result<Self, AesError> {
+        match algorithm {
+            SignatureAlgorithm::RsaPssSha256 => {
+                let key = RsaPrivateKey::new(&mut OsRng, RSA_KEY_BITS)
+                    .map_err(|e| SignatureError::InvalidKey(e.to_string()))?;
+                Self::from_rsa(key)
+            }
+            SignatureAlgorithm::Ed25519ph => {
+                Self::from_ed25519(ed25519_dalek::SigningKey::generate(&mut OsRng))
+            }
+        }
+    }
+
+    /// Loads an unencrypted `PRIVATE KEY` (PKCS#8) or `RSA PRIVATE KEY` (PKCS#1) PEM.
+    /// Use `keys::PrivateKey` for encrypted keys.
+    pub fn from_pem(pem: &[u8]) -> Result<Self, AesError> {
+        PrivateKey::from_pem(pem, None)?.into_signing_key()
+    }
+
+    pub fn from_rsa(key: RsaPrivateKey) -> Result<Self, AesError> {
+        keys::check_rsa_size(&key)?;
+        let key = rsa::pss::BlindedSigningKey::<Sha256>::new(key);
+        let fingerprint = fingerprint(
+            key.verifying_key()
+                .as_ref()
+                .to_public_key_der()
+                .map_err(|e| SignatureError::InvalidKey(e.to_string()))?
+                .as_bytes(),
+        );
+        Ok(SigningKey {
+            key: SecretKind::RsaPss(key),
+            fingerprint,
+        })
+    }
+
+    pub fn from_ed25519(key: ed25519_dalek::SigningKey) -> Result<Self, AesError> {
+        let fingerprint = fingerprint(
+            key.verifying_key()
+                .to_public_key_der()
+                .map_err(|e| SignatureError::InvalidKey(e.to_string()))?
+                .as_bytes(),
+        );
+        Ok(SigningKey {
+            key: SecretKind::Ed25519(key),
+            fingerprint,
+        })
+    }
+
+    pub fn algorithm(&self) -> SignatureAlgorithm {
+        match self.key {
+            SecretKind::RsaPss(_) => SignatureAlgorithm::RsaPssSha256,
+            SecretKind::Ed25519(_) => SignatureAlgorithm::Ed25519ph,
+        }
+    }
+
+    pub fn fingerprint(&self) -> &Fingerprint {
+        &self.fingerprint
+    }
+
+    /// Public half that verifies this key's signatures.
+    pub fn verifying_key(&self) -> VerifyingKey {
+        let key = match &self.key {
+            SecretKind::RsaPss(key) => PublicKind::RsaPss(key.verifying_key()),
+            SecretKind::Ed25519(key) => PublicKind::Ed25519(key.verifying_key()),
+        };
+        VerifyingKey {
+            key,
+            fingerprint: self.fingerprint,
+        }
+    }
+
+    /// Length of every signature this key produces.
+    pub fn signature_length(&self) -> usize {
+        match &self.key {
+            SecretKind::RsaPss(key) => key.as_ref().size(),
+            SecretKind::Ed25519(_) => ED25519_SIGNATURE_LENGTH,
+        }
+    }
+
+    pub fn signer_info(&self) -> SignerInfo {
+        SignerInfo {
+            algorithm: self.algorithm(),
+            fingerprint: self.fingerprint,
+            signature_length: self.signature_length() as u16,
+        }
+    }
+
+    pub fn sign(&self, data: &[u8]) -> Result<Vec<u8>, AesError> {
+        let mut digest = self.algorithm().new_digest();
+        digest.update(data);
+        self.sign_digest(digest)
+    }
+
+    /// Signs a message already fed into `digest`, which must come from this key's
+    /// `SignatureAlgorithm::new_digest`.
+    pub fn sign_digest(&self, digest: MessageDigest) -> Result<Vec<u8>, AesError> {
+        let signature = match (&self.key, digest) {
+            (SecretKind::RsaPss(key), MessageDigest::Sha256(digest)) => key
+                .try_sign_digest_with_rng(&mut OsRng, digest)
+                .map_err(|e| SignatureError::SigningFailed(e.to_string()))?
+                .to_vec(),
+            (SecretKind::Ed25519(key), MessageDigest::Sha512(digest)) => key
+                .sign_prehashed(digest, None)
+                .map_err(|e| SignatureError::SigningFailed(e.to_string()))?
+                .to_bytes()
+                .to_vec(),
+            _ => {
+                return Err(SignatureError::SigningFailed(
+                    "digest does not match the key's algorithm".to_string(),
+                )
+                .into())
+            }
+        };
+        Ok(signature)
+    }
+
+    /// Signs everything `reader` yields without buffering it.
+    pub async fn sign_reader<R: AsyncRead + Unpin>(
+        &self,
+        reader: &mut R,
+    ) -> Result<Vec<u8>, AesError> {
+        let mut digest = self.algorithm().new_digest();
+        digest.update_from(reader).await?;
+        self.sign_digest(digest)
+    }
+
+    pub async fn sign_file(&self, path: &Path) -> Result<Vec<u8>, AesError> {
+        self.sign_reader(&mut File::open(path).await?).await
+    }
+}
+
+#[derive(Clone, Debug)]
+enum PublicKind {
+    RsaPss(rsa::pss::VerifyingKey<Sha256>),
+    Ed25519(ed25519_dalek::VerifyingKey),
+}
+
+/// Public key that checks signatures.
+#[derive(Clone, Debug)]
+pub struct VerifyingKey {
+    key: PublicKind,
+    fingerprint: Fingerprint,
+}
+
+impl VerifyingKey {
+    /// Loads a `PUBLIC KEY` (SubjectPublicKeyInfo) or `RSA PUBLIC KEY` (PKCS#1) PEM.
+    pub fn from_pem(pem: &[u8]) -> Result<Self, AesError> {
+        PublicKey::from_pem(pem)?.to_verifying_key()
+    }
+
+    pub fn from_rsa(key: RsaPublicKey) -> Result<Self, AesError> {
+        keys::check_rsa_size(&key)?;
+        let fingerprint = fingerprint(
+            key.to_public_key_der()
+                .map_err(|e| SignatureError::InvalidKey(e.to_string()))?
+                .as_bytes(),
+        );
+        Ok(VerifyingKey {
+            key: PublicKind::RsaPss(rsa::pss::VerifyingKey::new(key)),
+            fingerprint,
+        })
+    }
+
+    pub fn from_ed25519(key: ed25519_dalek::VerifyingKey) -> Result<Self, AesError> {
+        let fingerprint = fingerprint(
+            key.to_public_key_der()
+                .map_err(|e| SignatureError::InvalidKey(e.to_string()))?
+                .as_bytes(),
+        );
+        Ok(VerifyingKey {
+            key: PublicKind::Ed25519(key),
+            fingerprint,
+        })
+    }
+
+    pub fn algorithm(&self) -> SignatureAlgorithm {
+        match self.key {
+            PublicKind::RsaPss(_) => SignatureAlgorithm::RsaPssSha256,
+            PublicKind::Ed25519(_) => SignatureAlgorithm::Ed25519ph,
+        }
+    }
+
+    pub fn fingerprint(&self) -> &Fingerprint {
+        &self.fingerprint
+    }
+
+    pub fn verify(&self, data: &[u8], signature: &[u8]) -> Result<(), AesError> {
+        let mut digest = self.algorithm().new_digest();
+        digest.update(data);
+        self.verify_digest(digest, signature)
+    }
+
+    /// Checks `signature` over a message already fed into `digest`.
+    pub fn verify_digest(&self, digest: MessageDigest, signature: &[u8]) -> Result<(), AesError> {
+        let verified = match (&self.key, digest) {
+            (PublicKind::RsaPss(key), MessageDigest::Sha256(digest)) => {
+                let signature = rsa::pss::Signature::try_from(signature)
+                    .map_err(|e| SignatureError::MalformedSignature(e.to_string()))?;
+                key.verify_digest(digest, &signature).is_ok()
+            }
+            (PublicKind::Ed25519(key), MessageDigest::Sha512(digest)) => {
+                let signature = ed25519_dalek::Signature::from_slice(signature)
+                    .map_err(|e| SignatureError::MalformedSignature(e.to_string()))?;
+                key.verify_prehashed_strict(digest, None, &signature)
+                    .is_ok()
+            }
+            _ => false,
+        };
+        if !verified {
+            return Err(SignatureError::VerificationFailed.into());
+        }
+        Ok(())
+    }
+
+    /// Checks `signature` over everything `reader` yields without buffering it.
+    pub async fn verify_reader<R: AsyncRead + Unpin>(
+        &self,
+        reader: &mut R,
+        signature: &[u8],
+    ) -> Result<(), AesError> {
+        let mut digest = self.algorithm().new_digest();
+        digest.update_from(reader).await?;
+        self.verify_digest(digest, signature)
+    }
+
+    pub async fn verify_file(&self, path: &Path, signature: &[u8]) -> Result<(), AesError> {
+        self.verify_reader(&mut File::open(path).await?, signature)
+            .await
+    }
+}
+
+/// Passes `reader` through while hashing it, then appends the signature over everything
+/// hashed once `reader` ends. Used to sign envelope plaintext on the fly.
+pub(crate) struct SignedReader<'a, R> {
+    reader: &'a mut R,
+    key: &'a SigningKey,
+    /// `None` once `reader` ended and the signature was computed.
+    digest: Option<MessageDigest>,
+    trailer: Vec<u8>,
+    position: usize,
+}
+
+impl<'a, R: AsyncRead + Unpin> SignedReader<'a, R> {
+    /// `digest` may already hold data that is signed but not passed through.
+    pub(crate) fn new(reader: &'a mut R, key: &'a SigningKey, digest: MessageDigest) -> Self {
+        SignedReader {
+            reader,
+            key,
+            digest: Some(digest),
+            trailer: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for SignedReader<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if let Some(digest) = this.digest.as_mut() {
+            let filled = buf.filled().len();
+            match Pin::new(&mut *this.reader).poll_read(cx, buf) {
+                Poll::Ready(Ok(())) => {}
+                other => return other,
+            }
+            let read = &buf.filled()[filled..];
+            if !read.is_empty() || buf.remaining() == 0 {
+                digest.update(read);
+                return Poll::Ready(Ok(()));
+            }
+
+            let digest = this.digest.take().expect("digest is present until the end");
+            this.trailer = this
+                .key
+                .sign_digest(digest)
+                .map_err(|e| io::Error::other(e.to_string()))?;
+        }
+
+        let remaining = &this.trailer[this.position..];
+        let length = remaining.len().min(buf.remaining());
+        buf.put_slice(&remaining[..length]);
+        this.position += length;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Splits decrypted envelope plaintext into the message and the signature trailing it,
+/// hashing the message as it is released. Held plaintext is wiped once released.
+pub(crate) struct TrailingSignature {
+    digest: MessageDigest,
+    length: usize,
+    tail: SecretBytes,
+}
+
+impl TrailingSignature {
+    pub(crate) fn new(digest: MessageDigest, length: usize) -> Self {
+        TrailingSignature {
+            digest,
+            length,
+            tail: SecretBytes::default(),
+        }
+    }
+
+    /// Takes the next plaintext chunk and returns the message bytes that can no longer
+    /// be part of the signature.
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> SecretBytes {
+        let total = self.tail.len() + chunk.len();
+        let release = total.saturating_sub(self.length);
+
+        // Both buffers get their final size up front, so nothing is left behind by
+        // reallocation; the previous tail is wiped when replaced.
+        let mut message = SecretBytes::new(Vec::with_capacity(release));
+        let mut tail = SecretBytes::new(Vec::with_capacity(self.length));
+        for part in [&self.tail[..], chunk] {
+            let take = release.saturating_sub(message.len()).min(part.len());
+            message.extend_from_slice(&part[..take]);
+            tail.extend_from_slice(&part[take..]);
+        }
+        self.tail = tail;
+        self.digest.update(&message);
+        message
+    }
+
+    /// The digest of the whole message and the signature, once the plaintext ended.
+    pub(crate) fn finish(self) -> Result<(MessageDigest, Vec<u8>), SignatureError> {
+        if self.tail.len() != self.length {
+            return Err(SignatureError::MalformedSignature(
+                "plaintext is shorter than its signature".to_string(),
+            ));
+        }
+        Ok((self.digest, self.tail.to_vec()))
+    }
+
+    /// Like `push` followed by `finish` for a plaintext that is complete, without copying
+    /// it. Returns the digest and the length of the message in front of the signature.
+    pub(crate) fn split(
+        mut self,
+        plaintext: &[u8],
+    ) -> Result<(MessageDigest, usize), SignatureError> {
+        let message_length = plaintext.len().checked_sub(self.length).ok_or_else(|| {
+            SignatureError::MalformedSignature(
+                "plaintext is shorter than its signature".to_string(),
+            )
+        })?;
+        self.digest.update(&plaintext[..message_length]);
+        Ok((self.digest, message_length))
+    }
+}
+
+fn fingerprint(spki_der: &[u8]) -> Fingerprint {
+    Sha256::digest(spki_der).into()
+}
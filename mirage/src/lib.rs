@@ -0,0 +1,3 @@
+pub mod actors;
+pub mod tools;
+pub mod utils;
@@ -1,11 +1,8 @@
-mod actors;
-mod tools;
-mod utils;
-
-use crate::actors::memory::Input;
-use actors::memory::secure_memory_provider::SecureMemoryProvider;
+use mirage::actors::memory::secure_memory_provider::SecureMemoryProvider;
+use mirage::actors::memory::Input;
+use mirage::tools::{config::Config, Tool};
+use mirage::utils;
 use std::sync::{Arc, Mutex};
-use tools::{config::Config, Tool};
 
 #[tokio::main]
 async fn main() {
@@ -25,7 +22,9 @@ async fn main() {
     let sample_id = "sample_id";
     {
         let mut provider = secure_memory_provider.lock().unwrap();
-        provider.push(sample_id.to_owned(), sample_data);
+        if let Err(e) = provider.push(sample_id.to_owned(), sample_data) {
+            println!("Error storing data: {}", e);
+        }
     }
 
     // Retrieve and decrypt the string
@@ -36,7 +35,7 @@ async fn main() {
                 Some(Input::Buffer(decrypted_data)) => {
                     println!(
                         "Decrypted data: {}",
-                        String::from_utf8_lossy(&decrypted_data)
+                        String::from_utf8_lossy(decrypted_data)
                     );
                 }
                 _ => println!("Unexpected data format"),
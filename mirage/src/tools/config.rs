@@ -5,18 +5,30 @@ use serde::{Deserialize, Serialize};
 use std::{fs, path::Path, sync::Mutex};
 
 use super::{Tool, Tools};
+use crate::actors::encryption::kdf::KdfAlgorithm;
+use crate::actors::encryption::suite::CipherSuite;
 
 lazy_static! {
     static ref CONFIG: Mutex<AppConfig> = Mutex::new(AppConfig::default());
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum EncryptionLevel {
     Level1,
     Level2,
 }
 
+impl EncryptionLevel {
+    /// Key length in bytes required by the cipher behind this level.
+    pub fn key_length(&self) -> usize {
+        match self {
+            EncryptionLevel::Level1 => 16, // AES128 (16 bytes)
+            EncryptionLevel::Level2 => 32, // AES256 (32 bytes)
+        }
+    }
+}
+
 impl std::fmt::Display for EncryptionLevel {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
@@ -38,6 +50,15 @@ pub struct Features {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Parameters {
     pub encryption_level: EncryptionLevel,
+    /// Overrides the suite implied by `encryption_level` when set.
+    #[serde(default)]
+    pub cipher_suite: Option<CipherSuite>,
+    /// Passphrase key derivation function, Argon2id when unset.
+    #[serde(default)]
+    pub key_derivation: Option<KdfAlgorithm>,
+    /// Time a passphrase derivation should take on this machine, one second when unset.
+    #[serde(default)]
+    pub kdf_unlock_time_ms: Option<u64>,
     pub observer_temperature: f32,
     pub memory_scramble_size: usize,
 }
@@ -60,6 +81,9 @@ impl Default for AppConfig {
             },
             parameters: Parameters {
                 encryption_level: EncryptionLevel::Level1,
+                cipher_suite: None,
+                key_derivation: None,
+                kdf_unlock_time_ms: None,
                 observer_temperature: 5.0,
                 memory_scramble_size: 10,
             },
@@ -118,6 +142,12 @@ impl Tool for Config {
     }
 }
 
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Config {
     pub fn new() -> Self {
         if cfg!(feature = "development") {
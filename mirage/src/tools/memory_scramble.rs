@@ -18,6 +18,12 @@ pub struct MemoryScramble {
     memory_size: usize,
 }
 
+impl Default for MemoryScramble {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MemoryScramble {
     pub fn new() -> Self {
         let rng = create_seeded_rng();
@@ -40,6 +46,7 @@ impl MemoryScramble {
         }
     }
 
+    #[allow(dead_code)]
     fn adjust_memory_size(&mut self) {
         let is_stealth = *self.stealth_mode.lock().unwrap();
         let configured_memory_size = Config::get_parameters().memory_scramble_size * MB;
@@ -81,7 +88,7 @@ impl MemoryScramble {
                     // Apply stealth measures if enabled
                     if *self.stealth_mode.lock().unwrap() {
                         apply_gaussian_noise_on_buffer(fragment_slice, &mut self.rng);
-                        encrypt_buffer_aes(fragment_slice, &mut self.rng);
+                        encrypt_buffer_aes(fragment_slice, &[], &mut self.rng);
                     }
                 }
             }
@@ -117,7 +124,7 @@ impl MemoryScramble {
 
     fn apply_stealth_measures(&mut self, memory_buffer: &mut [u8]) {
         apply_gaussian_noise_on_buffer(memory_buffer, &mut self.rng);
-        encrypt_buffer_aes(memory_buffer, &mut self.rng);
+        encrypt_buffer_aes(memory_buffer, &[], &mut self.rng);
         if cfg!(feature = "development") {
             debug!("[Tools/{}] Applied Stealth Measures", Tools::MemoryScramble);
         }
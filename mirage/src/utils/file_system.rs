@@ -1,16 +1,16 @@
 use rand::{rngs::OsRng, Rng, RngCore};
 use rand_distr::StandardNormal;
 use std::{
-    fs::{self, File},
+    fs::{self, File, Metadata},
     io::{self, Write},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 pub fn secure_delete_file(path: impl AsRef<Path>) -> io::Result<()> {
     let path = path.as_ref();
-    let metadata = fs::metadata(&path)?;
+    let metadata = fs::metadata(path)?;
     let length = metadata.len();
-    let mut file = File::options().write(true).open(&path)?;
+    let mut file = File::options().write(true).open(path)?;
 
     // Overwrite patterns
     overwrite_file_byte(&mut file, length, 0x00)?;
@@ -25,7 +25,7 @@ pub fn secure_delete_file(path: impl AsRef<Path>) -> io::Result<()> {
     // Truncate and remove the file
     file.set_len(0)?;
     drop(file); // Close the file before deletion
-    fs::remove_file(&path)
+    fs::remove_file(path)
 }
 
 fn overwrite_file_byte(file: &mut File, length: u64, byte: u8) -> io::Result<()> {
@@ -57,3 +57,114 @@ fn overwrite_file_xor(file: &mut File, length: u64) -> io::Result<()> {
     file.write_all(&buffer)?;
     file.sync_all()
 }
+
+/// A file written under a temporary name next to its destination and moved into place
+/// only by `commit`. Readers of `destination` never observe a partially written file,
+/// and a crash leaves the original untouched. Dropping without `commit` removes the
+/// temporary file.
+pub struct AtomicFile {
+    file: Option<tokio::fs::File>,
+    temp_path: PathBuf,
+    destination: PathBuf,
+}
+
+impl AtomicFile {
+    pub async fn create(destination: impl AsRef<Path>) -> io::Result<Self> {
+        let destination = destination.as_ref().to_path_buf();
+        let directory = match destination.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let file_name = destination
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?
+            .to_string_lossy();
+        let temp_path = directory.join(format!(".{}.{:016x}.tmp", file_name, OsRng.gen::<u64>()));
+
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        // Only the owner may read the data until `commit` copies the final permissions.
+        #[cfg(unix)]
+        options.mode(0o600);
+        let file = options.open(&temp_path).await?;
+
+        Ok(AtomicFile {
+            file: Some(file),
+            temp_path,
+            destination,
+        })
+    }
+
+    pub fn file(&mut self) -> &mut tokio::fs::File {
+        self.file.as_mut().expect("atomic file already committed")
+    }
+
+    /// Flushes the data to disk, copies permissions and modification time from
+    /// `preserve` when given, then renames the file over its destination. On error the
+    /// temporary file is removed and the destination is left as it was.
+    pub async fn commit(mut self, preserve: Option<&Metadata>) -> io::Result<()> {
+        let file = self.file.as_mut().expect("atomic file already committed");
+
+        if let Some(metadata) = preserve {
+            file.set_permissions(metadata.permissions()).await?;
+            let modified = metadata.modified()?;
+            let std_file = file.try_clone().await?.into_std().await;
+            blocking(move || std_file.set_modified(modified)).await?;
+        }
+        file.sync_all().await?;
+
+        tokio::fs::rename(&self.temp_path, &self.destination).await?;
+        // The temporary name is gone now, so there is nothing left for `Drop` to remove.
+        self.file = None;
+
+        let destination = self.destination.clone();
+        blocking(move || sync_parent_directory(&destination)).await
+    }
+
+    /// Like `commit`, but links the file into place instead of renaming it, so it fails
+    /// with `ErrorKind::AlreadyExists` rather than replace an existing destination.
+    pub async fn commit_new(mut self) -> io::Result<()> {
+        let file = self.file.as_mut().expect("atomic file already committed");
+        file.sync_all().await?;
+
+        tokio::fs::hard_link(&self.temp_path, &self.destination).await?;
+        let destination = self.destination.clone();
+        // Removes the temporary name; the data stays reachable under `destination`.
+        drop(self);
+
+        blocking(move || sync_parent_directory(&destination)).await
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// Runs a blocking filesystem call off the async runtime's worker threads.
+async fn blocking<T, F>(f: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Makes a rename inside the parent directory durable.
+#[cfg(unix)]
+fn sync_parent_directory(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent_directory(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -1,23 +1,32 @@
 use rand::Rng;
 
-use crate::tools::config::{Config, EncryptionLevel};
+use crate::actors::encryption::suite::CipherSuite;
 
 use super::math::statistics_probability::create_seeded_rng;
+use super::secret::SecretKey;
 
-const NONCE_SIZE: usize = 12;
+/// Generates a random key for the configured cipher suite.
+pub fn generate_aes_key() -> SecretKey {
+    generate_key(CipherSuite::from_config())
+}
 
-pub fn generate_aes_key() -> Vec<u8> {
-    let level = Config::get_parameters().encryption_level;
-    let key_size = match level {
-        EncryptionLevel::Level1 => 16, // AES128 (16 bytes)
-        EncryptionLevel::Level2 => 32, // AES256 (32 bytes)
-    };
+/// Generates a random key of the length required by `suite`.
+pub fn generate_key(suite: CipherSuite) -> SecretKey {
+    let mut key = SecretKey::zeroed(suite.key_length());
+    create_seeded_rng().fill(key.as_mut_bytes());
+    key
+}
 
+/// Generates a fresh random STREAM nonce prefix. Must be called once per encrypted message.
+pub fn generate_nonce_prefix(suite: CipherSuite) -> Vec<u8> {
     let mut rng = create_seeded_rng();
-    (0..key_size).map(|_| rng.gen::<u8>()).collect()
+    (0..suite.nonce_prefix_length())
+        .map(|_| rng.gen::<u8>())
+        .collect()
 }
 
-pub fn generate_nonce() -> Vec<u8> {
+/// Generates a random salt for passphrase key derivation.
+pub fn generate_salt(length: usize) -> Vec<u8> {
     let mut rng = create_seeded_rng();
-    (0..NONCE_SIZE).map(|_| rng.gen::<u8>()).collect()
+    (0..length).map(|_| rng.gen::<u8>()).collect()
 }
@@ -94,7 +94,7 @@ fn generate_combined_seed(rng: &mut StdRng) -> [u8; 32] {
         .map(|(&os_byte, &entropy_byte)| os_byte ^ entropy_byte)
         .collect::<Vec<u8>>()
         .try_into()
-        .unwrap_or_else(|_| [0u8; 32]) // Fallback for conversion error
+        .unwrap_or([0u8; 32]) // Fallback for conversion error
 }
 
 /// Enhances the seed with Lorenz system entropy.
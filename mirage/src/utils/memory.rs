@@ -19,6 +19,12 @@ pub fn create_layout_from_size(block_size: usize) -> Layout {
 }
 
 /// Utility function to allocate a memory buffer based on `Layout`.
+///
+/// The memory is neither locked nor wiped; secrets belong in a `SecureRegion`.
+///
+/// # Safety
+/// `layout` must have a non-zero size, and the caller is responsible for
+/// deallocating the returned pointer with the same `layout`.
 pub unsafe fn allocate_memory_from_layout(layout: Layout) -> *mut u32 {
     let heap_memory = alloc::alloc(layout) as *mut u32;
     if heap_memory.is_null() {
@@ -27,18 +33,6 @@ pub unsafe fn allocate_memory_from_layout(layout: Layout) -> *mut u32 {
     heap_memory
 }
 
-// Utility function to allocate a memory buffer based on input size in bytes.
-pub fn allocate_memory_from_size(size: usize) -> *mut u8 {
-    unsafe {
-        let layout = Layout::from_size_align(size, 4).expect("Failed to create memory layout");
-        let heap_memory = alloc::alloc(layout) as *mut u8;
-        if heap_memory.is_null() {
-            panic!("Failed to allocate memory");
-        }
-        heap_memory
-    }
-}
-
 /// Utility function to shuffle a memory buffer in place using `StdRng`.
 pub fn shuffle_buffer_with_rng(buffer: &mut [u8], rng: &mut StdRng) {
     let len = buffer.len();
@@ -57,7 +51,8 @@ pub fn xor_buffer_with_seed(buffer: &mut [u8], rng: &mut StdRng) {
 }
 
 /// Utility function to encrypt memory buffer in place AES-128-GCM using `Key` and `Nonce`.
-pub fn encrypt_buffer_aes(buffer: &[u8], rng: &mut StdRng) -> Vec<u8> {
+/// `aad` is authenticated alongside the buffer but not encrypted.
+pub fn encrypt_buffer_aes(buffer: &[u8], aad: &[u8], rng: &mut StdRng) -> Vec<u8> {
     // Create variables to hold the key and nonce values
     let key_bytes = rng.gen::<[u8; 16]>();
     let nonce_bytes = rng.gen::<[u8; 12]>();
@@ -67,10 +62,7 @@ pub fn encrypt_buffer_aes(buffer: &[u8], rng: &mut StdRng) -> Vec<u8> {
     let nonce = GenericArray::from_slice(&nonce_bytes);
     let cipher = Aes128Gcm::new(key);
 
-    let payload = Payload {
-        msg: buffer,
-        aad: b"".as_ref(),
-    };
+    let payload = Payload { msg: buffer, aad };
 
     match cipher.encrypt(nonce, payload) {
         Ok(encrypted_data) => encrypted_data,
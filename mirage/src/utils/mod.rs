@@ -4,4 +4,6 @@ pub mod key_generator;
 pub mod logger;
 pub mod math;
 pub mod memory;
+pub mod secret;
+pub mod secure_region;
 pub mod sleep;
@@ -0,0 +1,460 @@
+//! Page-backed buffers for secrets.
+//!
+//! A `SecureRegion` owns whole pages mapped for it alone, so its bytes never share a
+//! page with ordinary heap data. On Linux the pages are
+//!
+//! - locked with `mlock`, so they are never written to swap,
+//! - excluded from core dumps with `MADV_DONTDUMP`,
+//! - wiped in children with `MADV_WIPEONFORK`,
+//!
+//! and every region is zeroized and unmapped when dropped.
+//!
+//! The layout follows libsodium's `sodium_malloc`. The data pages sit between two
+//! `PROT_NONE` guard pages, with the data right-aligned against the trailing guard and
+//! a random canary right before it:
+//!
+//! ```text
+//! | guard | ... canary | data | guard |
+//! ```
+//!
+//! Reading or writing past the end faults immediately. An underflow that stays within
+//! the data pages overwrites the canary instead, which is checked when the region is
+//! dropped; a mismatch aborts the process. The data pages themselves can be switched
+//! between `Protection::NoAccess`, `ReadOnly` and `ReadWrite` with `mprotect`, so
+//! secrets that are only kept are not even readable until `read` is called.
+//!
+//! With `Backend::SecretMemory` the pages come from `memfd_secret(2)` instead (Linux
+//! 5.14+). The kernel removes them from its direct map, so not even the kernel reads them
+//! casually. Whether the syscall is available is detected at runtime; when it is not,
+//! e.g. `ENOSYS` on older kernels or `EPERM` under a seccomp filter, or when a secret
+//! mapping fails, the region falls back to the `mlock` pages of `Backend::Locked`.
+//!
+//! Locking is bounded by `RLIMIT_MEMLOCK`, which also covers secret memory. A region that
+//! would exceed the limit, or whose `mlock` fails, is still created but left unlocked.
+//! `protection_level` reports what a region actually got, and a warning is logged the
+//! first time a region falls short of its backend. Other Unix systems get the locking and
+//! guard pages but not the `madvise` flags, and everything else falls back to the global
+//! allocator without guards.
+
+use std::cell::Cell;
+use std::fmt;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use lazy_static::lazy_static;
+use log::{error, warn};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use zeroize::Zeroize;
+
+mod sys;
+
+/// Length of the canary in front of every region's data.
+pub const CANARY_LENGTH: usize = 16;
+
+lazy_static! {
+    /// Canary shared by all regions of this process.
+    static ref CANARY: [u8; CANARY_LENGTH] = {
+        let mut canary = [0; CANARY_LENGTH];
+        OsRng.fill_bytes(&mut canary);
+        canary
+    };
+}
+
+/// Bytes currently locked by all regions of this process.
+static LOCKED_BYTES: AtomicUsize = AtomicUsize::new(0);
+static WARNED_UNLOCKED: AtomicBool = AtomicBool::new(false);
+static SECRET_MEMORY: OnceLock<bool> = OnceLock::new();
+
+/// Where the pages of a region come from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Anonymous pages locked with `mlock`.
+    #[default]
+    Locked,
+    /// `memfd_secret(2)` pages, falling back to `Locked` where unavailable.
+    SecretMemory,
+}
+
+/// Protection a region actually got, weakest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProtectionLevel {
+    /// Ordinary pages that may be swapped out.
+    Unlocked,
+    /// Pages locked in memory.
+    Locked,
+    /// `memfd_secret` pages, locked and outside the kernel's direct map.
+    SecretMemory,
+}
+
+/// Access allowed to the data pages of a region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protection {
+    NoAccess,
+    ReadOnly,
+    ReadWrite,
+}
+
+/// A zero-initialized buffer in pages of its own, between guard pages, locked in memory
+/// where possible and wiped on drop.
+///
+/// Dereferencing panics unless the protection allows it; see `read`.
+pub struct SecureRegion {
+    /// First byte of the data, right before the trailing guard page.
+    ptr: NonNull<u8>,
+    len: usize,
+    /// First data page, right after the leading guard page.
+    pages: NonNull<u8>,
+    /// Bytes in the data pages, the canary and `len` rounded up to whole pages.
+    pages_len: usize,
+    backend: Backend,
+    level: ProtectionLevel,
+    /// Bytes counted in `LOCKED_BYTES` for this region.
+    reserved: usize,
+    protection: Cell<Protection>,
+}
+
+// The region exclusively owns its pages. It is not `Sync`: `read` changes the protection
+// of pages another thread could be reading.
+unsafe impl Send for SecureRegion {}
+
+impl SecureRegion {
+    /// Maps `len` zeroed bytes from `Backend::Locked`, readable and writable.
+    pub fn new(len: usize) -> io::Result<Self> {
+        Self::new_in(len, Backend::Locked)
+    }
+
+    /// Maps `len` zeroed bytes from `backend`, readable and writable.
+    pub fn new_in(len: usize, backend: Backend) -> io::Result<Self> {
+        if len == 0 {
+            return Ok(SecureRegion {
+                ptr: NonNull::dangling(),
+                len,
+                pages: NonNull::dangling(),
+                pages_len: 0,
+                backend,
+                level: protection_level(backend),
+                reserved: 0,
+                protection: Cell::new(Protection::ReadWrite),
+            });
+        }
+
+        let page_size = sys::page_size();
+        let pages_len = (len + CANARY_LENGTH).div_ceil(page_size) * page_size;
+        let mapping_len = pages_len + 2 * page_size;
+        let secret = match backend {
+            Backend::SecretMemory => map_secret(mapping_len),
+            Backend::Locked => None,
+        };
+        let mapping = match secret {
+            Some(mapping) => mapping,
+            None => sys::map(mapping_len)?,
+        };
+        // SAFETY: both offsets stay within the mapping.
+        let (pages, trailing_guard) = unsafe {
+            (
+                NonNull::new_unchecked(mapping.as_ptr().add(page_size)),
+                NonNull::new_unchecked(mapping.as_ptr().add(page_size + pages_len)),
+            )
+        };
+        let guarded = sys::protect(mapping, page_size, Protection::NoAccess)
+            .and_then(|_| sys::protect(trailing_guard, page_size, Protection::NoAccess));
+        if let Err(e) = guarded {
+            sys::unmap(mapping, mapping_len);
+            if secret.is_some() {
+                release_lock(mapping_len);
+            }
+            return Err(e);
+        }
+        sys::advise(pages, pages_len);
+
+        let (level, reserved) = if secret.is_some() {
+            (ProtectionLevel::SecretMemory, mapping_len)
+        } else if reserve_lock(pages_len) {
+            if sys::lock(pages, pages_len) {
+                (ProtectionLevel::Locked, pages_len)
+            } else {
+                release_lock(pages_len);
+                (ProtectionLevel::Unlocked, 0)
+            }
+        } else {
+            (ProtectionLevel::Unlocked, 0)
+        };
+        if level == ProtectionLevel::Unlocked && !WARNED_UNLOCKED.swap(true, Ordering::Relaxed) {
+            warn!(
+                "Secure memory could not be locked (RLIMIT_MEMLOCK is {} bytes, {} in use); \
+                 secrets may be swapped to disk",
+                memlock_limit().map_or("unlimited".to_string(), |limit| limit.to_string()),
+                LOCKED_BYTES.load(Ordering::Relaxed)
+            );
+        }
+
+        // SAFETY: the data and its canary end where the data pages end.
+        let ptr = unsafe { NonNull::new_unchecked(trailing_guard.as_ptr().sub(len)) };
+        // SAFETY: the canary lies within the data pages, which are writable.
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                CANARY.as_ptr(),
+                ptr.as_ptr().sub(CANARY_LENGTH),
+                CANARY_LENGTH,
+            );
+        }
+
+        Ok(SecureRegion {
+            ptr,
+            len,
+            pages,
+            pages_len,
+            backend,
+            level,
+            reserved,
+            protection: Cell::new(Protection::ReadWrite),
+        })
+    }
+
+    /// Maps a region from `Backend::Locked` holding a copy of `data`, readable and
+    /// writable.
+    pub fn from_slice(data: &[u8]) -> io::Result<Self> {
+        Self::from_slice_in(data, Backend::Locked)
+    }
+
+    /// Maps a region from `backend` holding a copy of `data`, readable and writable.
+    pub fn from_slice_in(data: &[u8], backend: Backend) -> io::Result<Self> {
+        let mut region = Self::new_in(data.len(), backend)?;
+        region.copy_from_slice(data);
+        Ok(region)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// False when the pages may be swapped out, see the module documentation.
+    pub fn is_locked(&self) -> bool {
+        self.level >= ProtectionLevel::Locked
+    }
+
+    /// The backend asked for, which may be more than `protection_level` provides.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    pub fn protection_level(&self) -> ProtectionLevel {
+        self.level
+    }
+
+    pub fn protection(&self) -> Protection {
+        self.protection.get()
+    }
+
+    /// Changes the access allowed to the data with `mprotect`.
+    pub fn set_protection(&self, protection: Protection) -> io::Result<()> {
+        if protection != self.protection.get() {
+            if self.pages_len > 0 {
+                sys::protect(self.pages, self.pages_len, protection)?;
+            }
+            self.protection.set(protection);
+        }
+        Ok(())
+    }
+
+    /// Calls `f` with the data, making it readable for the duration of the call when it
+    /// is not, and restoring the previous protection afterwards.
+    pub fn read<R>(&self, f: impl FnOnce(&[u8]) -> R) -> io::Result<R> {
+        let restore = Restore(self, self.protection());
+        if restore.1 == Protection::NoAccess {
+            self.set_protection(Protection::ReadOnly)?;
+        }
+        Ok(f(restore.0))
+    }
+}
+
+/// Puts back a protection changed by `SecureRegion::read`, also when the reader panics.
+struct Restore<'a>(&'a SecureRegion, Protection);
+
+impl Drop for Restore<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.set_protection(self.1) {
+            warn!("Failed to restore secure memory protection: {}", e);
+        }
+    }
+}
+
+/// Whether `memfd_secret(2)` works in this process, probed once with a single page.
+pub fn secret_memory_available() -> bool {
+    *SECRET_MEMORY.get_or_init(|| match sys::map_secret(sys::page_size()) {
+        Ok(ptr) => {
+            sys::unmap(ptr, sys::page_size());
+            true
+        }
+        Err(e) => {
+            let unavailable = matches!(
+                e.kind(),
+                io::ErrorKind::Unsupported | io::ErrorKind::PermissionDenied
+            );
+            if unavailable {
+                warn!(
+                    "memfd_secret is unavailable ({}); secure memory falls back to mlock",
+                    e
+                );
+            }
+            // Any other error, e.g. an exhausted RLIMIT_MEMLOCK, may pass.
+            !unavailable
+        }
+    })
+}
+
+/// Protection a new region from `backend` gets, given the current `RLIMIT_MEMLOCK` use.
+pub fn protection_level(backend: Backend) -> ProtectionLevel {
+    if backend == Backend::SecretMemory && secret_memory_available() {
+        ProtectionLevel::SecretMemory
+    } else if memlock_limit().is_none_or(|limit| locked_bytes() < limit) {
+        ProtectionLevel::Locked
+    } else {
+        ProtectionLevel::Unlocked
+    }
+}
+
+/// Maps `len` bytes of secret memory, or `None` to fall back to `mlock`.
+fn map_secret(len: usize) -> Option<NonNull<u8>> {
+    if !secret_memory_available() || !reserve_lock(len) {
+        return None;
+    }
+    match sys::map_secret(len) {
+        Ok(ptr) => Some(ptr),
+        Err(e) => {
+            release_lock(len);
+            warn!("memfd_secret mapping failed ({}); falling back to mlock", e);
+            None
+        }
+    }
+}
+
+/// Bytes all regions may lock together, `None` when unlimited.
+pub fn memlock_limit() -> Option<usize> {
+    sys::memlock_limit()
+}
+
+/// Bytes currently locked by all regions.
+pub fn locked_bytes() -> usize {
+    LOCKED_BYTES.load(Ordering::Relaxed)
+}
+
+/// Accounts `bytes` against `RLIMIT_MEMLOCK`, failing if they would exceed it.
+fn reserve_lock(bytes: usize) -> bool {
+    let limit = memlock_limit().unwrap_or(usize::MAX);
+    LOCKED_BYTES
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |locked| {
+            locked.checked_add(bytes).filter(|total| *total <= limit)
+        })
+        .is_ok()
+}
+
+fn release_lock(bytes: usize) {
+    LOCKED_BYTES.fetch_sub(bytes, Ordering::Relaxed);
+}
+
+impl Deref for SecureRegion {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        assert!(
+            self.protection() != Protection::NoAccess,
+            "SecureRegion is not readable, see `SecureRegion::read`"
+        );
+        // SAFETY: `ptr` is valid for `len` initialized bytes, or dangling with `len` 0.
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for SecureRegion {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        assert!(
+            self.protection() == Protection::ReadWrite,
+            "SecureRegion is not writable"
+        );
+        // SAFETY: as in `deref`, and `&mut self` guarantees exclusive access.
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for SecureRegion {
+    fn drop(&mut self) {
+        if self.pages_len == 0 {
+            return;
+        }
+        // Without write access the pages are unmapped unwiped, which still discards them.
+        if self.set_protection(Protection::ReadWrite).is_ok() {
+            // SAFETY: the canary lies within the data pages, which are readable.
+            let canary = unsafe {
+                std::slice::from_raw_parts(self.ptr.as_ptr().sub(CANARY_LENGTH), CANARY_LENGTH)
+            };
+            if canary != &CANARY[..] {
+                error!("Secure memory canary was overwritten; aborting");
+                std::process::abort();
+            }
+            // SAFETY: `pages` is valid and writable for `pages_len` bytes.
+            unsafe { std::slice::from_raw_parts_mut(self.pages.as_ptr(), self.pages_len) }
+                .zeroize();
+        }
+        if self.level == ProtectionLevel::Locked {
+            sys::unlock(self.pages, self.pages_len);
+        }
+        release_lock(self.reserved);
+        let page_size = sys::page_size();
+        // SAFETY: the mapping starts one guard page before the data pages.
+        let mapping = unsafe { NonNull::new_unchecked(self.pages.as_ptr().sub(page_size)) };
+        sys::unmap(mapping, self.pages_len + 2 * page_size);
+    }
+}
+
+impl Clone for SecureRegion {
+    /// Copies into a new region with the same protection; panics if no memory can be
+    /// mapped or protected.
+    fn clone(&self) -> Self {
+        let copy = self
+            .read(|data| Self::from_slice_in(data, self.backend))
+            .and_then(|copy| copy)
+            .expect("Failed to map secure memory");
+        copy.set_protection(self.protection())
+            .expect("Failed to protect secure memory");
+        copy
+    }
+}
+
+impl PartialEq for SecureRegion {
+    /// Compares in constant time; panics if either region cannot be made readable.
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && self
+                .read(|x| {
+                    other.read(|y| {
+                        x.iter()
+                            .zip(y.iter())
+                            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+                            == 0
+                    })
+                })
+                .and_then(|equal| equal)
+                .expect("Failed to protect secure memory")
+    }
+}
+
+impl Eq for SecureRegion {}
+
+impl fmt::Debug for SecureRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecureRegion")
+            .field("len", &self.len)
+            .field("level", &self.level)
+            .field("protection", &self.protection())
+            .finish_non_exhaustive()
+    }
+}
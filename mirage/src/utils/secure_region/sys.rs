@@ -0,0 +1,199 @@
+//! Platform calls behind `SecureRegion`. Failures to lock, advise or protect are reported
+//! to the caller or ignored, never fatal: they only weaken the protection. Without
+//! `mprotect` the guard pages are ordinary memory.
+
+use std::io;
+use std::ptr::NonNull;
+
+use super::Protection;
+
+#[cfg(unix)]
+pub fn page_size() -> usize {
+    // SAFETY: `sysconf` has no preconditions.
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    usize::try_from(size).unwrap_or(4096)
+}
+
+#[cfg(not(unix))]
+pub fn page_size() -> usize {
+    4096
+}
+
+/// Maps `len` zeroed bytes, a multiple of the page size, readable and writable.
+#[cfg(unix)]
+pub fn map(len: usize) -> io::Result<NonNull<u8>> {
+    // SAFETY: an anonymous private mapping at an address of the kernel's choice.
+    let ptr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(NonNull::new(ptr as *mut u8).expect("mmap returned null"))
+}
+
+#[cfg(not(unix))]
+pub fn map(len: usize) -> io::Result<NonNull<u8>> {
+    let layout = std::alloc::Layout::from_size_align(len, page_size())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: `len` is non-zero.
+    NonNull::new(unsafe { std::alloc::alloc_zeroed(layout) })
+        .ok_or_else(|| io::Error::from(io::ErrorKind::OutOfMemory))
+}
+
+/// Maps `len` zeroed bytes of `memfd_secret(2)` memory, readable and writable. The pages
+/// are removed from the kernel's direct map and implicitly locked.
+#[cfg(all(
+    target_os = "linux",
+    any(
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "riscv64"
+    )
+))]
+pub fn map_secret(len: usize) -> io::Result<NonNull<u8>> {
+    // SAFETY: `memfd_secret` takes only flags.
+    let fd = unsafe { libc::syscall(libc::SYS_memfd_secret, libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fd = fd as libc::c_int;
+    let size =
+        libc::off_t::try_from(len).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e));
+    // SAFETY: sizing and mapping the descriptor created above; the mapping keeps the
+    // memory alive once the descriptor is closed.
+    let ptr = size.and_then(|size| unsafe {
+        if libc::ftruncate(fd, size) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ptr = libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            fd,
+            0,
+        );
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(NonNull::new(ptr as *mut u8).expect("mmap returned null"))
+    });
+    // SAFETY: closing the descriptor created above.
+    unsafe {
+        libc::close(fd);
+    }
+    ptr
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "riscv64"
+    )
+)))]
+pub fn map_secret(_len: usize) -> io::Result<NonNull<u8>> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Unmaps memory returned by `map` or `map_secret` with the same `len`.
+#[cfg(unix)]
+pub fn unmap(ptr: NonNull<u8>, len: usize) {
+    // SAFETY: `ptr` and `len` describe a mapping made by `map` that is no longer used.
+    unsafe {
+        libc::munmap(ptr.as_ptr() as *mut libc::c_void, len);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn unmap(ptr: NonNull<u8>, len: usize) {
+    let layout = std::alloc::Layout::from_size_align(len, page_size()).unwrap();
+    // SAFETY: `ptr` was allocated by `map` with this layout.
+    unsafe { std::alloc::dealloc(ptr.as_ptr(), layout) }
+}
+
+/// Keeps the pages out of core dumps and out of forked children.
+#[cfg(target_os = "linux")]
+pub fn advise(ptr: NonNull<u8>, len: usize) {
+    for advice in [libc::MADV_DONTDUMP, libc::MADV_WIPEONFORK] {
+        // SAFETY: advising on pages we mapped; older kernels reject unknown advice.
+        unsafe {
+            libc::madvise(ptr.as_ptr() as *mut libc::c_void, len, advice);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn advise(_ptr: NonNull<u8>, _len: usize) {}
+
+/// Applies `protection` to whole pages starting at `ptr`.
+#[cfg(unix)]
+pub fn protect(ptr: NonNull<u8>, len: usize, protection: Protection) -> io::Result<()> {
+    let prot = match protection {
+        Protection::NoAccess => libc::PROT_NONE,
+        Protection::ReadOnly => libc::PROT_READ,
+        Protection::ReadWrite => libc::PROT_READ | libc::PROT_WRITE,
+    };
+    // SAFETY: changing the protection of pages we mapped.
+    if unsafe { libc::mprotect(ptr.as_ptr() as *mut libc::c_void, len, prot) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn protect(_ptr: NonNull<u8>, _len: usize, _protection: Protection) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+pub fn lock(ptr: NonNull<u8>, len: usize) -> bool {
+    // SAFETY: locking pages we mapped.
+    unsafe { libc::mlock(ptr.as_ptr() as *const libc::c_void, len) == 0 }
+}
+
+#[cfg(not(unix))]
+pub fn lock(_ptr: NonNull<u8>, _len: usize) -> bool {
+    false
+}
+
+#[cfg(unix)]
+pub fn unlock(ptr: NonNull<u8>, len: usize) {
+    // SAFETY: unlocking pages we locked.
+    unsafe {
+        libc::munlock(ptr.as_ptr() as *const libc::c_void, len);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn unlock(_ptr: NonNull<u8>, _len: usize) {}
+
+#[cfg(unix)]
+pub fn memlock_limit() -> Option<usize> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid out pointer.
+    if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } != 0 {
+        return Some(0);
+    }
+    if limit.rlim_cur == libc::RLIM_INFINITY {
+        return None;
+    }
+    Some(usize::try_from(limit.rlim_cur).unwrap_or(usize::MAX))
+}
+
+#[cfg(not(unix))]
+pub fn memlock_limit() -> Option<usize> {
+    Some(0)
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use mirage::actors::encryption::suite::CipherSuite;
+use mirage::actors::encryption::{AesError, Input, Output};
+use mirage::actors::Actor;
+
+use common::actors;
+
+fn into_buffer(output: Output) -> Vec<u8> {
+    match output {
+        Output::Buffer(buffer) => buffer,
+        Output::File(path) => panic!("unexpected file output {:?}", path),
+    }
+}
+
+#[tokio::test]
+async fn run_returns_the_processed_buffer() {
+    let (encryptor, decryptor) = actors(CipherSuite::ChaCha20Poly1305);
+
+    let job = encryptor
+        .run(Some(Input::Buffer(b"secret".to_vec())))
+        .unwrap();
+    let ciphertext = into_buffer(job.await.unwrap().unwrap());
+    assert_ne!(ciphertext, b"secret");
+
+    let job = decryptor.run(Some(Input::Buffer(ciphertext))).unwrap();
+    assert_eq!(into_buffer(job.await.unwrap().unwrap()), b"secret");
+
+    assert_eq!(encryptor.pending_jobs(), 0);
+    assert!(encryptor.run(None).is_none());
+}
+
+#[tokio::test]
+async fn run_carries_associated_data() {
+    let (encryptor, decryptor) = actors(CipherSuite::Aes256Gcm);
+    let with_aad = |input, aad: &[u8]| Some(Input::WithAad(Box::new(input), aad.to_vec()));
+
+    let job = encryptor.run(with_aad(Input::Bit(1), b"record-7")).unwrap();
+    let ciphertext = into_buffer(job.await.unwrap().unwrap());
+
+    let job = decryptor
+        .run(with_aad(Input::Buffer(ciphertext.clone()), b"record-8"))
+        .unwrap();
+    assert!(job.await.unwrap().is_err());
+
+    let job = decryptor
+        .run(with_aad(Input::Buffer(ciphertext), b"record-7"))
+        .unwrap();
+    assert_eq!(into_buffer(job.await.unwrap().unwrap()), [1]);
+}
+
+#[tokio::test]
+async fn failures_are_reported_to_the_caller() {
+    let (_, decryptor) = actors(CipherSuite::Aes256Gcm);
+
+    let job = decryptor.run(Some(Input::Buffer(vec![0; 8]))).unwrap();
+    assert!(matches!(
+        job.await.unwrap(),
+        Err(AesError::EnvelopeError(_))
+    ));
+    assert!(decryptor.is_alive());
+}
+
+#[tokio::test]
+async fn stopped_actor_rejects_work() {
+    let (encryptor, _) = actors(CipherSuite::Aes128Gcm);
+    encryptor.stop();
+
+    assert!(!encryptor.is_alive());
+    let job = encryptor.run(Some(Input::Bit(0))).unwrap();
+    assert!(matches!(job.await.unwrap(), Err(AesError::ActorStopped)));
+}
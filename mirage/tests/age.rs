@@ -0,0 +1,204 @@
+//! The files under `tests/age/testkit` are the age test vectors from the Community
+//! Cryptography Test Vectors project (C2SP/CCTV), as shipped with the `age` crate. The
+//! ASCII-armored vectors are left out since armor is not supported.
+
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use mirage::actors::encryption::age::error::AgeError;
+use mirage::actors::encryption::age::scrypt::{ScryptIdentity, ScryptRecipient};
+use mirage::actors::encryption::age::x25519::{X25519Identity, X25519Recipient};
+use mirage::actors::encryption::age::{self, Identity, Recipient};
+use mirage::actors::encryption::AesError;
+
+/// Keeps the scrypt vectors fast; the testkit expects `scrypt_work_factor_23` to fail.
+const TESTKIT_MAX_WORK_FACTOR: u8 = 16;
+
+struct Vector {
+    expect: String,
+    payload_sha256: Option<String>,
+    identities: Vec<Identity>,
+    file: Vec<u8>,
+}
+
+fn parse_vector(contents: &[u8]) -> Vector {
+    let split = contents
+        .windows(2)
+        .position(|window| window == b"\n\n")
+        .expect("metadata ends with a blank line");
+    let metadata = std::str::from_utf8(&contents[..split]).unwrap();
+
+    let mut vector = Vector {
+        expect: String::new(),
+        payload_sha256: None,
+        identities: Vec::new(),
+        file: contents[split + 2..].to_vec(),
+    };
+    for line in metadata.lines() {
+        let (key, value) = line.split_once(": ").unwrap();
+        match key {
+            "expect" => vector.expect = value.to_string(),
+            "payload" => vector.payload_sha256 = Some(value.to_string()),
+            "identity" => vector.identities.push(Identity::parse(value).unwrap()),
+            "passphrase" => {
+                vector
+                    .identities
+                    .push(Identity::Scrypt(ScryptIdentity::with_max_work_factor(
+                        value.as_bytes(),
+                        TESTKIT_MAX_WORK_FACTOR,
+                    )))
+            }
+            _ => {}
+        }
+    }
+    vector
+}
+
+fn outcome(result: &Result<(), AesError>) -> &'static str {
+    match result {
+        Ok(()) => "success",
+        Err(AesError::AgeError(error)) => match error {
+            AgeError::MalformedHeader(_)
+            | AgeError::UnsupportedVersion(_)
+            | AgeError::InvalidStanza(_)
+            | AgeError::WorkFactorTooHigh(_) => "header failure",
+            AgeError::HeaderMacMismatch => "HMAC failure",
+            AgeError::NoMatchingIdentity => "no match",
+            AgeError::PayloadFailure(_) => "payload failure",
+            _ => "unexpected error",
+        },
+        Err(AesError::IoError(_)) => "header failure",
+        Err(_) => "unexpected error",
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[tokio::test]
+async fn testkit_vectors() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/age/testkit");
+    let mut entries = std::fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    entries.sort();
+    assert!(!entries.is_empty());
+
+    let mut failures = Vec::new();
+    for path in &entries {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let vector = parse_vector(&std::fs::read(path).unwrap());
+
+        let mut plaintext = Vec::new();
+        let result =
+            age::decrypt_stream(&vector.identities, &mut &vector.file[..], &mut plaintext).await;
+        let actual = outcome(&result);
+
+        if actual != vector.expect {
+            failures.push(format!(
+                "{}: expected {}, got {:?}",
+                name, vector.expect, result
+            ));
+        } else if actual == "success" {
+            if let Some(expected) = &vector.payload_sha256 {
+                if &sha256_hex(&plaintext) != expected {
+                    failures.push(format!("{}: payload hash mismatch", name));
+                }
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+async fn roundtrip(recipients: &[Recipient], identities: &[Identity], plaintext: &[u8]) {
+    let mut file = Vec::new();
+    age::encrypt_stream(recipients, &mut &plaintext[..], &mut file)
+        .await
+        .unwrap();
+    assert!(file.starts_with(b"age-encryption.org/v1\n"));
+
+    let mut decrypted = Vec::new();
+    age::decrypt_stream(identities, &mut &file[..], &mut decrypted)
+        .await
+        .unwrap();
+    assert_eq!(decrypted, plaintext);
+}
+
+#[tokio::test]
+async fn x25519_roundtrip_across_chunk_boundaries() {
+    let identity = X25519Identity::generate();
+    let recipient = Recipient::X25519(identity.to_recipient());
+    let identities = [Identity::X25519(identity)];
+
+    for length in [0, 1, 64 * 1024, 64 * 1024 + 1, 3 * 64 * 1024] {
+        let plaintext = vec![0x5a; length];
+        roundtrip(std::slice::from_ref(&recipient), &identities, &plaintext).await;
+    }
+}
+
+#[tokio::test]
+async fn any_of_several_recipients_can_decrypt() {
+    let alice = X25519Identity::generate();
+    let bob = X25519Identity::generate();
+    let recipients = [
+        Recipient::X25519(alice.to_recipient()),
+        Recipient::X25519(bob.to_recipient()),
+    ];
+
+    roundtrip(&recipients, &[Identity::X25519(bob)], b"for alice and bob").await;
+
+    let mut file = Vec::new();
+    age::encrypt_stream(&recipients, &mut &b"for alice and bob"[..], &mut file)
+        .await
+        .unwrap();
+    let stranger = [Identity::X25519(X25519Identity::generate())];
+    let result = age::decrypt_stream(&stranger, &mut &file[..], &mut Vec::new()).await;
+    assert!(matches!(
+        result,
+        Err(AesError::AgeError(AgeError::NoMatchingIdentity))
+    ));
+}
+
+#[tokio::test]
+async fn scrypt_roundtrip() {
+    let recipients = [Recipient::Scrypt(ScryptRecipient::new(b"hunter2", 10))];
+    let identities = [Identity::Scrypt(ScryptIdentity::new(b"hunter2"))];
+    roundtrip(&recipients, &identities, b"passphrase protected").await;
+}
+
+#[tokio::test]
+async fn scrypt_must_be_the_only_recipient() {
+    let recipients = [
+        Recipient::Scrypt(ScryptRecipient::new(b"hunter2", 10)),
+        Recipient::X25519(X25519Identity::generate().to_recipient()),
+    ];
+    let result = age::encrypt_stream(&recipients, &mut &b"data"[..], &mut Vec::new()).await;
+    assert!(matches!(
+        result,
+        Err(AesError::AgeError(AgeError::InvalidRecipient(_)))
+    ));
+}
+
+#[test]
+fn keys_roundtrip_through_bech32() {
+    let identity = X25519Identity::generate();
+    let encoded = identity.encode();
+    assert!(encoded.starts_with("AGE-SECRET-KEY-1"));
+
+    let parsed = X25519Identity::parse(&encoded).unwrap();
+    assert_eq!(parsed.to_recipient(), identity.to_recipient());
+
+    let recipient = identity.to_recipient().to_string();
+    assert!(recipient.starts_with("age1"));
+    assert_eq!(
+        X25519Recipient::parse(&recipient).unwrap(),
+        identity.to_recipient()
+    );
+    assert!(X25519Recipient::parse(&encoded).is_err());
+}
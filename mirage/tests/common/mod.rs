@@ -0,0 +1,48 @@
+// Each test binary uses a different subset of these helpers.
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use mirage::actors::encryption::decryptor::Decryptor;
+use mirage::actors::encryption::encryptor::Encryptor;
+use mirage::actors::encryption::kdf::{KdfCost, KdfParams};
+use mirage::actors::encryption::suite::CipherSuite;
+use mirage::utils::key_generator::generate_key;
+use tempfile::TempDir;
+
+/// Handle of the runtime driving the current test.
+pub fn runtime_handle() -> Arc<tokio::runtime::Handle> {
+    Arc::new(tokio::runtime::Handle::current())
+}
+
+/// An encryptor and decryptor sharing a fresh key for `suite`, on the current runtime.
+pub fn actors(suite: CipherSuite) -> (Encryptor, Decryptor) {
+    let key = generate_key(suite);
+    (
+        Encryptor::with_suite(suite, key.clone(), runtime_handle()),
+        Decryptor::with_suite(suite, key, runtime_handle()),
+    )
+}
+
+/// `actors` for holders that share them, such as stores.
+pub fn shared_actors(suite: CipherSuite) -> (Arc<Encryptor>, Arc<Decryptor>) {
+    let (encryptor, decryptor) = actors(suite);
+    (Arc::new(encryptor), Arc::new(decryptor))
+}
+
+/// Fresh directory under the system temp dir, removed when dropped even if the test panics.
+pub fn scratch_dir() -> TempDir {
+    tempfile::Builder::new()
+        .prefix("mirage-")
+        .tempdir()
+        .unwrap()
+}
+
+/// Scrypt parameters cheap enough for debug builds.
+pub fn fast_kdf() -> KdfParams {
+    KdfParams::new(KdfCost::Scrypt {
+        log_n: 10,
+        r: 8,
+        p: 1,
+    })
+}
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use mirage::actors::encryption::keys::error::KeyError;
+use mirage::actors::encryption::keys::hierarchy::KeyEncryptionKey;
+use mirage::actors::encryption::suite::CipherSuite;
+use mirage::actors::encryption::AesError;
+use mirage::actors::memory::generic::secure_key_value_store::blind_index::{NameIndex, NameKey};
+use mirage::actors::memory::generic::secure_key_value_store::data_keys::{DataKeyScope, DataKeys};
+use mirage::actors::memory::generic::secure_key_value_store::error::SecureStoreError;
+use mirage::actors::memory::generic::secure_key_value_store::SecureKeyValueStore;
+
+fn data_keys(scope: DataKeyScope) -> DataKeys {
+    let runtime_handle = Arc::new(tokio::runtime::Handle::current());
+    DataKeys::new(
+        KeyEncryptionKey::generate(),
+        CipherSuite::Aes256Gcm,
+        scope,
+        runtime_handle,
+    )
+}
+
+#[test]
+fn data_keys_only_open_under_their_kek() {
+    let kek = KeyEncryptionKey::generate();
+    let (data_key, wrapped) = kek
+        .generate_data_key(CipherSuite::ChaCha20Poly1305)
+        .unwrap();
+    assert_eq!(wrapped.kek, kek.fingerprint());
+    assert_eq!(kek.unwrap(&wrapped).unwrap(), data_key);
+
+    let other = KeyEncryptionKey::generate();
+    assert!(matches!(
+        other.unwrap(&wrapped),
+        Err(AesError::KeyError(KeyError::KekMismatch))
+    ));
+
+    let rewrapped = kek.rewrap(&wrapped, &other).unwrap();
+    assert_eq!(other.unwrap(&rewrapped).unwrap(), data_key);
+    assert_ne!(rewrapped.wrapped, wrapped.wrapped);
+}
+
+#[tokio::test]
+async fn entries_share_their_namespace_key() {
+    let mut keys = data_keys(DataKeyScope::Namespace);
+    let names = NameIndex::new(&NameKey::generate());
+    let alice = keys.seal(&names, "users/alice", b"alice's token").unwrap();
+    let bob = keys.seal(&names, "users/bob", b"bob's token").unwrap();
+    let build = keys.seal(&names, "build-secret", b"ci token").unwrap();
+    assert!(alice.data_key.is_none());
+
+    assert_eq!(
+        &keys.open(&names, "users/alice", &alice).unwrap()[..],
+        b"alice's token"
+    );
+    assert_eq!(
+        &keys.open(&names, "users/bob", &bob).unwrap()[..],
+        b"bob's token"
+    );
+    assert_eq!(
+        &keys.open(&names, "build-secret", &build).unwrap()[..],
+        b"ci token"
+    );
+
+    // The entry name is authenticated, so values cannot be swapped within a namespace.
+    assert!(keys.open(&names, "users/bob", &alice).is_err());
+
+    // Namespace keys are filed under blind indexes, which another name key does not find.
+    let other_names = NameIndex::new(&NameKey::generate());
+    assert!(matches!(
+        keys.open(&other_names, "users/alice", &alice),
+        Err(AesError::SecureStoreError(SecureStoreError::MissingDataKey))
+    ));
+}
+
+#[tokio::test]
+async fn rotating_the_kek_leaves_values_untouched() {
+    for scope in [DataKeyScope::Namespace, DataKeyScope::Entry] {
+        let mut keys = data_keys(scope);
+        let names = NameIndex::new(&NameKey::generate());
+        let mut values = [
+            (
+                "users/alice",
+                keys.seal(&names, "users/alice", b"one").unwrap(),
+            ),
+            ("users/bob", keys.seal(&names, "users/bob", b"two").unwrap()),
+            ("hosts/db", keys.seal(&names, "hosts/db", b"three").unwrap()),
+        ];
+        let envelopes: Vec<Vec<u8>> = values
+            .iter()
+            .map(|(_, v)| v.envelope.read(<[u8]>::to_vec).unwrap())
+            .collect();
+        let old_kek = keys.kek().clone();
+
+        let new_kek = KeyEncryptionKey::generate();
+        let rewrapped = keys
+            .rotate_kek(new_kek.clone(), values.iter_mut().map(|(_, value)| value))
+            .unwrap();
+        let expected = match scope {
+            DataKeyScope::Namespace => 2,
+            DataKeyScope::Entry => 3,
+        };
+        assert_eq!(rewrapped, expected);
+        assert_eq!(keys.kek().fingerprint(), new_kek.fingerprint());
+
+        for ((name, value), envelope) in values.iter().zip(&envelopes) {
+            value
+                .envelope
+                .read(|stored| assert_eq!(stored, &envelope[..]))
+                .unwrap();
+            if let Some(data_key) = &value.data_key {
+                assert!(old_kek.unwrap(data_key).is_err());
+            }
+            assert!(keys.open(&names, name, value).is_ok());
+        }
+        assert_eq!(
+            &keys.open(&names, "hosts/db", &values[2].1).unwrap()[..],
+            b"three"
+        );
+    }
+}
+
+#[tokio::test]
+async fn stores_rotate_their_kek() {
+    let store = SecureKeyValueStore::with_data_keys(data_keys(DataKeyScope::Entry));
+    let mut store = store.lock().unwrap();
+    store
+        .set("users/alice".to_string(), b"one".to_vec())
+        .unwrap();
+    store.set("users/bob".to_string(), b"two".to_vec()).unwrap();
+    assert!(store.remove("users/bob"));
+
+    assert_eq!(store.rotate_kek(KeyEncryptionKey::generate()).unwrap(), 1);
+    assert_eq!(&store.get("users/alice").unwrap().unwrap()[..], b"one");
+    assert!(store.get("users/bob").unwrap().is_none());
+    assert!(matches!(
+        store.encrypt_value(b"bare"),
+        Err(SecureStoreError::NoStoreKey)
+    ));
+}
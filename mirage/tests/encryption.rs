@@ -0,0 +1,86 @@
+mod common;
+
+use std::sync::Arc;
+
+use mirage::actors::encryption::decryptor::Decryptor;
+use mirage::actors::encryption::encryptor::Encryptor;
+use mirage::actors::encryption::suite::CipherSuite;
+use mirage::actors::encryption::{AesError, TAG_LENGTH};
+
+use common::actors;
+
+#[tokio::test]
+async fn every_message_gets_a_fresh_nonce() {
+    let (encryptor, decryptor) = actors(CipherSuite::Aes256Gcm);
+
+    let first = encryptor.encrypt(b"same plaintext").unwrap();
+    let second = encryptor.encrypt(b"same plaintext").unwrap();
+    assert_ne!(first, second);
+    assert_eq!(decryptor.decrypt(&first).unwrap(), b"same plaintext");
+    assert_eq!(decryptor.decrypt(&second).unwrap(), b"same plaintext");
+
+    // The nonce travels with the ciphertext, so the overhead does not depend on the input.
+    let empty = encryptor.encrypt(b"").unwrap();
+    let hundred = encryptor.encrypt(&[7u8; 100]).unwrap();
+    assert_eq!(hundred.len() - empty.len(), 100);
+}
+
+#[tokio::test]
+async fn keys_of_the_wrong_length_are_rejected() {
+    let runtime_handle = Arc::new(tokio::runtime::Handle::current());
+    let encryptor = Encryptor::with_suite(
+        CipherSuite::Aes256Gcm,
+        vec![0u8; 16],
+        runtime_handle.clone(),
+    );
+    let decryptor = Decryptor::with_suite(CipherSuite::Aes256Gcm, vec![0u8; 16], runtime_handle);
+
+    let error = encryptor.encrypt(b"data").unwrap_err();
+    assert!(matches!(
+        error,
+        AesError::InvalidKeyLength {
+            expected: 32,
+            actual: 16
+        }
+    ));
+    assert_eq!(
+        error.to_string(),
+        "Invalid key length: expected 32 bytes, got 16"
+    );
+    let (valid, _) = actors(CipherSuite::Aes256Gcm);
+    let ciphertext = valid.encrypt(b"data").unwrap();
+    assert!(matches!(
+        decryptor.decrypt(&ciphertext),
+        Err(AesError::InvalidKeyLength {
+            expected: 32,
+            actual: 16
+        })
+    ));
+}
+
+#[tokio::test]
+async fn truncated_ciphertexts_are_rejected() {
+    let (encryptor, decryptor) = actors(CipherSuite::Aes128Gcm);
+    let ciphertext = encryptor.encrypt(b"data").unwrap();
+
+    // Header, then at least a tag's worth of body, then a body the tag must cover.
+    let header = ciphertext.len() - b"data".len() - TAG_LENGTH;
+    for length in 0..header {
+        assert!(matches!(
+            decryptor.decrypt(&ciphertext[..length]),
+            Err(AesError::EnvelopeError(_))
+        ));
+    }
+    for length in header..header + TAG_LENGTH {
+        assert!(matches!(
+            decryptor.decrypt(&ciphertext[..length]),
+            Err(AesError::CiphertextTooShort(body)) if body == length - header
+        ));
+    }
+    for length in header + TAG_LENGTH..ciphertext.len() {
+        assert!(matches!(
+            decryptor.decrypt(&ciphertext[..length]),
+            Err(AesError::AesGcmError(_))
+        ));
+    }
+}
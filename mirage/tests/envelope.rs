@@ -0,0 +1,180 @@
+//! Golden-file tests for the v1 envelope format.
+//!
+//! The files under `tests/golden` were generated when the envelope format was introduced
+//! and are pinned here so the format cannot drift. Never regenerate them; add new files
+//! for new format versions.
+
+use std::sync::Arc;
+
+use mirage::actors::encryption::decryptor::Decryptor;
+use mirage::actors::encryption::encryptor::Encryptor;
+use mirage::actors::encryption::envelope::error::EnvelopeError;
+use mirage::actors::encryption::envelope::{EnvelopeHeader, MAGIC, VERSION_1};
+use mirage::actors::encryption::stream::DEFAULT_CHUNK_SIZE;
+use mirage::actors::encryption::suite::CipherSuite;
+use mirage::actors::encryption::AesError;
+use mirage::utils::secure_region::Backend;
+
+const PLAINTEXT: &[u8] = b"Mirage envelope v1 golden plaintext";
+const KEY_ID: &[u8] = b"golden";
+
+const GOLDEN: [(CipherSuite, &[u8]); 5] = [
+    (
+        CipherSuite::Aes128Gcm,
+        include_bytes!("golden/v1-aes-128-gcm.bin"),
+    ),
+    (
+        CipherSuite::Aes256Gcm,
+        include_bytes!("golden/v1-aes-256-gcm.bin"),
+    ),
+    (
+        CipherSuite::ChaCha20Poly1305,
+        include_bytes!("golden/v1-chacha20-poly1305.bin"),
+    ),
+    (
+        CipherSuite::XChaCha20Poly1305,
+        include_bytes!("golden/v1-xchacha20-poly1305.bin"),
+    ),
+    (
+        CipherSuite::Aes256GcmSiv,
+        include_bytes!("golden/v1-aes-256-gcm-siv.bin"),
+    ),
+];
+
+/// Golden files are sealed with the key `00 01 02 ..` of the suite's length.
+fn golden_key(suite: CipherSuite) -> Vec<u8> {
+    (0..suite.key_length() as u8).collect()
+}
+
+fn decryptor(suite: CipherSuite) -> Decryptor {
+    let runtime_handle = Arc::new(tokio::runtime::Handle::current());
+    Decryptor::with_suite(suite, golden_key(suite), runtime_handle)
+}
+
+#[tokio::test]
+async fn golden_files_decrypt() {
+    for (suite, envelope) in GOLDEN {
+        let plaintext = decryptor(suite).decrypt(envelope).unwrap();
+        assert_eq!(plaintext, PLAINTEXT, "{}", suite);
+    }
+}
+
+#[test]
+fn golden_headers_parse() {
+    for (suite, envelope) in GOLDEN {
+        let (header, length) = EnvelopeHeader::parse(envelope).unwrap();
+        assert_eq!(header.version, VERSION_1);
+        assert_eq!(header.suite, suite);
+        assert_eq!(header.flags, 0);
+        assert_eq!(header.chunk_size as usize, DEFAULT_CHUNK_SIZE);
+        assert_eq!(header.key_id, KEY_ID);
+        assert_eq!(header.nonce_prefix.len(), suite.nonce_prefix_length());
+        assert_eq!(header.encode().unwrap(), envelope[..length]);
+    }
+}
+
+#[tokio::test]
+async fn key_id_is_checked() {
+    let (suite, envelope) = GOLDEN[1];
+
+    let matching = decryptor(suite).with_key_id(KEY_ID.to_vec());
+    assert_eq!(matching.decrypt(envelope).unwrap(), PLAINTEXT);
+
+    let other = decryptor(suite).with_key_id(b"other".to_vec());
+    assert!(matches!(
+        other.decrypt(envelope),
+        Err(AesError::EnvelopeError(EnvelopeError::KeyIdMismatch))
+    ));
+}
+
+#[tokio::test]
+async fn unknown_version_is_rejected() {
+    let (suite, envelope) = GOLDEN[1];
+    let mut envelope = envelope.to_vec();
+    envelope[4] = 2;
+
+    assert!(matches!(
+        decryptor(suite).decrypt(&envelope),
+        Err(AesError::EnvelopeError(EnvelopeError::UnsupportedVersion(
+            2
+        )))
+    ));
+
+    // Streaming readers stop at the version too, before reading a header they cannot lay out.
+    let mut reader = &envelope[..MAGIC.len() + 1];
+    assert!(matches!(
+        EnvelopeHeader::read_from(&mut reader).await,
+        Err(AesError::EnvelopeError(EnvelopeError::UnsupportedVersion(
+            2
+        )))
+    ));
+}
+
+#[tokio::test]
+async fn malformed_headers_are_rejected() {
+    let (suite, envelope) = GOLDEN[1];
+
+    let mut bad_magic = envelope.to_vec();
+    bad_magic[0] = b'X';
+    assert!(matches!(
+        decryptor(suite).decrypt(&bad_magic),
+        Err(AesError::EnvelopeError(EnvelopeError::BadMagic))
+    ));
+
+    let mut unknown_flags = envelope.to_vec();
+    unknown_flags[6] = 0x80;
+    assert!(matches!(
+        decryptor(suite).decrypt(&unknown_flags),
+        Err(AesError::EnvelopeError(EnvelopeError::UnknownFlags(0x80)))
+    ));
+
+    assert!(matches!(
+        decryptor(suite).decrypt(&envelope[..10]),
+        Err(AesError::EnvelopeError(EnvelopeError::Truncated))
+    ));
+}
+
+#[tokio::test]
+async fn header_is_authenticated() {
+    let (suite, envelope) = GOLDEN[1];
+    let (_, length) = EnvelopeHeader::parse(envelope).unwrap();
+
+    // Flip a bit in the key id, which parses fine but is bound into every chunk.
+    let mut tampered = envelope.to_vec();
+    tampered[length - suite.nonce_prefix_length() - 2] ^= 1;
+    assert!(matches!(
+        decryptor(suite).decrypt(&tampered),
+        Err(AesError::AesGcmError(_))
+    ));
+}
+
+#[tokio::test]
+async fn associated_data_must_match() {
+    let suite = CipherSuite::Aes256Gcm;
+    let runtime_handle = Arc::new(tokio::runtime::Handle::current());
+    let encryptor = Encryptor::with_suite(suite, golden_key(suite), runtime_handle);
+
+    let envelope = encryptor
+        .encrypt_with_aad(PLAINTEXT, b"tenant=42/record=7")
+        .unwrap();
+
+    let decryptor = decryptor(suite);
+    assert_eq!(
+        decryptor
+            .decrypt_with_aad(&envelope, b"tenant=42/record=7")
+            .unwrap(),
+        PLAINTEXT
+    );
+    assert!(decryptor
+        .decrypt_with_aad(&envelope, b"tenant=42/record=8")
+        .is_err());
+    assert!(decryptor.decrypt(&envelope).is_err());
+
+    let region = decryptor
+        .decrypt_to_region(&envelope, b"tenant=42/record=7", Backend::Locked)
+        .unwrap();
+    assert_eq!(&region[..], PLAINTEXT);
+    assert!(decryptor
+        .decrypt_to_region(&envelope, b"tenant=42/record=8", Backend::Locked)
+        .is_err());
+}
@@ -0,0 +1,199 @@
+mod common;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+use mirage::actors::encryption::stream::DEFAULT_CHUNK_SIZE;
+use mirage::actors::encryption::suite::CipherSuite;
+use mirage::actors::encryption::{Input, Output};
+use mirage::actors::Actor;
+use mirage::utils::file_system::AtomicFile;
+use rand::RngCore;
+use tokio::io::AsyncWriteExt;
+
+use common::{actors, scratch_dir};
+
+fn random_bytes(length: usize) -> Vec<u8> {
+    let mut data = vec![0u8; length];
+    rand::thread_rng().fill_bytes(&mut data);
+    data
+}
+
+#[tokio::test]
+async fn stream_matches_buffer_format_across_chunk_boundaries() {
+    let (encryptor, decryptor) = actors(CipherSuite::Aes256Gcm);
+
+    for length in [
+        0,
+        1,
+        DEFAULT_CHUNK_SIZE - 1,
+        DEFAULT_CHUNK_SIZE,
+        DEFAULT_CHUNK_SIZE + 1,
+        3 * DEFAULT_CHUNK_SIZE,
+    ] {
+        let plaintext = random_bytes(length);
+
+        let mut envelope = Vec::new();
+        encryptor
+            .encrypt_stream(&mut plaintext.as_slice(), &mut envelope, b"ctx")
+            .await
+            .unwrap();
+        assert_eq!(
+            decryptor.decrypt_with_aad(&envelope, b"ctx").unwrap(),
+            plaintext,
+            "length {}",
+            length
+        );
+
+        let buffered = encryptor.encrypt_with_aad(&plaintext, b"ctx").unwrap();
+        let mut decrypted = Vec::new();
+        decryptor
+            .decrypt_stream(&mut buffered.as_slice(), &mut decrypted, b"ctx")
+            .await
+            .unwrap();
+        assert_eq!(decrypted, plaintext, "length {}", length);
+    }
+}
+
+#[tokio::test]
+async fn truncated_stream_is_rejected() {
+    let (encryptor, decryptor) = actors(CipherSuite::ChaCha20Poly1305);
+    let envelope = encryptor
+        .encrypt(&random_bytes(2 * DEFAULT_CHUNK_SIZE + 10))
+        .unwrap();
+
+    // Cut exactly after the first sealed chunk, which still looks like a whole chunk.
+    let header_length = envelope.len() - (2 * DEFAULT_CHUNK_SIZE + 10) - 3 * 16;
+    let truncated = &envelope[..header_length + DEFAULT_CHUNK_SIZE + 16];
+
+    let mut decrypted = Vec::new();
+    assert!(decryptor
+        .decrypt_stream(&mut &truncated[..], &mut decrypted, &[])
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn file_is_replaced_in_place_keeping_permissions() {
+    let (encryptor, decryptor) = actors(CipherSuite::XChaCha20Poly1305);
+    let dir = scratch_dir();
+    let path = dir.path().join("data.bin");
+    let plaintext = random_bytes(DEFAULT_CHUNK_SIZE * 2 + 7);
+    fs::write(&path, &plaintext).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+    let modified = fs::metadata(&path).unwrap().modified().unwrap();
+
+    let job = encryptor.run(Some(Input::File(path.clone()))).unwrap();
+    assert!(matches!(job.await.unwrap().unwrap(), Output::File(p) if p == path));
+    let metadata = fs::metadata(&path).unwrap();
+    assert_ne!(fs::read(&path).unwrap(), plaintext);
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+    assert_eq!(metadata.modified().unwrap(), modified);
+
+    let job = decryptor.run(Some(Input::File(path.clone()))).unwrap();
+    job.await.unwrap().unwrap();
+    assert_eq!(fs::read(&path).unwrap(), plaintext);
+
+    // Only the file itself is left behind, no temporary files.
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[tokio::test]
+async fn file_can_be_written_to_a_separate_path() {
+    let (encryptor, decryptor) = actors(CipherSuite::Aes128Gcm);
+    let dir = scratch_dir();
+    let source = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("plain.txt.mirage");
+    fs::write(&source, b"separate output").unwrap();
+
+    let job = encryptor
+        .run(Some(Input::FileTo(source.clone(), encrypted.clone())))
+        .unwrap();
+    assert!(matches!(job.await.unwrap().unwrap(), Output::File(p) if p == encrypted));
+    assert_eq!(fs::read(&source).unwrap(), b"separate output");
+
+    let restored = dir.path().join("restored.txt");
+    let job = decryptor
+        .run(Some(Input::FileTo(encrypted, restored.clone())))
+        .unwrap();
+    job.await.unwrap().unwrap();
+    assert_eq!(fs::read(&restored).unwrap(), b"separate output");
+}
+
+#[tokio::test]
+async fn failed_decryption_leaves_the_file_untouched() {
+    let (encryptor, _) = actors(CipherSuite::Aes256Gcm);
+    let (_, other_decryptor) = actors(CipherSuite::Aes256Gcm);
+    let dir = scratch_dir();
+    let path = dir.path().join("data.bin");
+    fs::write(&path, b"original").unwrap();
+
+    encryptor
+        .run(Some(Input::File(path.clone())))
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    let envelope = fs::read(&path).unwrap();
+
+    let job = other_decryptor
+        .run(Some(Input::File(path.clone())))
+        .unwrap();
+    assert!(job.await.unwrap().is_err());
+    assert_eq!(fs::read(&path).unwrap(), envelope);
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[tokio::test]
+async fn new_files_are_private_to_the_owner() {
+    let dir = scratch_dir();
+    let path = dir.path().join("secret.bin");
+
+    let mut output = AtomicFile::create(&path).await.unwrap();
+    let temp = fs::read_dir(dir.path()).unwrap().next().unwrap().unwrap();
+    assert_eq!(temp.metadata().unwrap().permissions().mode() & 0o777, 0o600);
+    output.file().write_all(b"secret").await.unwrap();
+    output.commit(None).await.unwrap();
+
+    let metadata = fs::metadata(&path).unwrap();
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    assert_eq!(fs::read(&path).unwrap(), b"secret");
+}
+
+#[tokio::test]
+async fn failed_commits_remove_the_temporary_file() {
+    let dir = scratch_dir();
+    // A non-empty directory cannot be replaced by a rename.
+    let destination = dir.path().join("occupied");
+    fs::create_dir(&destination).unwrap();
+    fs::write(destination.join("kept"), b"kept").unwrap();
+
+    let mut output = AtomicFile::create(&destination).await.unwrap();
+    output.file().write_all(b"data").await.unwrap();
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    assert!(output.commit(None).await.is_err());
+
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    assert_eq!(fs::read(destination.join("kept")).unwrap(), b"kept");
+}
+
+#[tokio::test]
+async fn new_files_never_replace_an_existing_one() {
+    let dir = scratch_dir();
+    let path = dir.path().join("keyring");
+    fs::write(&path, b"existing").unwrap();
+
+    let mut output = AtomicFile::create(&path).await.unwrap();
+    output.file().write_all(b"replacement").await.unwrap();
+    let err = output.commit_new().await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+    assert_eq!(fs::read(&path).unwrap(), b"existing");
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    fs::remove_file(&path).unwrap();
+    let mut output = AtomicFile::create(&path).await.unwrap();
+    output.file().write_all(b"replacement").await.unwrap();
+    output.commit_new().await.unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"replacement");
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
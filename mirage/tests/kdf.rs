@@ -0,0 +1,117 @@
+mod common;
+
+use hex_literal::hex;
+use mirage::actors::encryption::decryptor::Decryptor;
+use mirage::actors::encryption::encryptor::Encryptor;
+use mirage::actors::encryption::envelope::{EnvelopeHeader, FLAG_PASSPHRASE};
+use mirage::actors::encryption::kdf::error::KdfError;
+use mirage::actors::encryption::kdf::{KdfCost, KdfParams};
+use mirage::actors::encryption::suite::CipherSuite;
+use mirage::actors::encryption::AesError;
+
+use common::runtime_handle;
+
+/// Far below the calibration floors, to keep the tests fast.
+const CHEAP_ARGON2: KdfCost = KdfCost::Argon2id {
+    memory_kib: 64,
+    iterations: 1,
+    parallelism: 1,
+};
+const CHEAP_SCRYPT: KdfCost = KdfCost::Scrypt {
+    log_n: 4,
+    r: 8,
+    p: 1,
+};
+
+#[test]
+fn scrypt_matches_reference_implementation() {
+    // Python's hashlib.scrypt(b"password", salt=b"mirage-salt", n=1024, r=8, p=1, dklen=32)
+    let params = KdfParams {
+        cost: KdfCost::Scrypt {
+            log_n: 10,
+            r: 8,
+            p: 1,
+        },
+        salt: b"mirage-salt".to_vec(),
+    };
+    assert_eq!(
+        params.derive(b"password", 32).unwrap().as_bytes(),
+        hex!("2b20740132a2c296731da6b8ec9383b35a33c8000c2e8c6457d70006b96b79d7")
+    );
+}
+
+#[test]
+fn short_salt_is_rejected() {
+    let params = KdfParams {
+        cost: CHEAP_SCRYPT,
+        salt: b"NaCl".to_vec(),
+    };
+    assert!(matches!(
+        params.derive(b"password", 32),
+        Err(AesError::KdfError(KdfError::InvalidSalt(4)))
+    ));
+}
+
+#[tokio::test]
+async fn passphrase_roundtrip() {
+    for cost in [CHEAP_ARGON2, CHEAP_SCRYPT] {
+        let params = KdfParams::new(cost);
+        let encryptor = Encryptor::with_kdf(
+            CipherSuite::XChaCha20Poly1305,
+            b"correct horse",
+            params.clone(),
+            runtime_handle(),
+        )
+        .unwrap();
+        let envelope = encryptor.encrypt(b"survives a restart").unwrap();
+
+        let (header, _) = EnvelopeHeader::parse(&envelope).unwrap();
+        assert_eq!(header.flags & FLAG_PASSPHRASE, FLAG_PASSPHRASE);
+        assert_eq!(header.kdf, Some(params));
+
+        let decryptor = Decryptor::with_passphrase(b"correct horse", runtime_handle());
+        assert_eq!(decryptor.decrypt(&envelope).unwrap(), b"survives a restart");
+
+        let wrong = Decryptor::with_passphrase(b"wrong horse", runtime_handle());
+        assert!(matches!(
+            wrong.decrypt(&envelope),
+            Err(AesError::AesGcmError(_))
+        ));
+    }
+}
+
+#[tokio::test]
+async fn passphrase_decryptor_requires_kdf_header() {
+    let suite = CipherSuite::Aes256Gcm;
+    let key = (0..32).collect::<Vec<u8>>();
+    let envelope = Encryptor::with_suite(suite, key, runtime_handle())
+        .encrypt(b"raw key")
+        .unwrap();
+
+    let decryptor = Decryptor::with_passphrase(b"anything", runtime_handle());
+    assert!(matches!(
+        decryptor.decrypt(&envelope),
+        Err(AesError::KdfError(KdfError::MissingParameters))
+    ));
+}
+
+#[tokio::test]
+async fn excessive_cost_is_rejected() {
+    let encryptor = Encryptor::with_kdf(
+        CipherSuite::Aes256Gcm,
+        b"pw",
+        KdfParams::new(CHEAP_SCRYPT),
+        runtime_handle(),
+    )
+    .unwrap();
+    let mut envelope = encryptor.encrypt(b"data").unwrap();
+
+    // The scrypt log_n byte directly follows the cost length; ask for 2^40 blocks.
+    let (_, length) = EnvelopeHeader::parse(&envelope).unwrap();
+    envelope[length - 9] = 40;
+    let decryptor = Decryptor::with_passphrase(b"pw", runtime_handle());
+    assert!(matches!(
+        decryptor.decrypt(&envelope),
+        Err(AesError::KdfError(KdfError::InvalidParameters(_)))
+    ));
+}
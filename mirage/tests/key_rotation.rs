@@ -0,0 +1,234 @@
+mod common;
+
+use std::sync::Arc;
+
+use mirage::actors::encryption::suite::CipherSuite;
+use mirage::actors::encryption::AesError;
+use mirage::actors::memory::generic::secure_key_value_store::error::SecureStoreError;
+use mirage::actors::memory::generic::secure_key_value_store::rotation::{
+    RotationProgress, StoreKeys,
+};
+use mirage::actors::memory::generic::secure_key_value_store::SecureKeyValueStore;
+use mirage::actors::memory::secure_memory_provider::{Encryption, SecureMemoryProvider};
+use mirage::actors::memory::Input;
+use mirage::utils::key_generator::generate_key;
+
+use common::shared_actors;
+
+#[tokio::test]
+async fn values_stay_readable_across_a_rotation() {
+    let (encryptor, decryptor) = shared_actors(CipherSuite::Aes256Gcm);
+    let old_decryptor = Arc::downgrade(&decryptor);
+    let mut keys = StoreKeys::new(encryptor, decryptor);
+    let mut first = keys.seal(b"first").unwrap();
+    let mut second = keys.seal(b"second").unwrap();
+
+    let (encryptor, decryptor) = shared_actors(CipherSuite::XChaCha20Poly1305);
+    assert_eq!(keys.begin_rotation(encryptor, decryptor).unwrap(), 1);
+    assert!(keys.is_rotating());
+    let (encryptor, decryptor) = shared_actors(CipherSuite::Aes128Gcm);
+    assert!(matches!(
+        keys.begin_rotation(encryptor, decryptor),
+        Err(SecureStoreError::RotationInProgress)
+    ));
+
+    // Values under both keys open while the rotation runs; new ones use the new key.
+    let third = keys.seal(b"third").unwrap();
+    assert_eq!(third.key_version, 1);
+    assert!(!keys.needs_reseal(&third));
+    assert!(keys.needs_reseal(&first));
+
+    keys.reseal(&mut first).unwrap();
+    assert_eq!(first.key_version, 1);
+    assert_eq!(&keys.open(&first).unwrap()[..], b"first");
+    assert_eq!(&keys.open(&second).unwrap()[..], b"second");
+
+    keys.reseal(&mut second).unwrap();
+    keys.finish_rotation();
+    assert!(!keys.is_rotating());
+    for (value, expected) in [
+        (&first, &b"first"[..]),
+        (&second, b"second"),
+        (&third, b"third"),
+    ] {
+        assert_eq!(&keys.open(value).unwrap()[..], expected);
+    }
+
+    // Nothing holds the old key anymore, so it was wiped.
+    assert!(old_decryptor.upgrade().is_none());
+}
+
+#[tokio::test]
+async fn nested_envelopes_move_to_the_new_key() {
+    let (encryptor, decryptor) = shared_actors(CipherSuite::ChaCha20Poly1305);
+    let mut keys = StoreKeys::new(encryptor, decryptor);
+    let inner = keys.encrypt(b"fragment").unwrap();
+    let mut value = keys.seal_nested(&inner).unwrap();
+    // Only values sealed as nested are unwrapped, whatever their plaintext looks like.
+    let mut opaque = keys.seal(&inner).unwrap();
+
+    let (encryptor, decryptor) = shared_actors(CipherSuite::Aes256Gcm);
+    keys.begin_rotation(encryptor, decryptor).unwrap();
+    assert_eq!(&keys.decrypt(&inner).unwrap()[..], b"fragment");
+    keys.reseal(&mut value).unwrap();
+    keys.reseal(&mut opaque).unwrap();
+    keys.finish_rotation();
+
+    assert_eq!(&keys.open(&opaque).unwrap()[..], &inner);
+    let inner = keys.open(&value).unwrap();
+    assert_eq!(&keys.decrypt(&inner).unwrap()[..], b"fragment");
+
+    let mut stale = value.clone();
+    stale.key_version = 0;
+    assert!(matches!(
+        keys.open(&stale),
+        Err(AesError::SecureStoreError(SecureStoreError::MissingKey))
+    ));
+}
+
+#[tokio::test]
+async fn stores_re_encrypt_in_batches() {
+    let (encryptor, decryptor) = shared_actors(CipherSuite::Aes256Gcm);
+    let old_decryptor = Arc::downgrade(&decryptor);
+    let store = SecureKeyValueStore::new(encryptor, decryptor);
+    for i in 0..10 {
+        let value = format!("value {}", i).into_bytes();
+        store
+            .lock()
+            .unwrap()
+            .set(format!("key {}", i), value)
+            .unwrap();
+    }
+
+    let (encryptor, decryptor) = shared_actors(CipherSuite::XChaCha20Poly1305);
+    let rotation = SecureKeyValueStore::rotate_key(&store, encryptor, decryptor, 3).unwrap();
+    let progress = rotation.wait().await.unwrap();
+    assert_eq!(
+        progress,
+        RotationProgress {
+            total: 10,
+            re_encrypted: 10,
+            finished: true,
+        }
+    );
+
+    let store = store.lock().unwrap();
+    for i in 0..10 {
+        let value = store.get(&format!("key {}", i)).unwrap().unwrap();
+        assert_eq!(&value[..], format!("value {}", i).as_bytes());
+    }
+    assert!(old_decryptor.upgrade().is_none());
+}
+
+#[tokio::test]
+async fn only_one_rotation_task_runs_at_a_time() {
+    let (encryptor, decryptor) = shared_actors(CipherSuite::Aes256Gcm);
+    let store = SecureKeyValueStore::new(encryptor, decryptor);
+    for i in 0..3 {
+        store
+            .lock()
+            .unwrap()
+            .set(format!("key {}", i), b"value".to_vec())
+            .unwrap();
+    }
+
+    let (encryptor, decryptor) = shared_actors(CipherSuite::ChaCha20Poly1305);
+    let rotation = SecureKeyValueStore::rotate_key(&store, encryptor, decryptor, 1).unwrap();
+    // The task has not run yet on this single-threaded runtime.
+    assert!(matches!(
+        SecureKeyValueStore::resume_rotation(&store, 1),
+        Err(SecureStoreError::RotationInProgress)
+    ));
+    assert!(rotation.wait().await.unwrap().finished);
+    assert!(matches!(
+        SecureKeyValueStore::resume_rotation(&store, 1),
+        Err(SecureStoreError::NoRotation)
+    ));
+}
+
+#[tokio::test]
+async fn stores_keep_re_encrypting_while_they_grow() {
+    let (encryptor, decryptor) = shared_actors(CipherSuite::Aes256Gcm);
+    let old_decryptor = Arc::downgrade(&decryptor);
+    let store = SecureKeyValueStore::new(encryptor, decryptor);
+    for i in 0..5 {
+        let value = format!("old {}", i).into_bytes();
+        store
+            .lock()
+            .unwrap()
+            .set(format!("old {}", i), value)
+            .unwrap();
+    }
+
+    let (encryptor, decryptor) = shared_actors(CipherSuite::ChaCha20Poly1305);
+    let rotation = SecureKeyValueStore::rotate_key(&store, encryptor, decryptor, 1).unwrap();
+    let mut progress = rotation.subscribe();
+    progress.changed().await.unwrap();
+    assert_eq!(rotation.progress().re_encrypted, 1);
+
+    // Growing the table moves entries, including ones the rotation has not reached.
+    for i in 0..100 {
+        let value = format!("new {}", i).into_bytes();
+        store
+            .lock()
+            .unwrap()
+            .set(format!("new {}", i), value)
+            .unwrap();
+    }
+    let progress = rotation.wait().await.unwrap();
+    assert!(progress.finished);
+    assert_eq!(progress.re_encrypted, 5);
+
+    let store = store.lock().unwrap();
+    for i in 0..5 {
+        let value = store.get(&format!("old {}", i)).unwrap().unwrap();
+        assert_eq!(&value[..], format!("old {}", i).as_bytes());
+    }
+    assert!(old_decryptor.upgrade().is_none());
+}
+
+#[tokio::test]
+async fn providers_rotate_their_key() {
+    let mut provider = SecureMemoryProvider::new();
+    provider
+        .set(
+            "fragment".to_string(),
+            vec![Input::Buffer(b"data".to_vec())],
+        )
+        .unwrap();
+    provider
+        .push("fragment".to_string(), Input::Bit(1))
+        .unwrap();
+
+    let rotation = provider
+        .rotate_key(generate_key(CipherSuite::from_config()), 1)
+        .unwrap();
+    assert!(rotation.wait().await.unwrap().finished);
+
+    assert!(matches!(
+        provider.pop("fragment").unwrap(),
+        Some(Input::Bit(1))
+    ));
+    let inputs = provider.get("fragment").unwrap().unwrap();
+    assert!(matches!(inputs.as_slice(), [Input::Buffer(data)] if data == b"data"));
+}
+
+#[tokio::test]
+async fn encrypted_fragments_survive_a_rotation() {
+    let mut provider = SecureMemoryProvider::new();
+    provider
+        .set("fragment".to_string(), vec![Input::Bit(1)])
+        .unwrap();
+    provider.encrypt_fragment("fragment".to_string()).unwrap();
+
+    let rotation = provider
+        .rotate_key(generate_key(CipherSuite::from_config()), 1)
+        .unwrap();
+    assert!(rotation.wait().await.unwrap().finished);
+
+    let plaintext = provider
+        .decrypt_fragment("fragment".to_string())
+        .unwrap()
+        .unwrap();
+    assert!(!plaintext.is_empty());
+}
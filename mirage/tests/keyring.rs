@@ -0,0 +1,152 @@
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use mirage::actors::encryption::decryptor::Decryptor;
+use mirage::actors::encryption::encryptor::Encryptor;
+use mirage::actors::encryption::envelope::EnvelopeHeader;
+use mirage::actors::encryption::keyring::error::KeyringError;
+use mirage::actors::encryption::keyring::{KeyStatus, Keyring};
+use mirage::actors::encryption::suite::CipherSuite;
+use mirage::actors::encryption::AesError;
+use mirage::tools::config::EncryptionLevel;
+
+use common::{fast_kdf, runtime_handle, scratch_dir};
+
+const PASSPHRASE: &[u8] = b"keyring passphrase";
+
+#[tokio::test]
+async fn keys_survive_reopening() {
+    let dir = scratch_dir();
+    let path = dir.path().join("keyring");
+    let keyring = Keyring::create_with_kdf(&path, PASSPHRASE, fast_kdf())
+        .await
+        .unwrap();
+    let fragments = keyring
+        .generate(
+            "fragments",
+            EncryptionLevel::Level2,
+            Some(Duration::from_secs(86_400)),
+        )
+        .await
+        .unwrap();
+    assert_eq!(fragments.suite, CipherSuite::Aes256Gcm);
+    assert_eq!(fragments.status, KeyStatus::Active);
+
+    let encryptor = Encryptor::from_keyring(&keyring, "fragments", runtime_handle()).unwrap();
+    let envelope = encryptor.encrypt(b"named key").unwrap();
+    let (header, _) = EnvelopeHeader::parse(&envelope).unwrap();
+    assert_eq!(header.key_id, fragments.id.as_bytes());
+
+    let on_disk = std::fs::read(&path).unwrap();
+    assert!(!on_disk.windows(9).any(|window| window == b"fragments"));
+
+    let reopened = Keyring::open(&path, PASSPHRASE).await.unwrap();
+    assert_eq!(reopened.keys(), vec![fragments]);
+    let decryptor = Decryptor::with_keyring(reopened, runtime_handle());
+    assert_eq!(decryptor.decrypt(&envelope).unwrap(), b"named key");
+
+    assert!(matches!(
+        Keyring::open(&path, b"wrong").await,
+        Err(AesError::KeyringError(KeyringError::UnlockFailed))
+    ));
+    assert!(matches!(
+        Keyring::create_with_kdf(&path, PASSPHRASE, fast_kdf()).await,
+        Err(AesError::KeyringError(KeyringError::AlreadyExists(_)))
+    ));
+}
+
+#[tokio::test]
+async fn rotation_keeps_old_envelopes_readable() {
+    let dir = scratch_dir();
+    let keyring = Keyring::create_with_kdf(dir.path().join("keyring"), PASSPHRASE, fast_kdf())
+        .await
+        .unwrap();
+    let first = keyring
+        .generate_with_suite("files", CipherSuite::XChaCha20Poly1305, None)
+        .await
+        .unwrap();
+    assert_eq!(first.level, EncryptionLevel::Level2);
+    assert!(matches!(
+        keyring
+            .generate("files", EncryptionLevel::Level1, None)
+            .await,
+        Err(AesError::KeyringError(KeyringError::NameTaken(_)))
+    ));
+
+    let old_envelope = Encryptor::from_keyring(&keyring, "files", runtime_handle())
+        .unwrap()
+        .encrypt(b"before rotation")
+        .unwrap();
+    let decryptor = Decryptor::with_keyring(keyring.clone(), runtime_handle());
+
+    let second = keyring.rotate("files").await.unwrap();
+    assert_ne!(second.id, first.id);
+    assert_eq!(second.suite, first.suite);
+    assert_eq!(keyring.active("files").unwrap(), second);
+    assert_eq!(
+        keyring.metadata(&first.id).unwrap().status,
+        KeyStatus::DecryptOnly
+    );
+
+    let new_envelope = Encryptor::from_keyring(&keyring, "files", runtime_handle())
+        .unwrap()
+        .encrypt(b"after rotation")
+        .unwrap();
+    assert_eq!(
+        decryptor.decrypt(&old_envelope).unwrap(),
+        b"before rotation"
+    );
+    assert_eq!(decryptor.decrypt(&new_envelope).unwrap(), b"after rotation");
+
+    keyring.destroy(&first.id).await.unwrap();
+    assert!(matches!(
+        decryptor.decrypt(&old_envelope),
+        Err(AesError::KeyringError(KeyringError::KeyDestroyed(_)))
+    ));
+    assert_eq!(decryptor.decrypt(&new_envelope).unwrap(), b"after rotation");
+
+    keyring.retire(&second.id).await.unwrap();
+    assert!(matches!(
+        Encryptor::from_keyring(&keyring, "files", runtime_handle()),
+        Err(AesError::KeyringError(KeyringError::NoActiveKey(_)))
+    ));
+    assert_eq!(decryptor.decrypt(&new_envelope).unwrap(), b"after rotation");
+
+    let stranger = Encryptor::with_suite(CipherSuite::Aes256Gcm, vec![7u8; 32], runtime_handle())
+        .with_key_id(b"not-in-the-keyring".to_vec());
+    assert!(matches!(
+        decryptor.decrypt(&stranger.encrypt(b"data").unwrap()),
+        Err(AesError::KeyringError(KeyringError::UnknownKey(_)))
+    ));
+}
+
+#[tokio::test]
+async fn reports_keys_due_for_rotation() {
+    let dir = scratch_dir();
+    let keyring = Keyring::create_with_kdf(dir.path().join("keyring"), PASSPHRASE, fast_kdf())
+        .await
+        .unwrap();
+    let weekly = keyring
+        .generate(
+            "weekly",
+            EncryptionLevel::Level1,
+            Some(Duration::from_secs(7 * 86_400)),
+        )
+        .await
+        .unwrap();
+    keyring
+        .generate("forever", EncryptionLevel::Level1, None)
+        .await
+        .unwrap();
+
+    let now = SystemTime::now();
+    assert!(keyring.due_for_rotation(now).is_empty());
+    let next_week = now + Duration::from_secs(8 * 86_400);
+    assert_eq!(keyring.due_for_rotation(next_week), vec![weekly]);
+
+    // The rotated version is no longer active; its successor starts a new period.
+    let rotated = keyring.rotate("weekly").await.unwrap();
+    assert!(keyring.due_for_rotation(now).is_empty());
+    assert_eq!(keyring.due_for_rotation(next_week), vec![rotated]);
+}
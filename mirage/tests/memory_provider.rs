@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+use mirage::actors::encryption::AesError;
+use mirage::actors::memory::secure_memory_provider::error::SecureMemoryProviderError;
+use mirage::actors::memory::secure_memory_provider::{
+    SecureMemoryProvider, TYPED_ENCODING_VERSION,
+};
+use mirage::actors::memory::{Input, INPUT_ENCODING_VERSION};
+
+#[derive(Serialize, Deserialize, Zeroize, Debug, PartialEq)]
+struct Credentials {
+    user: String,
+    password: String,
+    scopes: Vec<String>,
+}
+
+fn credentials() -> Credentials {
+    Credentials {
+        user: "alice".to_string(),
+        password: "hunter2".to_string(),
+        scopes: vec!["read".to_string(), "write".to_string()],
+    }
+}
+
+#[tokio::test]
+async fn typed_values_roundtrip() {
+    let mut provider = SecureMemoryProvider::new();
+    provider
+        .set_typed("db".to_string(), &credentials())
+        .unwrap();
+    provider
+        .set_typed(
+            "tokens".to_string(),
+            &vec![("github", "ghp_1"), ("npm", "npm_2")],
+        )
+        .unwrap();
+
+    let stored = provider.get_typed::<Credentials>("db").unwrap().unwrap();
+    assert_eq!(*stored, credentials());
+    let tokens = provider
+        .get_typed::<Vec<(String, String)>>("tokens")
+        .unwrap()
+        .unwrap();
+    assert_eq!(tokens[1], ("npm".to_string(), "npm_2".to_string()));
+    assert!(provider
+        .get_typed::<Credentials>("missing")
+        .unwrap()
+        .is_none());
+    assert!(matches!(
+        provider.get_typed::<Credentials>("tokens"),
+        Err(AesError::DeserializeError(_))
+    ));
+}
+
+#[tokio::test]
+async fn typed_values_use_the_versioned_encoding() {
+    let mut provider = SecureMemoryProvider::new();
+    provider
+        .set_typed("db".to_string(), &credentials())
+        .unwrap();
+    let version = provider.read("db", |plaintext| plaintext[0]).unwrap();
+    assert_eq!(version, Some(TYPED_ENCODING_VERSION));
+
+    let header = provider.get_with("db", |value: &Credentials| value.scopes.len());
+    assert_eq!(header.unwrap(), Some(2));
+}
+
+#[tokio::test]
+async fn get_with_lends_the_value() {
+    let mut provider = SecureMemoryProvider::new();
+    provider
+        .set_typed("db".to_string(), &credentials())
+        .unwrap();
+
+    let header = provider
+        .get_with("db", |credentials: &Credentials| {
+            format!("{}:{}", credentials.user, credentials.password.len())
+        })
+        .unwrap();
+    assert_eq!(header.as_deref(), Some("alice:7"));
+    assert!(provider
+        .get_with("missing", |_: &Credentials| unreachable!())
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn read_lends_the_plaintext() {
+    let mut provider = SecureMemoryProvider::new();
+    provider
+        .set("key".to_string(), vec![Input::Buffer(b"material".to_vec())])
+        .unwrap();
+
+    let inputs = provider
+        .read("key", |plaintext| Input::deserialize(plaintext).unwrap())
+        .unwrap()
+        .unwrap();
+    assert!(matches!(inputs.as_slice(), [Input::Buffer(data)] if data == b"material"));
+    assert!(provider
+        .read("missing", |_| unreachable!())
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn plaintext_guards_expire() {
+    let mut provider = SecureMemoryProvider::new();
+    provider
+        .set("db".to_string(), vec![Input::Buffer(b"alice".to_vec())])
+        .unwrap();
+
+    let guard = provider.plaintext("db").unwrap().unwrap();
+    assert!(guard.expires_at().is_none());
+    for _ in 0..2 {
+        let inputs = guard
+            .read(|plaintext| Input::deserialize(plaintext).unwrap())
+            .unwrap();
+        assert!(matches!(inputs.as_slice(), [Input::Buffer(data)] if data == b"alice"));
+    }
+    drop(guard);
+
+    provider.set_plaintext_timeout(Some(Duration::ZERO));
+    let guard = provider.plaintext("db").unwrap().unwrap();
+    assert!(guard.is_expired());
+    assert!(matches!(
+        guard.read(|_| unreachable!()),
+        Err(AesError::SecureMemoryProviderError(
+            SecureMemoryProviderError::PlaintextExpired
+        ))
+    ));
+    assert!(guard.is_wiped());
+    assert!(provider.plaintext("missing").unwrap().is_none());
+}
+
+#[tokio::test]
+async fn plaintext_guards_wipe_without_being_read() {
+    let mut provider = SecureMemoryProvider::new();
+    provider
+        .set("db".to_string(), vec![Input::Buffer(b"alice".to_vec())])
+        .unwrap();
+    provider.set_plaintext_timeout(Some(Duration::from_millis(20)));
+
+    let guard = provider.plaintext("db").unwrap().unwrap();
+    assert!(!guard.is_wiped());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(guard.is_wiped());
+    assert!(matches!(
+        guard.read(|_| unreachable!()),
+        Err(AesError::SecureMemoryProviderError(
+            SecureMemoryProviderError::PlaintextExpired
+        ))
+    ));
+}
+
+#[test]
+fn inputs_encode_compactly() {
+    let inputs = vec![Input::Buffer(vec![0xa5; 32]), Input::Bit(1)];
+    let encoded = Input::serialize(&inputs).unwrap();
+    assert_eq!(encoded.len(), 1 + (1 + 4 + 32) + 2);
+    assert_eq!(encoded[0], INPUT_ENCODING_VERSION);
+
+    let decoded = Input::deserialize(&encoded).unwrap();
+    assert!(matches!(
+        decoded.as_slice(),
+        [Input::Buffer(data), Input::Bit(1)] if data == &vec![0xa5; 32]
+    ));
+    assert!(Input::deserialize(&[INPUT_ENCODING_VERSION])
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn binary_inputs_are_smaller_than_json() {
+    for inputs in [
+        vec![Input::Buffer(vec![0xa5; 32])],
+        vec![
+            Input::Buffer(vec![0x5a; 16]),
+            Input::Bit(1),
+            Input::Buffer(vec![0x3c; 64]),
+        ],
+        vec![Input::Buffer(vec![0xff; 4096])],
+    ] {
+        let binary = Input::serialize(&inputs).unwrap();
+        let json = serde_json::to_vec(&inputs).unwrap();
+        assert!(binary.len() < json.len());
+    }
+}
+
+#[test]
+fn legacy_json_inputs_still_decode() {
+    let legacy = br#"[{"Buffer":[1,2,3]},{"Bit":0}]"#;
+    let decoded = Input::deserialize(legacy).unwrap();
+    assert!(matches!(
+        decoded.as_slice(),
+        [Input::Buffer(data), Input::Bit(0)] if data == &[1, 2, 3]
+    ));
+
+    for corrupt in [
+        &[][..],
+        &[9],
+        &[INPUT_ENCODING_VERSION, 0, 8, 0, 0, 0, 1],
+        &[1, 7],
+    ] {
+        assert!(matches!(
+            Input::deserialize(corrupt),
+            Err(AesError::DeserializeError(_))
+        ));
+    }
+}
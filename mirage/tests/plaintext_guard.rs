@@ -0,0 +1,34 @@
+//! Kept apart from tests/memory_provider.rs: `locked_bytes` is process-wide, so nothing
+//! else may map secure memory while these tests run.
+
+use std::time::Duration;
+
+use mirage::actors::memory::secure_memory_provider::SecureMemoryProvider;
+use mirage::actors::memory::Input;
+use mirage::utils::secure_region::{locked_bytes, ProtectionLevel};
+
+#[test]
+fn dropping_a_guard_wipes_it_on_a_blocked_runtime() {
+    // The runtime is entered but never driven, so the guard's timer task cannot run.
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    let _runtime = runtime.enter();
+
+    let mut provider = SecureMemoryProvider::new();
+    provider
+        .set("db".to_string(), vec![Input::Buffer(b"alice".to_vec())])
+        .unwrap();
+    provider.set_plaintext_timeout(Some(Duration::from_secs(60)));
+
+    let guard = provider.plaintext("db").unwrap().unwrap();
+    assert!(!guard.is_wiped());
+    let locked = locked_bytes();
+    drop(guard);
+
+    // The region is zeroed right before its pages are unlocked and unmapped.
+    if provider.protection_level() >= ProtectionLevel::Locked {
+        assert!(locked_bytes() < locked);
+    }
+}
@@ -0,0 +1,119 @@
+//! The RSA keys under `tests/keys` are throwaway 2048-bit keys generated for these tests.
+
+mod common;
+
+use mirage::actors::encryption::decryptor::Decryptor;
+use mirage::actors::encryption::encryptor::Encryptor;
+use mirage::actors::encryption::envelope::{EnvelopeHeader, FLAG_RECIPIENTS};
+use mirage::actors::encryption::keys::error::KeyError;
+use mirage::actors::encryption::recipients::error::RecipientError;
+use mirage::actors::encryption::recipients::{Recipient, RecipientKey};
+use mirage::actors::encryption::suite::CipherSuite;
+use mirage::actors::encryption::AesError;
+
+use common::runtime_handle;
+
+const ALICE: &[u8] = include_bytes!("keys/alice.pem");
+const ALICE_PUBLIC: &[u8] = include_bytes!("keys/alice.pub.pem");
+const BOB: &[u8] = include_bytes!("keys/bob.pem");
+const BOB_PUBLIC: &[u8] = include_bytes!("keys/bob.pub.pem");
+/// PKCS#1 `RSA PRIVATE KEY` rather than PKCS#8.
+const CAROL: &[u8] = include_bytes!("keys/carol.pem");
+const CAROL_PUBLIC: &[u8] = include_bytes!("keys/carol.pub.pem");
+
+fn decryptor(private_pem: &[u8]) -> Decryptor {
+    Decryptor::with_recipient_key(
+        RecipientKey::from_pem(private_pem).unwrap(),
+        runtime_handle(),
+    )
+}
+
+#[tokio::test]
+async fn every_recipient_can_decrypt() {
+    let recipients = [
+        Recipient::from_pem(ALICE_PUBLIC).unwrap(),
+        Recipient::from_pem(BOB_PUBLIC).unwrap(),
+    ];
+    let encryptor =
+        Encryptor::for_recipients(CipherSuite::Aes256Gcm, &recipients, runtime_handle()).unwrap();
+    let envelope = encryptor.encrypt(b"shared with the team").unwrap();
+
+    let (header, _) = EnvelopeHeader::parse(&envelope).unwrap();
+    assert_eq!(header.flags & FLAG_RECIPIENTS, FLAG_RECIPIENTS);
+    assert_eq!(header.recipients.len(), 2);
+    assert_eq!(
+        &header.recipients[0].fingerprint,
+        recipients[0].fingerprint()
+    );
+
+    for private_pem in [ALICE, BOB] {
+        assert_eq!(
+            decryptor(private_pem).decrypt(&envelope).unwrap(),
+            b"shared with the team"
+        );
+    }
+
+    // The streaming path reads the recipients block straight from the reader.
+    let mut streamed = Vec::new();
+    decryptor(BOB)
+        .decrypt_stream(&mut envelope.as_slice(), &mut streamed, &[])
+        .await
+        .unwrap();
+    assert_eq!(streamed, b"shared with the team");
+
+    assert!(matches!(
+        decryptor(CAROL).decrypt(&envelope),
+        Err(AesError::RecipientError(RecipientError::NotARecipient))
+    ));
+}
+
+#[tokio::test]
+async fn pkcs1_private_keys_are_accepted() {
+    let recipients = [Recipient::from_pem(CAROL_PUBLIC).unwrap()];
+    let encryptor =
+        Encryptor::for_recipients(CipherSuite::ChaCha20Poly1305, &recipients, runtime_handle())
+            .unwrap();
+    let envelope = encryptor.encrypt(b"for carol").unwrap();
+
+    assert_eq!(decryptor(CAROL).decrypt(&envelope).unwrap(), b"for carol");
+}
+
+#[tokio::test]
+async fn wrapped_keys_are_authenticated() {
+    let recipients = [
+        Recipient::from_pem(ALICE_PUBLIC).unwrap(),
+        Recipient::from_pem(BOB_PUBLIC).unwrap(),
+    ];
+    let encryptor =
+        Encryptor::for_recipients(CipherSuite::Aes256Gcm, &recipients, runtime_handle()).unwrap();
+    let mut envelope = encryptor.encrypt(b"data").unwrap();
+
+    // Alice's entry is untouched, but Bob's wrapped key is part of the header and
+    // therefore bound to every chunk.
+    let (_, length) = EnvelopeHeader::parse(&envelope).unwrap();
+    envelope[length - 1] ^= 1;
+    assert!(matches!(
+        decryptor(ALICE).decrypt(&envelope),
+        Err(AesError::AesGcmError(_))
+    ));
+}
+
+#[test]
+fn invalid_keys_are_rejected() {
+    assert!(matches!(
+        Recipient::from_pem(ALICE),
+        Err(AesError::KeyError(KeyError::UnsupportedKeyType(_)))
+    ));
+    assert!(matches!(
+        Recipient::from_pem(b"not a pem"),
+        Err(AesError::KeyError(KeyError::InvalidPem(_)))
+    ));
+}
+
+#[tokio::test]
+async fn recipients_are_required() {
+    assert!(matches!(
+        Encryptor::for_recipients(CipherSuite::Aes256Gcm, &[], runtime_handle()),
+        Err(AesError::RecipientError(RecipientError::NoRecipients))
+    ));
+}
@@ -0,0 +1,36 @@
+use mirage::actors::encryption::suite::CipherSuite;
+use mirage::actors::memory::Input;
+use mirage::utils::key_generator::generate_key;
+use mirage::utils::secret::{Secret, SecretBytes, SecretKey};
+use zeroize::Zeroize;
+
+#[test]
+fn secrets_never_print_their_contents() {
+    let key = SecretKey::new(vec![0xab; 32]);
+    assert_eq!(format!("{:?}", key), "SecretKey([REDACTED; 32 bytes])");
+
+    let plaintext = SecretBytes::from_slice(b"hunter2");
+    let inputs = Secret::new(vec![Input::Buffer(b"hunter2".to_vec())]);
+    for printed in [format!("{:?}", plaintext), format!("{:?}", inputs)] {
+        assert_eq!(printed, "Secret([REDACTED])");
+    }
+}
+
+#[test]
+fn generated_keys_fit_their_suite() {
+    for suite in [CipherSuite::Aes128Gcm, CipherSuite::XChaCha20Poly1305] {
+        let key = generate_key(suite);
+        assert_eq!(key.len(), suite.key_length());
+        assert_ne!(key, generate_key(suite));
+        assert_eq!(key, key.clone());
+    }
+}
+
+#[test]
+fn inputs_are_wiped() {
+    let mut inputs = [Input::Buffer(b"secret".to_vec()), Input::Bit(1)];
+    inputs[0].zeroize();
+    inputs[1].zeroize();
+    assert!(matches!(&inputs[0], Input::Buffer(data) if data.is_empty()));
+    assert!(matches!(inputs[1], Input::Bit(0)));
+}
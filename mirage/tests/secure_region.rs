@@ -0,0 +1,75 @@
+use mirage::utils::secure_region::{
+    locked_bytes, memlock_limit, secret_memory_available, Backend, Protection, ProtectionLevel,
+    SecureRegion,
+};
+
+#[test]
+fn regions_hold_their_bytes() {
+    let mut region = SecureRegion::new(5000).unwrap();
+    assert_eq!(region.len(), 5000);
+    assert!(region.iter().all(|byte| *byte == 0));
+
+    region[..6].copy_from_slice(b"secret");
+    region[4999] = 0xff;
+    let copy = region.clone();
+    assert_eq!(copy, region);
+    assert_eq!(&copy[..6], b"secret");
+    assert_eq!(copy[4999], 0xff);
+
+    let empty = SecureRegion::from_slice(&[]).unwrap();
+    assert!(empty.is_empty());
+    assert_ne!(empty, region);
+}
+
+#[test]
+fn locked_regions_count_against_the_limit() {
+    let region = SecureRegion::from_slice(b"value").unwrap();
+    if region.is_locked() {
+        assert!(locked_bytes() > 0);
+        assert!(memlock_limit().is_none_or(|limit| locked_bytes() <= limit));
+    }
+    assert_eq!(
+        format!("{:?}", region),
+        format!(
+            "SecureRegion {{ len: 5, level: {:?}, protection: ReadWrite, .. }}",
+            region.protection_level()
+        )
+    );
+}
+
+#[test]
+fn secret_memory_falls_back_to_locked_pages() {
+    let region = SecureRegion::from_slice_in(b"secret", Backend::SecretMemory).unwrap();
+    assert_eq!(&region[..], b"secret");
+    assert_eq!(region.backend(), Backend::SecretMemory);
+    if secret_memory_available() {
+        assert_eq!(region.protection_level(), ProtectionLevel::SecretMemory);
+    } else {
+        assert!(region.protection_level() <= ProtectionLevel::Locked);
+    }
+    assert!(region.is_locked() || region.protection_level() == ProtectionLevel::Unlocked);
+
+    let copy = region.clone();
+    assert_eq!(copy.backend(), Backend::SecretMemory);
+    assert_eq!(copy, region);
+}
+
+#[test]
+fn hidden_regions_are_only_readable_inside_read() {
+    let region = SecureRegion::from_slice(b"secret").unwrap();
+    region.set_protection(Protection::NoAccess).unwrap();
+
+    let copy = region.read(<[u8]>::to_vec).unwrap();
+    assert_eq!(copy, b"secret");
+    assert_eq!(region.protection(), Protection::NoAccess);
+    assert_eq!(region.clone().protection(), Protection::NoAccess);
+    assert_eq!(region, SecureRegion::from_slice(b"secret").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "not readable")]
+fn hidden_regions_cannot_be_dereferenced() {
+    let region = SecureRegion::from_slice(b"secret").unwrap();
+    region.set_protection(Protection::NoAccess).unwrap();
+    let _ = region[0];
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use proptest::prelude::*;
+
+use mirage::actors::encryption::decryptor::Decryptor;
+use mirage::actors::encryption::encryptor::Encryptor;
+use mirage::actors::encryption::suite::CipherSuite;
+use mirage::actors::memory::generic::secure_hasher::SecureHasher;
+use mirage::actors::memory::generic::secure_key_value_store::blind_index::{NameIndex, NameKey};
+use mirage::actors::memory::generic::secure_key_value_store::error::SecureStoreError;
+use mirage::actors::memory::generic::secure_key_value_store::SecureKeyValueStore;
+use mirage::utils::key_generator::generate_key;
+use mirage::utils::secure_region::{self, Backend, ProtectionLevel};
+
+fn runtime() -> &'static tokio::runtime::Runtime {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| tokio::runtime::Runtime::new().unwrap())
+}
+
+fn store() -> Arc<Mutex<SecureKeyValueStore>> {
+    let runtime_handle = Arc::new(runtime().handle().clone());
+    let suite = CipherSuite::Aes128Gcm;
+    let key = generate_key(suite);
+    SecureKeyValueStore::new(
+        Arc::new(Encryptor::with_suite(
+            suite,
+            key.clone(),
+            runtime_handle.clone(),
+        )),
+        Arc::new(Decryptor::with_suite(suite, key, runtime_handle)),
+    )
+}
+
+fn keeping_names(name_key: &NameKey) -> NameIndex {
+    NameIndex::keeping_names(name_key, Arc::new(runtime().handle().clone()))
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+    Set(String, Vec<u8>),
+    Remove(String),
+}
+
+/// Few distinct names, so sequences overwrite, remove and re-insert the same entries.
+fn name() -> impl Strategy<Value = String> {
+    prop_oneof![
+        "[a-d]{0,2}".prop_map(String::from),
+        "users/[a-z]{1,8}".prop_map(String::from),
+    ]
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        3 => (name(), prop::collection::vec(any::<u8>(), 0..16))
+            .prop_map(|(name, value)| Op::Set(name, value)),
+        1 => name().prop_map(Op::Remove),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn behaves_like_a_hash_map(ops in prop::collection::vec(op(), 0..200)) {
+        let store = store();
+        let mut store = store.lock().unwrap();
+        store.set_name_index(keeping_names(&NameKey::generate())).unwrap();
+        let mut model = HashMap::new();
+
+        for op in ops {
+            match op {
+                Op::Set(name, value) => {
+                    store.set(name.clone(), value.clone()).unwrap();
+                    model.insert(name, value);
+                }
+                Op::Remove(name) => {
+                    prop_assert_eq!(store.remove(&name), model.remove(&name).is_some());
+                }
+            }
+            prop_assert_eq!(store.len(), model.len());
+        }
+
+        for (name, value) in &model {
+            let stored = store.get(name).unwrap();
+            prop_assert_eq!(stored.as_deref(), Some(value.as_slice()));
+        }
+        let names = store.names().unwrap();
+        let mut names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+        let mut expected: Vec<&str> = model.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        expected.sort_unstable();
+        prop_assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn misses_never_return_another_entry(
+        names in prop::collection::hash_set(name(), 1..64),
+        missing in name(),
+    ) {
+        let store = store();
+        let mut store = store.lock().unwrap();
+        for name in &names {
+            store.set(name.clone(), name.as_bytes().to_vec()).unwrap();
+        }
+
+        for name in &names {
+            let stored = store.get(name).unwrap().unwrap();
+            prop_assert_eq!(&stored[..], name.as_bytes());
+        }
+        prop_assert_eq!(store.contains_key(&missing), names.contains(&missing));
+        if !names.contains(&missing) {
+            prop_assert!(store.get(&missing).unwrap().is_none());
+        }
+    }
+}
+
+#[test]
+fn empty_store_lookups_miss() {
+    let store = store();
+    let mut store = store.lock().unwrap();
+    assert!(store.is_empty());
+    assert!(store.get("anything").unwrap().is_none());
+    assert!(!store.remove("anything"));
+}
+
+#[test]
+fn lookups_survive_many_removals() {
+    let store = store();
+    let mut store = store.lock().unwrap();
+    for round in 0..1_000 {
+        let name = format!("entry-{}", round);
+        store.set(name.clone(), vec![1]).unwrap();
+        assert!(store.remove(&name));
+    }
+    store.set("kept".to_string(), b"value".to_vec()).unwrap();
+    assert_eq!(store.len(), 1);
+    assert_eq!(&store.get("kept").unwrap().unwrap()[..], b"value");
+}
+
+#[test]
+fn hashes_depend_on_the_store_key() {
+    // Reference vectors from the SipHash paper: key 00..0f over the messages "" and 00..0e.
+    let key: [u8; 16] = std::array::from_fn(|i| i as u8);
+    let hasher = SecureHasher::with_key(key);
+    assert_eq!(hasher.build_hasher().finish(), 0x726fdb47dd0e0e31);
+    let mut digest = hasher.build_hasher();
+    digest.write(&key[..15]);
+    assert_eq!(digest.finish(), 0xa129ca6149be45e5);
+
+    let (first, second) = (SecureHasher::new(), SecureHasher::new());
+    assert_eq!(
+        first.hash_one("users/alice"),
+        first.clone().hash_one("users/alice")
+    );
+    assert_ne!(
+        first.hash_one("users/alice"),
+        second.hash_one("users/alice")
+    );
+    assert_eq!(format!("{:?}", first), "SecureHasher { .. }");
+}
+
+#[test]
+fn names_are_only_listed_when_kept() {
+    let store = store();
+    let mut store = store.lock().unwrap();
+    store
+        .set("alice@example.com".to_string(), b"token".to_vec())
+        .unwrap();
+    assert!(matches!(store.names(), Err(SecureStoreError::NamesNotKept)));
+    assert!(matches!(
+        store.set_name_index(keeping_names(&NameKey::generate())),
+        Err(SecureStoreError::StoreNotEmpty)
+    ));
+    assert_eq!(
+        &store.get("alice@example.com").unwrap().unwrap()[..],
+        b"token"
+    );
+}
+
+#[test]
+fn blind_indexes_follow_the_name_key() {
+    let name_key = NameKey::generate();
+    let (first, second) = (NameIndex::new(&name_key), keeping_names(&name_key));
+    let other = NameIndex::new(&NameKey::generate());
+    assert_eq!(first.index("alice"), second.index("alice"));
+    assert_ne!(first.index("alice"), first.index("bob"));
+    assert_ne!(first.index("alice"), other.index("alice"));
+    assert_eq!(
+        first.namespace_index("alice"),
+        second.namespace_index("alice")
+    );
+    // Namespaces are indexed in a separate domain from entries.
+    assert_ne!(first.namespace_index("alice"), first.index("alice"));
+
+    // A sealed name only opens for the index it was sealed with.
+    assert!(first
+        .seal_name(&first.index("alice"), "alice")
+        .unwrap()
+        .is_none());
+    let sealed = second
+        .seal_name(&second.index("alice"), "alice")
+        .unwrap()
+        .unwrap();
+    let name = second.open_name(&second.index("alice"), &sealed).unwrap();
+    assert_eq!(name.as_str(), "alice");
+    assert!(second.open_name(&second.index("bob"), &sealed).is_err());
+}
+
+#[test]
+fn stores_report_their_protection_level() {
+    let store = store();
+    let mut store = store.lock().unwrap();
+    assert_eq!(store.backend(), Backend::Locked);
+    store.set_backend(Backend::SecretMemory);
+    let expected = secure_region::protection_level(Backend::SecretMemory);
+    assert_eq!(store.protection_level(), expected);
+
+    store.set("key".to_string(), b"material".to_vec()).unwrap();
+    let value = store.get("key").unwrap().unwrap();
+    assert_eq!(&value[..], b"material");
+    assert_eq!(value.backend(), Backend::SecretMemory);
+    if secure_region::secret_memory_available() {
+        assert_eq!(store.protection_level(), ProtectionLevel::SecretMemory);
+    } else {
+        assert!(store.protection_level() <= ProtectionLevel::Locked);
+    }
+}
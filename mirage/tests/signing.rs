@@ -0,0 +1,187 @@
+//! `keys/dave.pem` is a throwaway Ed25519 key. The `signing/message.*.bin` signatures were
+//! made with OpenSSL over `signing/message.txt`: RSA-PSS (SHA-256, 32-byte salt) with
+//! `keys/alice.pem` and Ed25519ph with `keys/dave.pem`.
+
+mod common;
+
+use mirage::actors::encryption::decryptor::Decryptor;
+use mirage::actors::encryption::encryptor::Encryptor;
+use mirage::actors::encryption::envelope::{EnvelopeHeader, FLAG_SIGNED};
+use mirage::actors::encryption::suite::CipherSuite;
+use mirage::actors::encryption::AesError;
+use mirage::actors::signing::detached::{self, DetachedSignature};
+use mirage::actors::signing::error::SignatureError;
+use mirage::actors::signing::{SignatureAlgorithm, SigningKey, VerifyingKey};
+use mirage::utils::secure_region::Backend;
+
+use common::{runtime_handle, scratch_dir};
+
+const ALICE: &[u8] = include_bytes!("keys/alice.pem");
+const ALICE_PUBLIC: &[u8] = include_bytes!("keys/alice.pub.pem");
+const DAVE: &[u8] = include_bytes!("keys/dave.pem");
+const DAVE_PUBLIC: &[u8] = include_bytes!("keys/dave.pub.pem");
+
+const MESSAGE: &[u8] = include_bytes!("signing/message.txt");
+const ALICE_SIGNATURE: &[u8] = include_bytes!("signing/message.alice.bin");
+const DAVE_SIGNATURE: &[u8] = include_bytes!("signing/message.dave.bin");
+
+fn key() -> Vec<u8> {
+    vec![7u8; 32]
+}
+
+fn is_signature_error(result: Result<Vec<u8>, AesError>) -> bool {
+    matches!(result, Err(AesError::SignatureError(_)))
+}
+
+#[test]
+fn verifies_openssl_signatures() {
+    let alice = VerifyingKey::from_pem(ALICE_PUBLIC).unwrap();
+    assert_eq!(alice.algorithm(), SignatureAlgorithm::RsaPssSha256);
+    alice.verify(MESSAGE, ALICE_SIGNATURE).unwrap();
+
+    let dave = VerifyingKey::from_pem(DAVE_PUBLIC).unwrap();
+    assert_eq!(dave.algorithm(), SignatureAlgorithm::Ed25519ph);
+    dave.verify(MESSAGE, DAVE_SIGNATURE).unwrap();
+
+    assert!(dave.verify(b"something else", DAVE_SIGNATURE).is_err());
+    assert!(alice.verify(MESSAGE, DAVE_SIGNATURE).is_err());
+}
+
+#[test]
+fn signs_and_verifies_buffers() {
+    for pem in [ALICE, DAVE] {
+        let key = SigningKey::from_pem(pem).unwrap();
+        let verifying_key = key.verifying_key();
+        let signature = key.sign(b"release-1.2.3.tar.gz").unwrap();
+        assert_eq!(signature.len(), key.signature_length());
+
+        verifying_key
+            .verify(b"release-1.2.3.tar.gz", &signature)
+            .unwrap();
+        let result = verifying_key.verify(b"release-1.2.4.tar.gz", &signature);
+        assert!(matches!(
+            result,
+            Err(AesError::SignatureError(SignatureError::VerificationFailed))
+        ));
+    }
+
+    let alice = SigningKey::from_pem(ALICE).unwrap();
+    let alice_public = VerifyingKey::from_pem(ALICE_PUBLIC).unwrap();
+    assert_eq!(alice.fingerprint(), alice_public.fingerprint());
+}
+
+#[tokio::test]
+async fn detached_signature_files() {
+    let dir = scratch_dir();
+    let path = dir.path().join("report.pdf");
+    tokio::fs::write(&path, vec![0x42; 200_000]).await.unwrap();
+
+    let dave = SigningKey::from_pem(DAVE).unwrap();
+    let signature_path = detached::sign_file(&dave, &path).await.unwrap();
+    assert_eq!(signature_path, dir.path().join("report.pdf.sig"));
+
+    let pem = tokio::fs::read(&signature_path).await.unwrap();
+    assert!(pem.starts_with(b"-----BEGIN MIRAGE SIGNATURE-----"));
+    let signature = DetachedSignature::from_pem(&pem).unwrap();
+    signature
+        .verify(&dave.verifying_key(), &vec![0x42; 200_000])
+        .unwrap();
+
+    detached::verify_file(&dave.verifying_key(), &path)
+        .await
+        .unwrap();
+
+    let other = SigningKey::generate(SignatureAlgorithm::Ed25519ph).unwrap();
+    let result = detached::verify_file(&other.verifying_key(), &path).await;
+    assert!(matches!(
+        result,
+        Err(AesError::SignatureError(SignatureError::KeyMismatch))
+    ));
+
+    tokio::fs::write(&path, vec![0x43; 200_000]).await.unwrap();
+    let result = detached::verify_file(&dave.verifying_key(), &path).await;
+    assert!(matches!(
+        result,
+        Err(AesError::SignatureError(SignatureError::VerificationFailed))
+    ));
+}
+
+#[tokio::test]
+async fn signed_envelopes_are_verified_on_decrypt() {
+    for pem in [ALICE, DAVE] {
+        let signer = SigningKey::from_pem(pem).unwrap();
+        let verifier = signer.verifying_key();
+        let encryptor = Encryptor::with_suite(CipherSuite::Aes256Gcm, key(), runtime_handle())
+            .with_signer(signer);
+        let envelope = encryptor.encrypt(b"signed then sealed").unwrap();
+
+        let (header, _) = EnvelopeHeader::parse(&envelope).unwrap();
+        assert_eq!(header.flags & FLAG_SIGNED, FLAG_SIGNED);
+        assert_eq!(header.signer.unwrap().fingerprint, *verifier.fingerprint());
+
+        let decryptor = Decryptor::with_suite(CipherSuite::Aes256Gcm, key(), runtime_handle())
+            .with_verifier(verifier);
+        assert_eq!(decryptor.decrypt(&envelope).unwrap(), b"signed then sealed");
+        let region = decryptor
+            .decrypt_to_region(&envelope, b"", Backend::Locked)
+            .unwrap();
+        assert_eq!(&region[..], b"signed then sealed");
+    }
+}
+
+#[tokio::test]
+async fn signature_policy_is_enforced() {
+    let dave = SigningKey::from_pem(DAVE).unwrap();
+    let stranger = SigningKey::generate(SignatureAlgorithm::Ed25519ph).unwrap();
+
+    let plain = Encryptor::with_suite(CipherSuite::Aes256Gcm, key(), runtime_handle());
+    let signed = plain.clone().with_signer(stranger);
+    let unsigned_envelope = plain.encrypt(b"data").unwrap();
+    let signed_envelope = signed.encrypt(b"data").unwrap();
+
+    let verifying = Decryptor::with_suite(CipherSuite::Aes256Gcm, key(), runtime_handle())
+        .with_verifier(dave.verifying_key());
+    assert!(matches!(
+        verifying.decrypt(&unsigned_envelope),
+        Err(AesError::SignatureError(SignatureError::Unsigned))
+    ));
+    assert!(matches!(
+        verifying.decrypt(&signed_envelope),
+        Err(AesError::SignatureError(SignatureError::UnknownSigner))
+    ));
+
+    let trusting = Decryptor::with_suite(CipherSuite::Aes256Gcm, key(), runtime_handle());
+    assert!(is_signature_error(trusting.decrypt(&signed_envelope)));
+    assert_eq!(trusting.decrypt(&unsigned_envelope).unwrap(), b"data");
+}
+
+#[tokio::test]
+async fn signed_streams_across_chunks() {
+    let dir = scratch_dir();
+    let source = dir.path().join("large.bin");
+    let encrypted = dir.path().join("large.bin.enc");
+    let decrypted = dir.path().join("large.bin.out");
+    let data: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+    tokio::fs::write(&source, &data).await.unwrap();
+
+    let signer = SigningKey::from_pem(ALICE).unwrap();
+    let verifier = signer.verifying_key();
+    let encryptor = Encryptor::with_suite(CipherSuite::ChaCha20Poly1305, key(), runtime_handle())
+        .with_signer(signer);
+    encryptor
+        .encrypt_file(&source, &encrypted, b"")
+        .await
+        .unwrap();
+
+    // Streaming and buffered encryption produce the same layout.
+    let envelope = tokio::fs::read(&encrypted).await.unwrap();
+    let decryptor = Decryptor::with_suite(CipherSuite::ChaCha20Poly1305, key(), runtime_handle())
+        .with_verifier(verifier);
+    assert_eq!(decryptor.decrypt(&envelope).unwrap(), data);
+
+    decryptor
+        .decrypt_file(&encrypted, &decrypted, b"")
+        .await
+        .unwrap();
+    assert_eq!(tokio::fs::read(&decrypted).await.unwrap(), data);
+}
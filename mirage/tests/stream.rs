@@ -0,0 +1,108 @@
+use aes_gcm::aead::KeyInit;
+use aes_gcm::Aes256Gcm;
+use chacha20poly1305::XChaCha20Poly1305;
+
+use mirage::actors::encryption::stream::{nonce_prefix_length, Stream};
+use mirage::actors::encryption::{AesError, TAG_LENGTH};
+
+const CHUNK_SIZE: usize = 16;
+const PREFIX: [u8; 7] = [7; 7];
+
+fn cipher() -> Aes256Gcm {
+    Aes256Gcm::new_from_slice(&[3u8; 32]).unwrap()
+}
+
+/// Splits a sealed body into its chunks.
+fn chunks(body: &[u8]) -> Vec<&[u8]> {
+    body.chunks(CHUNK_SIZE + TAG_LENGTH).collect()
+}
+
+#[test]
+fn parallel_and_serial_sealing_agree() {
+    let stream = Stream::new(&PREFIX, b"header", CHUNK_SIZE);
+    let data: Vec<u8> = (0..=200).collect();
+
+    let parallel = stream.seal(&cipher(), &data, true).unwrap();
+    let serial = stream.seal(&cipher(), &data, false).unwrap();
+    assert_eq!(parallel, serial);
+    assert_eq!(chunks(&serial).len(), data.len().div_ceil(CHUNK_SIZE));
+
+    assert_eq!(
+        stream.open(&cipher(), &serial, true).unwrap().expose(),
+        &data
+    );
+    assert_eq!(
+        stream.open(&cipher(), &serial, false).unwrap().expose(),
+        &data
+    );
+}
+
+#[test]
+fn empty_messages_are_one_final_chunk() {
+    let stream = Stream::new(&PREFIX, b"", CHUNK_SIZE);
+    let body = stream.seal(&cipher(), b"", false).unwrap();
+    assert_eq!(body.len(), TAG_LENGTH);
+    assert!(stream.open(&cipher(), &body, false).unwrap().is_empty());
+}
+
+#[test]
+fn rearranged_chunks_fail_to_open() {
+    let stream = Stream::new(&PREFIX, b"header", CHUNK_SIZE);
+    let data = [42u8; 4 * CHUNK_SIZE];
+    let body = stream.seal(&cipher(), &data, false).unwrap();
+    let sealed = chunks(&body);
+    assert_eq!(sealed.len(), 4);
+
+    let swapped = [sealed[1], sealed[0], sealed[2], sealed[3]].concat();
+    let dropped = [sealed[0], sealed[2], sealed[3]].concat();
+    let duplicated = [sealed[0], sealed[0], sealed[1], sealed[2], sealed[3]].concat();
+    let without_last = sealed[..3].concat();
+    let truncated = &body[..body.len() - 1];
+    for body in [
+        &swapped[..],
+        &dropped,
+        &duplicated,
+        &without_last,
+        truncated,
+    ] {
+        assert!(stream.open(&cipher(), body, false).is_err());
+        assert!(stream.open(&cipher(), body, true).is_err());
+    }
+
+    let other_aad = Stream::new(&PREFIX, b"other", CHUNK_SIZE);
+    assert!(other_aad.open(&cipher(), &body, false).is_err());
+    assert!(matches!(
+        stream.open(&cipher(), &body[..TAG_LENGTH - 1], false),
+        Err(AesError::CiphertextTooShort(15))
+    ));
+}
+
+#[test]
+fn stray_tags_after_the_last_chunk_fail_to_open() {
+    let stream = Stream::new(&PREFIX, b"header", CHUNK_SIZE);
+    let data = [42u8; 2 * CHUNK_SIZE];
+    let mut body = stream.seal(&cipher(), &data, false).unwrap();
+    assert_eq!(stream.plaintext_length(&body).unwrap(), data.len());
+
+    body.extend_from_slice(&[0; TAG_LENGTH]);
+    assert_eq!(stream.plaintext_length(&body).unwrap(), data.len());
+    assert!(matches!(
+        stream.open(&cipher(), &body, false),
+        Err(AesError::AesGcmError(_))
+    ));
+}
+
+#[test]
+fn prefixes_must_fit_the_cipher_nonce() {
+    assert_eq!(nonce_prefix_length::<Aes256Gcm>(), 7);
+    assert_eq!(nonce_prefix_length::<XChaCha20Poly1305>(), 19);
+
+    let stream = Stream::new(&[0u8; 8], b"", CHUNK_SIZE);
+    assert!(matches!(
+        stream.seal(&cipher(), b"data", false),
+        Err(AesError::InvalidNonceLength {
+            expected: 7,
+            actual: 8
+        })
+    ));
+}
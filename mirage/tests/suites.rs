@@ -0,0 +1,97 @@
+mod common;
+
+use mirage::actors::encryption::stream::Stream;
+use mirage::actors::encryption::suite::CipherSuite;
+use mirage::actors::encryption::AesError;
+
+use common::actors;
+
+const SUITES: [CipherSuite; 5] = [
+    CipherSuite::Aes128Gcm,
+    CipherSuite::Aes256Gcm,
+    CipherSuite::ChaCha20Poly1305,
+    CipherSuite::XChaCha20Poly1305,
+    CipherSuite::Aes256GcmSiv,
+];
+
+/// Offset of the suite id in the envelope header, after the magic and version.
+const SUITE_ID_OFFSET: usize = 5;
+
+#[test]
+fn suite_ids_are_stable() {
+    for (id, suite) in (1..).zip(SUITES) {
+        assert_eq!(suite.id(), id);
+        assert_eq!(CipherSuite::from_id(id).unwrap(), suite);
+    }
+    for id in [0, 6, u8::MAX] {
+        assert!(matches!(
+            CipherSuite::from_id(id),
+            Err(AesError::UnsupportedCipherSuite(unknown)) if unknown == id
+        ));
+    }
+}
+
+#[tokio::test]
+async fn every_suite_round_trips() {
+    for suite in SUITES {
+        let (encryptor, decryptor) = actors(suite);
+        for data in [&b""[..], b"x", &[5u8; 100_000]] {
+            let ciphertext = encryptor.encrypt(data).unwrap();
+            assert_eq!(ciphertext[SUITE_ID_OFFSET], suite.id());
+            assert_eq!(decryptor.decrypt(&ciphertext).unwrap(), data, "{:?}", suite);
+        }
+    }
+}
+
+#[tokio::test]
+async fn every_suite_rejects_tampering() {
+    for suite in SUITES {
+        let (encryptor, decryptor) = actors(suite);
+        let ciphertext = encryptor.encrypt(b"attack at dawn").unwrap();
+
+        for offset in [
+            SUITE_ID_OFFSET + 2,
+            ciphertext.len() - 20,
+            ciphertext.len() - 1,
+        ] {
+            let mut tampered = ciphertext.clone();
+            tampered[offset] ^= 1;
+            assert!(
+                decryptor.decrypt(&tampered).is_err(),
+                "{:?} at {}",
+                suite,
+                offset
+            );
+        }
+        for other in SUITES.into_iter().filter(|other| *other != suite) {
+            let mut relabelled = ciphertext.clone();
+            relabelled[SUITE_ID_OFFSET] = other.id();
+            assert!(
+                decryptor.decrypt(&relabelled).is_err(),
+                "{:?} as {:?}",
+                suite,
+                other
+            );
+        }
+    }
+}
+
+#[test]
+fn suites_do_not_open_each_others_ciphertext() {
+    let key = [9u8; 32];
+    let prefix = [4u8; 19];
+
+    for sealer in SUITES {
+        let stream = Stream::new(&prefix[..sealer.nonce_prefix_length()], b"", 1024);
+        let body = sealer
+            .seal(&key[..sealer.key_length()], &stream, b"secret", false)
+            .unwrap();
+
+        for opener in SUITES.into_iter().filter(|opener| *opener != sealer) {
+            // Give every opener a key and prefix it accepts, so only the algorithm differs.
+            let stream = Stream::new(&prefix[..opener.nonce_prefix_length()], b"", 1024);
+            let opened = opener.open(&key[..opener.key_length()], &stream, &body, false);
+            assert!(opened.is_err(), "{:?} opened {:?}", opener, sealer);
+        }
+    }
+}